@@ -1,99 +1,315 @@
 use actix::{Actor, Addr, System};
 use futures::join;
 use lib::{
-    coordinator::{Coordinator, NewOrder, NewStore},
-    ecom::{ecom_connection_listener, ecom_network},
+    admin_api::{serve_http, EcomRoute, HttpRequest, HttpResponse},
+    catalog::Catalog,
+    clock::{real_clock, SharedClock},
+    config::EcomConfig,
+    coordinator::{
+        CoordElection, Coordinator, DisconnectStore, EcomPendingWork, GetMembers, GetOrderStatus,
+        GetSalesReport, GetState, GetStoresStock, IntakeOrder, NewOrder, SetIntakePaused,
+        ShowState, ShowStoresStock, ShutDown,
+    },
+    ecom::{discover_stores, ecom_connection_listener, ecom_network, join_cluster},
+    ecom_console::{EcomCommand, USAGE},
     errors::Errors,
+    logging::init_logging,
+    metrics::{serve_metrics, Metrics},
+    random::{node_seed, stream_rng, NodeRng},
+    sales_report::SalesReport,
+    shutdown::{shutdown_signal, write_pending_lines, ShutdownSummary},
+    trace::TraceId,
 };
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use std::{
     collections::HashMap,
     env::args,
     fs::File,
     io::{BufRead, BufReader},
-    str::FromStr,
 };
+use tokio::io::AsyncBufReadExt;
 use tokio::time::{sleep, Duration};
-use tokio::{io::Interest, net::TcpListener};
+use tracing::{debug, error, info, warn};
 
-const ARGS_ORDER_FILE: usize = 1;
-const ARGS_MY_IP: usize = 2;
-const ARGS_MY_ID: usize = 3;
-const ARGS_ECOM_PORT: usize = 8;
-const ARGS_STORES_PORT: usize = 9;
+const REPORTS_DIR: &str = "reports";
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(200);
 
 /// This main starts the system where every async function and actors will co-exist.
-/// But before all that, it parses de arguments from the terminal. With this arguments
-/// main knows the ecoms ips, the ports tu use, orders and stock files, and the process id.
+/// But before all that, it loads the configuration, given as a TOML file with `--config` and
+/// overridden setting by setting with `--<setting> <value>`. With it main knows the ecoms addresses,
+/// the addresses to listen on, orders and catalog files, and the process id.
+/// On SIGINT or SIGTERM the ecommerce shuts down in order, and the exit status tells whether it left work pending.
 fn main() -> Result<(), Errors> {
-    let args: Vec<String> = args().collect(); // Args Order: orders_file, my_ip, my_id, ecommerce_ip1, ecom1_id, ecommerce_ip2, ecom2_id, ecommerces_port, stores_port
-    let address_stores = args[ARGS_MY_IP].to_string() + ":" + &args[ARGS_STORES_PORT];
+    let args: Vec<String> = args().collect();
+    let config = EcomConfig::from_args(&args)?;
+    init_logging(&config.log_level, config.log_format)?;
+    let seed = node_seed(config.seed);
+    info!(seed, "Semilla del ecommerce");
 
-    let orders = load_online_orders(args[ARGS_ORDER_FILE].clone())?;
-    let rng = thread_rng();
-    let my_id =
-        <usize as FromStr>::from_str(&args[ARGS_MY_ID]).map_err(|_| Errors::CouldNotParse)?;
+    let orders = load_online_orders(config.orders_file.clone())?;
+    let catalog = Catalog::load(&config.catalog_file)?;
+    let rng = stream_rng(seed, "coordinator");
+    let my_id = config.id;
+    let metrics = Metrics::new();
     let coord = Coordinator {
         online_orders: orders.clone(),
         active_stores: HashMap::new(),
         active_ecoms: HashMap::new(),
         rng,
+        traces: stream_rng(seed, "trace"),
         id: my_id,
         curr_leader: Some(my_id),
+        members: config.members(),
+        reconnect: config.tuning.reconnect_policy().seeded(seed),
+        detached_stores: HashMap::new(),
+        backorders: HashMap::new(),
+        backorder_max_wait: Duration::from_secs(config.tuning.backorder_max_wait_secs),
+        catalog,
+        sales: SalesReport::new(),
+        intake_paused: false,
+        metrics: metrics.clone(),
+        election_started: None,
+        clock: real_clock(),
     };
 
     let system = System::new();
 
-    let mut ecoms: Vec<(String, String)> = vec![];
-    for i in (4..8).step_by(2) {
-        let addr = format!("{}:{}", args[i].clone(), args[ARGS_ECOM_PORT]);
-        let id = args[i + 1].clone();
-        ecoms.push((addr, id));
-    }
+    let ecoms: Vec<(String, String)> = config
+        .peers
+        .iter()
+        .map(|peer| (peer.address.clone(), peer.id.to_string()))
+        .collect();
 
-    system.block_on(async {
+    let exit_code = system.block_on(async {
         let coord_addr = coord.start();
 
-        let my_addr = args[ARGS_MY_IP].to_string() + ":" + &args[ARGS_ECOM_PORT];
-        let ecom_network_fut = ecom_network(ecoms, coord_addr.clone(), my_id.to_string());
-        let ecom_conn_istener_fut = ecom_connection_listener(my_addr, coord_addr.clone());
-
-        let discover_stores_fut = discover_stores(address_stores, coord_addr.clone());
-        let order_manager_fut = order_manager(coord_addr.clone(), orders);
+        let ecom_network_fut = ecom_network(
+            ecoms,
+            coord_addr.clone(),
+            my_id.to_string(),
+            config.tuning.reconnect_policy().seeded(seed),
+        );
+        let ecom_conn_istener_fut =
+            ecom_connection_listener(config.ecoms_address.clone(), coord_addr.clone());
 
-        let (_, _, _, _) = join!(
-            discover_stores_fut,
-            ecom_network_fut,
-            order_manager_fut,
-            ecom_conn_istener_fut
+        let discover_stores_fut =
+            discover_stores(config.stores_address.clone(), coord_addr.clone(), my_id);
+        let join_cluster_fut = async {
+            if config.seeds.is_empty() {
+                return Ok(());
+            }
+            join_cluster(
+                config.seeds.clone(),
+                coord_addr.clone(),
+                my_id,
+                config.ecoms_address.clone(),
+                config.stores_address.clone(),
+                config.tuning.reconnect_policy().seeded(seed).forever(),
+            )
+            .await
+        };
+        let order_manager_fut = order_manager(
+            coord_addr.clone(),
+            orders,
+            config.tuning.min_order_delay_secs,
+            config.tuning.max_order_delay_secs,
+            stream_rng(seed, "order_manager"),
+            real_clock(),
         );
+        let admin_console_fut = admin_console(coord_addr.clone());
+        let admin_api_fut = admin_api(config.admin_address(), coord_addr.clone());
+        let metrics_fut = serve_metrics(config.metrics_address(), metrics);
+
+        let tasks_fut = async {
+            join!(
+                discover_stores_fut,
+                ecom_network_fut,
+                order_manager_fut,
+                ecom_conn_istener_fut,
+                join_cluster_fut,
+                admin_console_fut,
+                admin_api_fut,
+                metrics_fut
+            )
+        };
+
+        // The actors keep working after every task is done, until the ecommerce is asked to stop
+        let signal = tokio::select! {
+            _ = tasks_fut => shutdown_signal().await,
+            signal = shutdown_signal() => signal,
+        };
+        info!(signal, "Cerrando el ecommerce");
+        let summary = shut_down(&coord_addr, my_id).await;
+        dump_sales_report(&coord_addr, my_id).await;
+        summary.exit_code()
     });
 
-    system.run().map_err(|_| Errors::SystemRunFail)?;
+    std::process::exit(exit_code)
+}
+
+/// Shuts the ecommerce down in order. It takes no more orders, hands the leadership over if it has it, and closes
+/// the connections with the other ecommerces. The orders still waiting for stock are written to the reports directory.
+async fn shut_down(coord: &Addr<Coordinator>, id: usize) -> ShutdownSummary {
+    let pending = match coord.send(ShutDown).await {
+        Ok(Ok(pending)) => pending,
+        _ => {
+            error!("No se pudo obtener el trabajo pendiente del ecommerce");
+            EcomPendingWork::default()
+        }
+    };
+    // The connections are closed by the actors, which need a moment to write CLOSE
+    sleep(SHUTDOWN_GRACE).await;
 
+    let name = format!("ecom_{id}_pending");
+    if !pending.backorders.is_empty() {
+        match write_pending_lines(REPORTS_DIR, &name, &pending.backorders) {
+            Ok(_) => info!(
+                path = %format!("{REPORTS_DIR}/{name}"),
+                "Pedidos pendientes guardados"
+            ),
+            Err(e) => error!(error = ?e, "No se pudieron guardar los pedidos pendientes"),
+        }
+    }
+
+    let mut summary = ShutdownSummary::new();
+    summary.add("pedidos esperando stock", pending.backorders.len());
+    summary.add("stores desconectadas", pending.detached_stores);
+    for line in summary.lines() {
+        warn!("Pendiente al cerrar: {line}");
+    }
+    summary
+}
+
+/// Asks the Coordinator actor for its sales report and writes it as CSV and JSON in the reports directory.
+async fn dump_sales_report(coord: &Addr<Coordinator>, id: usize) {
+    let name = format!("ecom_{id}_sales");
+    match coord.send(GetSalesReport).await {
+        Ok(Ok(report)) => match report.write_files(REPORTS_DIR, &name) {
+            Ok(_) => info!(
+                path = %format!("{REPORTS_DIR}/{name}"),
+                "Reporte de ventas guardado"
+            ),
+            Err(e) => error!(error = ?e, "No se pudo guardar el reporte de ventas"),
+        },
+        _ => error!("No se pudo obtener el reporte de ventas"),
+    }
+}
+
+/// This async function reads the commands of the admin console from the terminal and sends the matching message to the Coordinator actor.
+async fn admin_console(coord: Addr<Coordinator>) -> Result<(), Errors> {
+    let mut input = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = input.next_line().await {
+        let command = match EcomCommand::parse(&line) {
+            Ok(command) => command,
+            Err(_) => {
+                warn!(line, "Comando invalido");
+                println!("Los comandos son:");
+                for usage in USAGE {
+                    println!("    {usage}");
+                }
+                continue;
+            }
+        };
+        let result = match command {
+            EcomCommand::ShowState => coord.send(ShowState).await,
+            EcomCommand::ShowStock => coord.send(ShowStoresStock).await,
+            EcomCommand::Order { product, quantity } => {
+                coord
+                    .send(NewOrder {
+                        order: format!("{product},{quantity}"),
+                        visited_stores: vec![],
+                        trace_id: None,
+                        origin: None,
+                    })
+                    .await
+            }
+            EcomCommand::Election => coord.send(CoordElection { visited: vec![] }).await,
+            EcomCommand::Pause => coord.send(SetIntakePaused { paused: true }).await,
+            EcomCommand::Resume => coord.send(SetIntakePaused { paused: false }).await,
+            EcomCommand::DisconnectStore { store_id } => {
+                coord.send(DisconnectStore { store_id }).await
+            }
+        };
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => warn!(line, error = ?e, "No se pudo ejecutar el comando"),
+            Err(_) => error!(line, "No se pudo enviar el comando al coordinador"),
+        }
+    }
     Ok(())
 }
 
-/// This async function gets all the orders from a vec, and sends those orders to the Coordinator actor
-/// in random intervals.
-async fn order_manager(addr: Addr<Coordinator>, orders: Vec<String>) -> Result<(), Errors> {
-    let mut rng_sleep = thread_rng();
+/// Serves the admin API in `address`, if it is set. Each request is answered with the same messages as the admin console.
+async fn admin_api(address: Option<String>, coord: Addr<Coordinator>) -> Result<(), Errors> {
+    let address = match address {
+        Some(address) => address,
+        None => return Ok(()),
+    };
+    let result = serve_http(address, move |request| {
+        answer_api_request(request, coord.clone())
+    })
+    .await;
+    if let Err(e) = &result {
+        error!(error = ?e, "No se pudo levantar la API de administracion");
+    }
+    result
+}
+
+async fn answer_api_request(request: HttpRequest, coord: Addr<Coordinator>) -> HttpResponse {
+    let route = match EcomRoute::parse(&request) {
+        Ok(route) => route,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+    let result = match route {
+        EcomRoute::Leader => return HttpResponse::from_query(coord.send(GetState).await),
+        EcomRoute::Members => return HttpResponse::from_query(coord.send(GetMembers).await),
+        EcomRoute::Stock => return HttpResponse::from_query(coord.send(GetStoresStock).await),
+        EcomRoute::Orders => return HttpResponse::from_query(coord.send(GetOrderStatus).await),
+        EcomRoute::Order { product, quantity } => {
+            coord
+                .send(NewOrder {
+                    order: format!("{product},{quantity}"),
+                    visited_stores: vec![],
+                    trace_id: None,
+                    origin: None,
+                })
+                .await
+        }
+        EcomRoute::Election => coord.send(CoordElection { visited: vec![] }).await,
+        EcomRoute::Pause => coord.send(SetIntakePaused { paused: true }).await,
+        EcomRoute::Resume => coord.send(SetIntakePaused { paused: false }).await,
+        EcomRoute::DisconnectStore { store_id } => coord.send(DisconnectStore { store_id }).await,
+    };
+    HttpResponse::from_command(result)
+}
 
+/// This async function gets all the orders from a vec, and sends those orders to the Coordinator actor
+/// in random intervals of between `min_delay_secs` and `max_delay_secs`, drawn from `rng` as the traces of the orders.
+/// The intervals are waited on `clock`. While the intake is paused the order waits.
+async fn order_manager(
+    addr: Addr<Coordinator>,
+    orders: Vec<String>,
+    min_delay_secs: u64,
+    max_delay_secs: u64,
+    mut rng: NodeRng,
+    clock: SharedClock,
+) -> Result<(), Errors> {
     let mut i = 0;
+    // An order keeps its trace while it is retried
+    let mut trace_id = TraceId::from_rng(&mut rng);
     while i < orders.len() {
-        let secs = rng_sleep.gen_range(2, 6);
+        let secs = rng.gen_range(min_delay_secs, max_delay_secs);
         let dur = Duration::from_secs(secs);
-        sleep(dur).await;
+        clock.sleep(dur).await;
         let order = orders[i].to_string();
-        println!("[ORDER_MANAGER] Orden procesando...");
-        if let Ok(Ok(_)) = addr
-            .send(NewOrder {
-                order,
-                visited_stores: vec![],
-            })
-            .await
-        {
-            i += 1;
+        debug!(order, %trace_id, "Pedido online recibido");
+        match addr.send(IntakeOrder { order, trace_id }).await {
+            // Orders of products that are not in the catalog will never be accepted, so they are skipped
+            Ok(Ok(_)) | Ok(Err(Errors::UnknownProductError)) => {
+                i += 1;
+                trace_id = TraceId::from_rng(&mut rng);
+            }
+            _ => {}
         }
     }
     Ok(())
@@ -110,41 +326,3 @@ fn load_online_orders(orders_file: String) -> Result<Vec<String>, Errors> {
     }
     Ok(online_orders)
 }
-
-/// This async function is responsible for discovering and connecting with new stores.
-/// With each connection the coordinator is told to create a new AbstractStore actor instance.
-async fn discover_stores(addr: String, coord: Addr<Coordinator>) -> Result<(), Errors> {
-    let listener = TcpListener::bind(addr)
-        .await
-        .map_err(|_| Errors::ConnectionError)?;
-    while let Ok(tuple) = listener.accept().await {
-        let (stream, _) = tuple;
-        let mut buf = [0; 1];
-        let timeout_duration = Duration::from_secs(10);
-        match tokio::time::timeout(timeout_duration, stream.ready(Interest::READABLE)).await {
-            Ok(_) => {
-                match stream.try_read(&mut buf) {
-                    Ok(_) => {
-                        // The coordinator is told to create a new AbstractStore
-                        println!(
-                            "[DISC_STORES] El id recibido es [{:?}]",
-                            String::from_utf8(buf.to_vec())
-                        );
-                        let store_id =
-                            String::from_utf8(buf.to_vec()).map_err(|_| Errors::CouldNotParse)?;
-                        let res = coord.try_send(NewStore { store_id, stream });
-                        match res {
-                            Ok(_) => {},
-                            Err(_) => println!("[DISC_STORES] No se pudo mandar el mensaje para crear una nueva store"),
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("[DISC_STORES] Error a la hora de leer: {:?}", e)
-                    }
-                }
-            }
-            Err(_) => eprintln!("[DISC_STORES] Tiempo de lectura agotado."),
-        }
-    }
-    Ok(())
-}
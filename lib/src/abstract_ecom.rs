@@ -4,30 +4,44 @@ use std::str::FromStr;
 
 use actix::dev::ContextFutureSpawner;
 use actix::{fut::wrap_future, Actor, Addr, Context, Handler, Message, StreamHandler};
-use actix::{ActorContext, ActorFutureExt};
+use actix::{ActorContext, ActorFutureExt, AsyncContext};
 use tokio::io::{AsyncWriteExt, WriteHalf};
-use tokio::net::TcpStream;
+use tokio::sync::oneshot;
 
-use crate::coordinator::{ChangeLeader, CoordElection, EcomDisconnected};
-use crate::ecom::election_from_vec;
+use crate::coordinator::{
+    ChangeLeader, CoordElection, EcomConnectionClosed, EcomDisconnected, MemberAnnounced, Members,
+    OrderExpired,
+};
+use crate::ecom::{election_from_vec, members_msg, BYE_MSG, CLOSE_MSG};
+use crate::logging::{connection_span, order_span};
+use crate::trace::{split_trace, with_trace, TraceId};
+use crate::transport::Connection;
 use crate::{
     coordinator::{Coordinator, NewOrder},
     ecom::vec_from_election_msg,
     errors::Errors,
 };
+use tracing::{debug, info, warn};
 
 /// The actor that manages the connection between different ecommerces.
+/// If the connection was dialed by this ecommerce, `closed` tells the dialing task when the other side closes it.
+/// `closing` is set when the other side closes the connection on purpose because it kept another one,
+/// and `left` when it closes it because it is shutting down.
 pub struct AbstractEcom {
     pub id: usize,
-    pub write: Option<WriteHalf<TcpStream>>,
+    pub write: Option<WriteHalf<Connection>>,
     pub coord: Addr<Coordinator>,
+    pub closed: Option<oneshot::Sender<()>>,
+    pub closing: bool,
+    pub left: bool,
 }
 
 impl Actor for AbstractEcom {
     type Context = Context<Self>;
 
     fn started(&mut self, _: &mut Self::Context) {
-        println!("¡AbstractEcom is alive!");
+        let _span = connection_span("ecom", &self.id.to_string()).entered();
+        debug!("Conexion con el ecommerce iniciada");
     }
 }
 
@@ -35,16 +49,33 @@ impl Actor for AbstractEcom {
 /// It handles the different supported messages between ecommerces.
 impl StreamHandler<Result<String, std::io::Error>> for AbstractEcom {
     fn handle(&mut self, read: Result<String, std::io::Error>, _: &mut Self::Context) {
+        let _span = connection_span("ecom", &self.id.to_string()).entered();
         if let Ok(line) = read {
-            let split: Vec<&str> = line.split(',').collect();
+            debug!(line, "Mensaje recibido");
+            let (fields, trace_id) = split_trace(&line);
+            let split: Vec<&str> = fields.split(',').collect();
             match split[0] {
                 "ORDER" => {
                     let order = format!("{},{}", split[1], split[2]);
+                    // Orders from ecommerces that do not send traces start a new one in the coordinator
+                    let _span = trace_id.map(|trace_id| order_span(trace_id, &order).entered());
+                    // Without an origin, the order was taken by the ecommerce that sent it
+                    let origin = split
+                        .get(3)
+                        .and_then(|origin| <usize as FromStr>::from_str(origin).ok())
+                        .unwrap_or(self.id);
+                    debug!(origin, "Pedido redirigido por otro ecommerce");
                     let _ = self.coord.try_send(NewOrder {
                         order,
                         visited_stores: vec![],
+                        trace_id,
+                        origin: Some(origin),
                     });
                 }
+                "EXPIRED" if split.len() == 3 => {
+                    let order = format!("{},{}", split[1], split[2]);
+                    let _ = self.coord.try_send(OrderExpired { order, trace_id });
+                }
                 "LEADER" => {
                     let id =
                         <usize as FromStr>::from_str(split[1]).map_err(|_| Errors::CouldNotParse);
@@ -54,40 +85,155 @@ impl StreamHandler<Result<String, std::io::Error>> for AbstractEcom {
                 }
                 "ELECTION" => {
                     let visited = vec_from_election_msg(split[1].to_owned());
-                    let _ = self.coord.try_send(CoordElection {
-                        visited,
-                        ecom_id: self.id + 1,
-                    });
+                    let _ = self.coord.try_send(CoordElection { visited });
                 }
-                _ => {
-                    println!(
-                        "[ECOM_TASK] Se recibio un mensaje desconocido desde un ecommerce: [{}]",
-                        line
-                    )
+                "CLOSE" => {
+                    self.closing = true;
                 }
+                "BYE" => {
+                    self.left = true;
+                }
+                "MEMBER" if split.len() == 4 => {
+                    let id =
+                        <usize as FromStr>::from_str(split[1]).map_err(|_| Errors::CouldNotParse);
+                    if let Ok(id) = id {
+                        let _ = self.coord.try_send(MemberAnnounced {
+                            id,
+                            ecoms_address: split[2].to_string(),
+                            stores_address: split[3].to_string(),
+                        });
+                    }
+                }
+                _ => warn!(line, "Se recibio un mensaje desconocido desde un ecommerce"),
             }
         }
     }
 
     fn finished(&mut self, ctx: &mut Self::Context) {
-        println!("[ABSTRACT_ECOM_{}] ECOM {} DISCONNECTED", self.id, self.id);
-        let _ = self.coord.try_send(EcomDisconnected { ecom_id: self.id });
+        let _span = connection_span("ecom", &self.id.to_string()).entered();
+        if self.closing {
+            info!("Se cerro una conexion repetida con el ecommerce");
+            let _ = self.coord.try_send(EcomConnectionClosed {
+                ecom_id: self.id,
+                addr: ctx.address(),
+            });
+            ctx.stop();
+            return;
+        }
+        if self.left {
+            // The ecommerce already handed over the leadership, so no election is held, but it is dialed until it is back
+            info!("El ecommerce se fue del cluster");
+            let _ = self.coord.try_send(EcomConnectionClosed {
+                ecom_id: self.id,
+                addr: ctx.address(),
+            });
+        } else {
+            warn!("Se perdio la conexion con el ecommerce");
+            let _ = self.coord.try_send(EcomDisconnected {
+                ecom_id: self.id,
+                addr: ctx.address(),
+            });
+        }
+        if let Some(closed) = self.closed.take() {
+            let _ = closed.send(());
+        }
         ctx.stop();
     }
 }
 
-/// Sends an order to the other ecommerce.
+impl AbstractEcom {
+    /// Tells the other ecommerce why the connection is closed with `line`, and then closes it.
+    fn close_with(&mut self, line: &'static str, ctx: &mut Context<Self>) {
+        let mut write_half = self
+            .write
+            .take()
+            .expect("No debería poder llegar otro mensaje antes de que vuelva por usar ctx.wait");
+        wrap_future::<_, Self>(async move {
+            let _ = write_half.write_all(line.as_bytes()).await;
+            let _ = write_half.shutdown().await;
+        })
+        .map(|_, _, ctx: &mut Context<Self>| ctx.stop())
+        .wait(ctx);
+    }
+}
+
+/// Closes the connection because another one with the same ecommerce is kept.
+/// The other ecommerce is told with _CLOSE_ so it does not take it as a disconnection nor dials it again.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct CloseConnection;
+
+impl Handler<CloseConnection> for AbstractEcom {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: CloseConnection, ctx: &mut Self::Context) -> Result<(), Errors> {
+        self.close_with(CLOSE_MSG, ctx);
+        Ok(())
+    }
+}
+
+/// Closes the connection because this ecommerce is shutting down. The other ecommerce is told with _BYE_ so it
+/// does not hold an election, but it keeps dialing this one until it is back.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct Leave;
+
+impl Handler<Leave> for AbstractEcom {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: Leave, ctx: &mut Self::Context) -> Result<(), Errors> {
+        self.close_with(BYE_MSG, ctx);
+        Ok(())
+    }
+}
+
+/// Sends an order to the other ecommerce, along with the ecommerce that took it and its trace.
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
 pub struct SendOrder {
     pub order: String,
+    pub trace_id: TraceId,
+    pub origin: usize,
 }
 
 impl Handler<SendOrder> for AbstractEcom {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: SendOrder, ctx: &mut Self::Context) -> Result<(), Errors> {
-        let msg = format!("ORDER,{}\n", msg.order);
+        let msg = format!(
+            "{}\n",
+            with_trace(&format!("ORDER,{},{}", msg.order, msg.origin), msg.trace_id)
+        );
+        let mut write_half = self
+            .write
+            .take()
+            .expect("No debería poder llegar otro mensaje antes de que vuelva por usar ctx.wait");
+        wrap_future::<_, Self>(async move {
+            let _ = write_half.write_all(msg.as_bytes()).await;
+            write_half
+        })
+        .map(|write, this, _| this.write = Some(write))
+        .wait(ctx);
+        Ok(())
+    }
+}
+
+/// Tells the ecommerce that took an order that it expired waiting for stock, along with its trace.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct SendExpired {
+    pub order: String,
+    pub trace_id: TraceId,
+}
+
+impl Handler<SendExpired> for AbstractEcom {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: SendExpired, ctx: &mut Self::Context) -> Result<(), Errors> {
+        let msg = format!(
+            "{}\n",
+            with_trace(&format!("EXPIRED,{}", msg.order), msg.trace_id)
+        );
         let mut write_half = self
             .write
             .take()
@@ -113,7 +259,7 @@ impl Handler<Election> for AbstractEcom {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: Election, ctx: &mut Self::Context) -> Result<(), Errors> {
-        let election_msg = election_from_vec(msg.visited, self.id.to_string());
+        let election_msg = election_from_vec(msg.visited);
         let mut write_half = self
             .write
             .take()
@@ -156,3 +302,64 @@ impl Handler<NewLeader2> for AbstractEcom {
         Ok(())
     }
 }
+
+/// Answers an ecommerce that joined the cluster with its members and leader.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct SendMembers {
+    pub sender_id: usize,
+    pub members: Members,
+}
+
+impl Handler<SendMembers> for AbstractEcom {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: SendMembers, ctx: &mut Self::Context) -> Result<(), Errors> {
+        let msg = members_msg(msg.sender_id, &msg.members);
+        let mut write_half = self
+            .write
+            .take()
+            .expect("No debería poder llegar otro mensaje antes de que vuelva por usar ctx.wait");
+        wrap_future::<_, Self>(async move {
+            let _ = write_half.write_all(msg.as_bytes()).await;
+            write_half
+        })
+        .map(|write, this, _| this.write = Some(write))
+        .wait(ctx);
+
+        Ok(())
+    }
+}
+
+/// Tells the other ecommerce that a new one joined the cluster, with the addresses where it accepts
+/// ecommerces and stores.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct AnnounceEcomMember {
+    pub id: usize,
+    pub ecoms_address: String,
+    pub stores_address: String,
+}
+
+impl Handler<AnnounceEcomMember> for AbstractEcom {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: AnnounceEcomMember, ctx: &mut Self::Context) -> Result<(), Errors> {
+        let msg = format!(
+            "MEMBER,{},{},{}\n",
+            msg.id, msg.ecoms_address, msg.stores_address
+        );
+        let mut write_half = self
+            .write
+            .take()
+            .expect("No debería poder llegar otro mensaje antes de que vuelva por usar ctx.wait");
+        wrap_future::<_, Self>(async move {
+            let _ = write_half.write_all(msg.as_bytes()).await;
+            write_half
+        })
+        .map(|write, this, _| this.write = Some(write))
+        .wait(ctx);
+
+        Ok(())
+    }
+}
@@ -1,34 +1,89 @@
 extern crate actix;
 
 use crate::{
-    coordinator::{Coordinator, GetLeader, NewOrder, StoreDisconnected},
+    coordinator::{
+        Coordinator, GetLeader, NewOrder, OrderOutcome, StockReplenished, StoreDisconnected,
+    },
     errors::Errors,
+    logging::{connection_span, order_span},
+    metrics::{Counter, Metrics},
+    offline_log::orders_in,
+    outbox::{ack_msg, parse_sequenced},
+    sales_report::Channel,
+    store::{BYE_MSG, DRAIN_MSG, RESYNC_MSG, UNDRAIN_MSG},
+    trace::{split_trace, with_trace, TraceId},
+    transport::Connection,
 };
 use actix::{
     fut::wrap_future, prelude::ContextFutureSpawner, Actor, ActorContext, ActorFutureExt, Addr,
     AsyncContext, Context, Handler, Message, StreamHandler,
 };
 use std::{collections::HashMap, str::FromStr};
-use tokio::{
-    io::{AsyncWriteExt, WriteHalf},
-    net::TcpStream,
-};
+use tokio::io::{AsyncWriteExt, WriteHalf};
+use tracing::{debug, info, warn};
 
 /// AbstractStore actor. It is in charge of handling the connection with the coordinator and the actual store.
-/// It also handles the stock and the orders.
+/// It also handles the stock and the orders. It is `resumed` when its stock is the one the coordinator kept
+/// from a previous connection with the store. `last_seq` is the number of the last notification of the store applied to the stock,
+/// within the `session` of the run of the store that sent it.
+/// The `metrics` are the ones of the coordinator. The store `left` once it said _BYE_, so its disconnection was already reported,
+/// and is `draining` from _DRAIN_ until it leaves or says _UNDRAIN_, so no orders are sent to it meanwhile.
 pub struct AbstractStore {
-    pub write: Option<WriteHalf<TcpStream>>,
+    pub write: Option<WriteHalf<Connection>>,
     pub store_id: String,
     pub stock: HashMap<String, usize>,
     pub orders_buffer: Vec<UpdateStock>,
     pub coordinator: Addr<Coordinator>,
+    pub resumed: bool,
+    pub last_seq: u64,
+    pub session: u64,
+    pub metrics: Metrics,
+    pub left: bool,
+    pub draining: bool,
 }
 
 impl Actor for AbstractStore {
     type Context = Context<Self>;
 
     fn started(&mut self, _ctx: &mut Self::Context) {
-        println!("¡AbstractStore is alive with id [{}]!", self.store_id);
+        let _span = connection_span("store", &self.store_id).entered();
+        debug!("Conexion con la store iniciada");
+    }
+}
+
+impl AbstractStore {
+    /// Tells the coordinator that the store is gone, along with what is known of its stock.
+    fn report_disconnection(&mut self, ctx: &mut Context<Self>) {
+        let sent = self.coordinator.try_send(StoreDisconnected {
+            store_id: self.store_id.clone(),
+            stock: std::mem::take(&mut self.stock),
+            last_seq: self.last_seq,
+            session: self.session,
+            addr: ctx.address(),
+        });
+        self.metrics.sent(sent, "coordinator");
+    }
+
+    /// Tells the coordinator that the store approved or cancelled the orders of the notification with `fields`,
+    /// so they can be added to the sales report.
+    fn report_outcome(&self, fields: &[&str], approved: bool) {
+        let product = fields[1];
+        let channel = if approved {
+            fields.get(3).copied()
+        } else {
+            None
+        };
+        if let Ok(quantity) = <usize as FromStr>::from_str(fields[2]) {
+            let sent = self.coordinator.try_send(OrderOutcome {
+                store_id: self.store_id.clone(),
+                product: product.to_string(),
+                quantity,
+                orders: orders_in(fields),
+                channel: Channel::from_wire(channel),
+                approved,
+            });
+            self.metrics.sent(sent, "coordinator");
+        }
     }
 }
 
@@ -37,79 +92,147 @@ impl Actor for AbstractStore {
 /// or even the Coordinator.
 impl StreamHandler<Result<String, std::io::Error>> for AbstractStore {
     fn handle(&mut self, read: Result<String, std::io::Error>, ctx: &mut Self::Context) {
-        if let Ok(line) = read {
-            let split: Vec<&str> = line.split(',').collect();
+        let _span = connection_span("store", &self.store_id).entered();
+        if let Ok(mut line) = read {
+            debug!(line, "Mensaje recibido");
+            if let Some((session, seq, notification)) = parse_sequenced(&line) {
+                // A notification is acknowledged even if it was already applied, the store sends it again until it is
+                ctx.notify(SendAck { seq });
+                if session != self.session {
+                    // The store was restarted, and numbers its notifications from 1 again
+                    debug!(session, "Nueva sesion de la store");
+                    self.session = session;
+                    self.last_seq = 0;
+                }
+                if seq <= self.last_seq {
+                    return;
+                }
+                self.last_seq = seq;
+                line = notification.to_string();
+            }
+            if line == RESYNC_MSG {
+                // The store replayed what changed while it was disconnected, which is only enough with the previous stock
+                if !self.resumed {
+                    ctx.notify(RequestStock);
+                }
+                self.resumed = false;
+                return;
+            }
+            if line == DRAIN_MSG || line == UNDRAIN_MSG {
+                info!(line, "La store cambio si toma pedidos");
+                self.draining = line == DRAIN_MSG;
+                return;
+            }
+            if line == BYE_MSG {
+                // The store leaves the network, so no more orders are sent to it before its connection closes
+                info!("La store se desconecta");
+                self.left = true;
+                self.report_disconnection(ctx);
+                return;
+            }
+            let (fields, trace_id) = split_trace(&line);
+            let split: Vec<&str> = fields.split(',').collect();
             let product = split[1].to_owned();
             let quantity = split[2].to_owned();
+            // The outcome of an online order is logged within its trace
+            let _order_span = trace_id
+                .map(|trace_id| order_span(trace_id, &format!("{product},{quantity}")).entered());
+            // The stock is changed right away, since the notified messages are not handled in the order the lines arrive
             match split[0] {
                 "STOCK" => {
                     let msg = AddStock { product, quantity };
-                    ctx.notify(msg);
+                    let _ = <Self as Handler<AddStock>>::handle(self, msg, ctx);
                 }
                 "APPROVED" => {
-                    println!("[ABSTRACT_STORE] [{}], pedido [{}]", self.store_id, line);
+                    info!(line = fields, "Pedido aprobado por la store");
+                    self.report_outcome(&split, true);
                     let msg = UpdateStock { product, quantity };
-                    ctx.notify(msg);
+                    let _ = <Self as Handler<UpdateStock>>::handle(self, msg, ctx);
                 }
                 "CANCELLED" => {
-                    println!(
-                        "[ABSTRACT_STORE] Mi id es [{}], pedido [{}]",
-                        self.store_id, line
-                    );
+                    info!(line = fields, "Pedido cancelado por la store");
+                    self.report_outcome(&split, false);
+                    // The cancelled reservation frees units that waiting backorders may use
+                    if let Some(stock_quantity) = self.stock.get(&product) {
+                        let sent = self.coordinator.try_send(StockReplenished {
+                            store_id: self.store_id.clone(),
+                            product,
+                            quantity: *stock_quantity,
+                        });
+                        self.metrics.sent(sent, "coordinator");
+                    }
                 }
                 "LEADER" => {
-                    let _ = self.coordinator.try_send(GetLeader {
+                    let sent = self.coordinator.try_send(GetLeader {
                         sender_id: self.store_id.clone(),
                     });
+                    self.metrics.sent(sent, "coordinator");
                 }
-                _ => println!(
-                    "[ABSTRACT_STORE] Mi id es [{}], me llego un mensaje que no se responder",
-                    self.store_id
+                _ => warn!(
+                    line = fields,
+                    "Se recibio un mensaje desconocido desde la store"
                 ),
             }
         }
     }
 
     fn finished(&mut self, ctx: &mut Self::Context) {
-        println!("[ABSTRACT_STORE_{}] Conexion terminada.", self.store_id);
-        let _ = self.coordinator.try_send(StoreDisconnected {
-            store_id: self.store_id.clone(),
-        });
+        let _span = connection_span("store", &self.store_id).entered();
+        if self.left {
+            debug!("Se cerro la conexion con la store que se desconecto");
+        } else {
+            warn!("Se perdio la conexion con la store");
+            self.report_disconnection(ctx);
+        }
         ctx.stop()
     }
 }
 
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
-/// Message to notify the coordinator that a new order has arrived. It contains the order and the stores that have already been visited.
-/// It redirects the order to a store that has the product in stock. If no store has the product in stock, it returns an error.
+/// Message to notify the coordinator that a new order has arrived. It contains the order, the stores that have already been visited,
+/// the trace of the order and the ecommerce that took it, if it was another one. It redirects the order to a store that has the
+/// product in stock. If no store has the product in stock, it returns an error.
 pub struct Order {
     pub order: String,
     pub visited_stores: Vec<String>,
+    pub trace_id: TraceId,
+    pub origin: Option<usize>,
 }
 
 impl Handler<Order> for AbstractStore {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: Order, ctx: &mut Self::Context) -> Result<(), Errors> {
+        let _span = order_span(msg.trace_id, &msg.order).entered();
         // Checks if there is stock of the product
         let split: Vec<&str> = msg.order.split(',').collect();
         let product = split[0].to_string();
         let quantity = <usize as FromStr>::from_str(split[1]).map_err(|_| Errors::CouldNotParse)?;
 
-        if let Some(stock_quantity) = self.stock.get(&product) {
-            if stock_quantity < &quantity {
-                let mut new_vec = msg.visited_stores;
-                new_vec.push(self.store_id.clone());
-                let _ = self.coordinator.try_send(NewOrder {
-                    order: msg.order,
-                    visited_stores: new_vec,
-                });
-                return Ok(());
-            }
+        // A draining store takes no orders, as if it had no stock
+        let short_of_stock = self
+            .stock
+            .get(&product)
+            .is_some_and(|stock_quantity| *stock_quantity < quantity);
+        if self.draining || short_of_stock {
+            debug!(store_id = %self.store_id, draining = self.draining, "La store no puede tomar el pedido, se busca otra");
+            let mut new_vec = msg.visited_stores;
+            new_vec.push(self.store_id.clone());
+            let sent = self.coordinator.try_send(NewOrder {
+                order: msg.order,
+                visited_stores: new_vec,
+                trace_id: Some(msg.trace_id),
+                origin: msg.origin,
+            });
+            self.metrics.sent(sent, "coordinator");
+            return Ok(());
         }
 
-        let order = msg.order.clone();
+        debug!(store_id = %self.store_id, "Pedido enviado a la store");
+        self.metrics
+            .inc(Counter::OrdersRouted, &[("store", &self.store_id)]);
+        let order = with_trace(&msg.order, msg.trace_id);
         let mut write = self
             .write
             .take()
@@ -137,24 +260,6 @@ impl Handler<UpdateStock> for AbstractStore {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: UpdateStock, _: &mut Self::Context) -> Result<(), Errors> {
-        if !self.orders_buffer.is_empty() {
-            let mut new_orders_buffer: Vec<UpdateStock> = vec![];
-            for order in &self.orders_buffer {
-                let prod_opt = self.stock.get_mut(&order.product);
-                if let Some(prod_quantity) = prod_opt {
-                    let order_quantity = <usize as FromStr>::from_str(&order.quantity)
-                        .map_err(|_| Errors::CouldNotParse)?;
-                    if order_quantity > *prod_quantity {
-                        return Err(Errors::NoStockError);
-                    }
-                    *prod_quantity -= order_quantity;
-                } else {
-                    new_orders_buffer.push((*order).clone());
-                }
-            }
-            self.orders_buffer = new_orders_buffer;
-        }
-
         let prod_opt = self.stock.get_mut(&msg.product);
         if let Some(prod_quantity) = prod_opt {
             let order_quantity =
@@ -164,13 +269,10 @@ impl Handler<UpdateStock> for AbstractStore {
             }
             *prod_quantity -= order_quantity;
         } else {
-            // If we receive an update of a product we dont have, we put it on the buffer to check later
+            // If we receive an update of a product we dont have, we put it on the buffer until its stock arrives
             self.orders_buffer.push(msg);
         }
-        println!(
-            "[ABSTRACT_STORE] Mi id es [{}] y mi stock es [{:?}]",
-            self.store_id, self.stock
-        );
+        debug!(store_id = %self.store_id, stock = ?self.stock, "Stock actualizado");
         Ok(())
     }
 }
@@ -189,7 +291,18 @@ impl Handler<AddStock> for AbstractStore {
     fn handle(&mut self, msg: AddStock, _: &mut Self::Context) -> Result<(), Errors> {
         let quantity =
             <usize as FromStr>::from_str(&msg.quantity).map_err(|_| Errors::CouldNotParse)?;
-        self.stock.insert(msg.product, quantity);
+        self.stock.insert(msg.product.clone(), quantity);
+        // The store sends its stock after the updates it already counted, so the buffered ones of the product are dropped
+        self.orders_buffer
+            .retain(|order| order.product != msg.product);
+
+        // Backorders of the product may be waiting for this stock
+        let sent = self.coordinator.try_send(StockReplenished {
+            store_id: self.store_id.clone(),
+            product: msg.product,
+            quantity,
+        });
+        self.metrics.sent(sent, "coordinator");
         Ok(())
     }
 }
@@ -221,6 +334,144 @@ impl Handler<NewLeader> for AbstractStore {
     }
 }
 
+/// Acknowledges to the store every notification up to `seq`.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct SendAck {
+    pub seq: u64,
+}
+
+impl Handler<SendAck> for AbstractStore {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: SendAck, ctx: &mut Self::Context) -> Result<(), Errors> {
+        let mut write = self
+            .write
+            .take()
+            .expect("No debería poder llegar otro mensaje antes de que vuelva por usar ctx.wait");
+        wrap_future::<_, Self>(async move {
+            let _ = write
+                .write_all(format!("{}\n", ack_msg(msg.seq)).as_bytes())
+                .await;
+            write
+        })
+        .map(|write, this, _| this.write = Some(write))
+        .wait(ctx);
+        Ok(())
+    }
+}
+
+/// Asks the store for the stock of all its products.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct RequestStock;
+
+impl Handler<RequestStock> for AbstractStore {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: RequestStock, ctx: &mut Self::Context) -> Result<(), Errors> {
+        let mut write = self
+            .write
+            .take()
+            .expect("No debería poder llegar otro mensaje antes de que vuelva por usar ctx.wait");
+        wrap_future::<_, Self>(async move {
+            let _ = write.write_all(b"SHARE\n").await;
+            write
+        })
+        .map(|write, this, _| this.write = Some(write))
+        .wait(ctx);
+        Ok(())
+    }
+}
+
+/// Prints the stock of the store as the ecommerce knows it.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct ShowStock;
+
+impl Handler<ShowStock> for AbstractStore {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: ShowStock, _: &mut Self::Context) -> Result<(), Errors> {
+        let mut products: Vec<(&String, &usize)> = self.stock.iter().collect();
+        products.sort();
+        println!("Stock de la store [{}]:", self.store_id);
+        for (product, quantity) in products {
+            println!("    [{product}]: [{quantity}]");
+        }
+        Ok(())
+    }
+}
+
+/// Returns the stock of the store as the ecommerce knows it.
+#[derive(Message)]
+#[rtype(result = "Result<HashMap<String, usize>, Errors>")]
+pub struct GetStock;
+
+impl Handler<GetStock> for AbstractStore {
+    type Result = Result<HashMap<String, usize>, Errors>;
+
+    fn handle(
+        &mut self,
+        _: GetStock,
+        _: &mut Self::Context,
+    ) -> Result<HashMap<String, usize>, Errors> {
+        Ok(self.stock.clone())
+    }
+}
+
+/// Closes the connection with the store from the side of the ecommerce. The store sees it as a lost connection,
+/// so it reconnects later, and the actor stops once the store closes its side.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct Disconnect;
+
+impl Handler<Disconnect> for AbstractStore {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: Disconnect, ctx: &mut Self::Context) -> Result<(), Errors> {
+        let mut write = self
+            .write
+            .take()
+            .expect("No debería poder llegar otro mensaje antes de que vuelva por usar ctx.wait");
+        wrap_future::<_, Self>(async move {
+            let _ = write.shutdown().await;
+            write
+        })
+        .map(|write, this, _| this.write = Some(write))
+        .wait(ctx);
+        Ok(())
+    }
+}
+
+/// Tells the store that a new ecommerce joined the cluster, with the address where it accepts stores.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct AnnounceStoreMember {
+    pub ecom_id: usize,
+    pub stores_address: String,
+}
+
+impl Handler<AnnounceStoreMember> for AbstractStore {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: AnnounceStoreMember, ctx: &mut Self::Context) -> Result<(), Errors> {
+        let mut write = self
+            .write
+            .take()
+            .expect("No debería poder llegar otro mensaje antes de que vuelva por usar ctx.wait");
+        wrap_future::<_, Self>(async move {
+            let _ = write
+                .write_all(format!("MEMBER,{},{}\n", msg.ecom_id, msg.stores_address).as_bytes())
+                .await;
+            write
+        })
+        .map(|write, this, _| this.write = Some(write))
+        .wait(ctx);
+        Ok(())
+    }
+}
+
 // ------------------ TEST PURPOSE MESSAGES ------------------
 
 pub struct _GetStock;
@@ -0,0 +1,341 @@
+use crate::errors::Errors;
+use actix::MailboxError;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::str::FromStr;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+
+/// Where the admin API listens when only its port is configured.
+pub const DEFAULT_ADMIN_HOST: &str = "127.0.0.1";
+/// Bodies bigger than this are rejected, the API only takes small JSON objects.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+const CONTENT_LENGTH_HEADER: &str = "content-length";
+const JSON_CONTENT_TYPE: &str = "application/json";
+const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A request to the admin API. The query string of the path is dropped.
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The answer to a request to the admin API, a JSON body unless it is the metrics of the process.
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The body of the requests that take units of a product, as in `{"product": "ZAP-001", "quantity": 2}`.
+struct ProductQuantity {
+    product: String,
+    quantity: usize,
+}
+
+impl HttpResponse {
+    /// A 200 answer with the value as JSON.
+    pub fn json<T: Serialize>(value: &T) -> HttpResponse {
+        match serde_json::to_string(value) {
+            Ok(body) => HttpResponse {
+                status: 200,
+                content_type: JSON_CONTENT_TYPE,
+                body,
+            },
+            Err(e) => HttpResponse::error(500, e.to_string()),
+        }
+    }
+
+    /// The answer to a write request that was carried out.
+    pub fn accepted() -> HttpResponse {
+        HttpResponse {
+            status: 200,
+            content_type: JSON_CONTENT_TYPE,
+            body: "{\"ok\":true}".to_string(),
+        }
+    }
+
+    /// A 200 answer in the Prometheus text format.
+    pub fn text(body: String) -> HttpResponse {
+        HttpResponse {
+            status: 200,
+            content_type: TEXT_CONTENT_TYPE,
+            body,
+        }
+    }
+
+    pub fn error(status: u16, error: String) -> HttpResponse {
+        let body = serde_json::to_string(&ErrorBody { error }).unwrap_or_default();
+        HttpResponse {
+            status,
+            content_type: JSON_CONTENT_TYPE,
+            body,
+        }
+    }
+
+    /// The answer to a request that failed. Bad requests are a 400, things that do not exist a 404,
+    /// an actor that could not be reached a 503 and anything the actors rejected a 409.
+    pub fn from_error(error: Errors) -> HttpResponse {
+        match error {
+            Errors::InvalidCommand(reason) => HttpResponse::error(400, reason),
+            Errors::UnknownRouteError => HttpResponse::error(404, "no existe la ruta".to_string()),
+            Errors::ActorMsgError => {
+                HttpResponse::error(503, "no se pudo contactar al actor".to_string())
+            }
+            Errors::ProductNotFoundError
+            | Errors::UnknownProductError
+            | Errors::ReservationNotFoundError
+            | Errors::StoreNotConnectedError => HttpResponse::error(404, format!("{:?}", error)),
+            other => HttpResponse::error(409, format!("{:?}", other)),
+        }
+    }
+
+    /// The answer to a read request, with what the actor answered as JSON.
+    pub fn from_query<T: Serialize>(
+        result: Result<Result<T, Errors>, MailboxError>,
+    ) -> HttpResponse {
+        match result {
+            Ok(Ok(value)) => HttpResponse::json(&value),
+            Ok(Err(e)) => HttpResponse::from_error(e),
+            Err(_) => HttpResponse::from_error(Errors::ActorMsgError),
+        }
+    }
+
+    /// The answer to a write request, once the actor carried it out or rejected it.
+    pub fn from_command(result: Result<Result<(), Errors>, MailboxError>) -> HttpResponse {
+        match result {
+            Ok(Ok(())) => HttpResponse::accepted(),
+            Ok(Err(e)) => HttpResponse::from_error(e),
+            Err(_) => HttpResponse::from_error(Errors::ActorMsgError),
+        }
+    }
+
+    /// The response as it is written in the connection. Every connection serves a single request.
+    pub fn to_http(&self) -> String {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            409 => "Conflict",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        };
+        format!(
+            "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )
+    }
+}
+
+/// Reads a request: the request line, the headers and a body of `Content-Length` bytes, if any.
+pub async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<HttpRequest, Errors> {
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .await
+        .map_err(|_| Errors::ErrorReadingFile)?;
+    let mut request_line = line.split_whitespace();
+    let (method, target) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(invalid("la linea del pedido no es valida")),
+    };
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .await
+            .map_err(|_| Errors::ErrorReadingFile)?;
+        let header = line.trim_end();
+        if read == 0 || header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case(CONTENT_LENGTH_HEADER) {
+                content_length = <usize as FromStr>::from_str(value.trim())
+                    .map_err(|_| invalid("el Content-Length no es un numero"))?;
+            }
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Err(invalid("el cuerpo del pedido es demasiado grande"));
+    }
+
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|_| Errors::ErrorReadingFile)?;
+    let body = String::from_utf8(body).map_err(|_| invalid("el cuerpo no es UTF-8"))?;
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    Ok(HttpRequest { method, path, body })
+}
+
+/// Accepts connections in `address` and answers each request with `handler`. It only returns if it can not listen.
+pub async fn serve_http<F, Fut>(address: String, handler: F) -> Result<(), Errors>
+where
+    F: Fn(HttpRequest) -> Fut + Clone + 'static,
+    Fut: Future<Output = HttpResponse> + 'static,
+{
+    let listener = TcpListener::bind(&address)
+        .await
+        .map_err(|_| Errors::ConnectionError)?;
+    info!(address, "Escuchando pedidos HTTP");
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(_) => continue,
+        };
+        let handler = handler.clone();
+        actix::spawn(async move {
+            let (read, mut write) = tokio::io::split(stream);
+            let mut reader = BufReader::new(read);
+            let response = match read_request(&mut reader).await {
+                Ok(request) => {
+                    let (method, path) = (request.method.clone(), request.path.clone());
+                    let response = handler(request).await;
+                    debug!(%peer, method, path, status = response.status, "Pedido HTTP atendido");
+                    response
+                }
+                Err(e) => HttpResponse::from_error(e),
+            };
+            let _ = write.write_all(response.to_http().as_bytes()).await;
+            let _ = write.shutdown().await;
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A request to the admin API of the store.
+pub enum StoreRoute {
+    /// `GET /stock`
+    Stock,
+    /// `GET /reservations`
+    Reservations,
+    /// `GET /connections`, the leader and the connected ecommerces.
+    Connections,
+    /// `POST /sales` with a product and a quantity, a sale to a client at the shop.
+    Sale { product: String, quantity: usize },
+    /// `POST /restock` with a product and a quantity.
+    Restock { product: String, quantity: usize },
+    /// `DELETE /reservations/<id>`
+    CancelReservation { reservation_id: u64 },
+    /// `POST /connect`
+    Connect,
+    /// `POST /disconnect`, after draining the store.
+    Disconnect,
+}
+
+impl StoreRoute {
+    /// Finds the route of a request. The error tells what is wrong with the request, or that the route does not exist.
+    pub fn parse(request: &HttpRequest) -> Result<Self, Errors> {
+        let segments = path_segments(&request.path);
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["stock"]) => Ok(StoreRoute::Stock),
+            ("GET", ["reservations"]) => Ok(StoreRoute::Reservations),
+            ("GET", ["connections"]) => Ok(StoreRoute::Connections),
+            ("POST", ["sales"]) => {
+                let (product, quantity) = product_and_quantity(&request.body)?;
+                Ok(StoreRoute::Sale { product, quantity })
+            }
+            ("POST", ["restock"]) => {
+                let (product, quantity) = product_and_quantity(&request.body)?;
+                Ok(StoreRoute::Restock { product, quantity })
+            }
+            ("DELETE", ["reservations", id]) => <u64 as FromStr>::from_str(id)
+                .map(|reservation_id| StoreRoute::CancelReservation { reservation_id })
+                .map_err(|_| invalid(format!("la reserva [{id}] no es un numero"))),
+            ("POST", ["connect"]) => Ok(StoreRoute::Connect),
+            ("POST", ["disconnect"]) => Ok(StoreRoute::Disconnect),
+            _ => Err(Errors::UnknownRouteError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A request to the admin API of the ecommerce.
+pub enum EcomRoute {
+    /// `GET /leader`, the leader and the connected stores and ecommerces.
+    Leader,
+    /// `GET /members`, every ecommerce of the cluster.
+    Members,
+    /// `GET /stock`, the stock of each connected store as this ecommerce knows it.
+    Stock,
+    /// `GET /orders`, the orders waiting for stock and the outcome of the ones already placed.
+    Orders,
+    /// `POST /orders` with a product and a quantity.
+    Order { product: String, quantity: usize },
+    /// `POST /election`
+    Election,
+    /// `POST /intake/pause`
+    Pause,
+    /// `POST /intake/resume`
+    Resume,
+    /// `POST /stores/<id>/disconnect`
+    DisconnectStore { store_id: String },
+}
+
+impl EcomRoute {
+    /// Finds the route of a request. The error tells what is wrong with the request, or that the route does not exist.
+    pub fn parse(request: &HttpRequest) -> Result<Self, Errors> {
+        let segments = path_segments(&request.path);
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["leader"]) => Ok(EcomRoute::Leader),
+            ("GET", ["members"]) => Ok(EcomRoute::Members),
+            ("GET", ["stock"]) => Ok(EcomRoute::Stock),
+            ("GET", ["orders"]) => Ok(EcomRoute::Orders),
+            ("POST", ["orders"]) => {
+                let (product, quantity) = product_and_quantity(&request.body)?;
+                Ok(EcomRoute::Order { product, quantity })
+            }
+            ("POST", ["election"]) => Ok(EcomRoute::Election),
+            ("POST", ["intake", "pause"]) => Ok(EcomRoute::Pause),
+            ("POST", ["intake", "resume"]) => Ok(EcomRoute::Resume),
+            ("POST", ["stores", store_id, "disconnect"]) => Ok(EcomRoute::DisconnectStore {
+                store_id: store_id.to_string(),
+            }),
+            _ => Err(Errors::UnknownRouteError),
+        }
+    }
+}
+
+fn path_segments(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// Parses a body with a product and a quantity bigger than zero.
+fn product_and_quantity(body: &str) -> Result<(String, usize), Errors> {
+    let parsed: ProductQuantity = serde_json::from_str(body)
+        .map_err(|_| invalid("se espera {\"product\": producto, \"quantity\": cantidad}"))?;
+    if parsed.product.is_empty() || parsed.product.contains(',') {
+        return Err(invalid(format!(
+            "el producto [{}] no es valido",
+            parsed.product
+        )));
+    }
+    if parsed.quantity == 0 {
+        return Err(invalid("la cantidad tiene que ser mayor a cero"));
+    }
+    Ok((parsed.product, parsed.quantity))
+}
+
+fn invalid(reason: impl Into<String>) -> Errors {
+    Errors::InvalidCommand(reason.into())
+}
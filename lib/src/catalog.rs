@@ -0,0 +1,95 @@
+use crate::errors::Errors;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+
+const CENTS_PER_UNIT: u64 = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A product that can be sold. Stock files, order files and the messages between processes refer to it by its SKU.
+/// The price is kept in cents so revenue can be added up without rounding errors.
+pub struct CatalogEntry {
+    pub sku: String,
+    pub name: String,
+    pub price_cents: u64,
+    pub category: String,
+}
+
+#[derive(Debug, Clone, Default)]
+/// The products known by the system, indexed by SKU.
+pub struct Catalog {
+    entries: HashMap<String, CatalogEntry>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Catalog::default()
+    }
+
+    /// Reads the catalog file. Each line has the form `sku,name,price,category`, where the price can have up to two decimals.
+    pub fn load(catalog_file: &str) -> Result<Catalog, Errors> {
+        let file = File::open(catalog_file).map_err(|_| Errors::FileDoesNotExist)?;
+        let reader = BufReader::new(file);
+        let mut catalog = Catalog::new();
+        for line in reader.lines() {
+            let text = line.map_err(|_| Errors::ErrorReadingFile)?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            catalog.add(parse_entry(&text)?)?;
+        }
+        Ok(catalog)
+    }
+
+    /// Adds a product to the catalog. Fails if its SKU is already taken.
+    pub fn add(&mut self, entry: CatalogEntry) -> Result<(), Errors> {
+        if self.entries.contains_key(&entry.sku) {
+            return Err(Errors::DuplicateProductError);
+        }
+        self.entries.insert(entry.sku.clone(), entry);
+        Ok(())
+    }
+
+    pub fn get(&self, sku: &str) -> Option<&CatalogEntry> {
+        self.entries.get(sku)
+    }
+
+    pub fn contains(&self, sku: &str) -> bool {
+        self.entries.contains_key(sku)
+    }
+
+    /// Returns an error if the SKU is not in the catalog.
+    pub fn check(&self, sku: &str) -> Result<&CatalogEntry, Errors> {
+        self.get(sku).ok_or(Errors::UnknownProductError)
+    }
+}
+
+/// Parses a catalog line in the form `sku,name,price,category`.
+fn parse_entry(line: &str) -> Result<CatalogEntry, Errors> {
+    let split: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+    if split.len() != 4 || split[0].is_empty() {
+        return Err(Errors::CouldNotParse);
+    }
+    Ok(CatalogEntry {
+        sku: split[0].to_string(),
+        name: split[1].to_string(),
+        price_cents: parse_price(split[2])?,
+        category: split[3].to_string(),
+    })
+}
+
+/// Parses a price like `1500`, `1500.5` or `1500.50` into cents.
+pub fn parse_price(price: &str) -> Result<u64, Errors> {
+    let (units, cents) = price.split_once('.').unwrap_or((price, ""));
+    if cents.len() > 2 || !cents.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Errors::CouldNotParse);
+    }
+    let units = <u64 as FromStr>::from_str(units).map_err(|_| Errors::CouldNotParse)?;
+    let cents =
+        <u64 as FromStr>::from_str(&format!("{:0<2}", cents)).map_err(|_| Errors::CouldNotParse)?;
+    units
+        .checked_mul(CENTS_PER_UNIT)
+        .and_then(|units| units.checked_add(cents))
+        .ok_or(Errors::CouldNotParse)
+}
@@ -0,0 +1,162 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// How many times `VirtualClock::advance()` yields after waking sleepers, so the woken tasks run up to their next wait.
+const SETTLE_YIELDS: usize = 20;
+
+/// A wait of the clock, that ends when the given time has passed on it.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Where the nodes read the time and wait for it: deadlines, dispatch delays, arrival of clients and orders.
+/// The binaries use the real time, while tests and simulations can move a virtual one at will.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// A clock shared by every actor and task of a node.
+pub type SharedClock = Arc<dyn Clock>;
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The time of the system, waited for with the timers of tokio.
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+pub fn real_clock() -> SharedClock {
+    Arc::new(RealClock)
+}
+
+#[derive(Debug)]
+struct VirtualTime {
+    start: Instant,
+    elapsed: Duration,
+    /// The waits that have not ended yet, with the elapsed time at which they end.
+    sleepers: Vec<(Duration, oneshot::Sender<()>)>,
+}
+
+#[derive(Debug, Clone)]
+/// A clock that only moves when it is advanced, so a wait of minutes takes no time at all.
+/// Clones share the same time.
+pub struct VirtualClock {
+    time: Arc<Mutex<VirtualTime>>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        VirtualClock::new()
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        VirtualClock {
+            time: Arc::new(Mutex::new(VirtualTime {
+                start: Instant::now(),
+                elapsed: Duration::ZERO,
+                sleepers: vec![],
+            })),
+        }
+    }
+
+    pub fn shared(&self) -> SharedClock {
+        Arc::new(self.clone())
+    }
+
+    /// How much time went by since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        self.time
+            .lock()
+            .map(|time| time.elapsed)
+            .unwrap_or_default()
+    }
+
+    /// How many waits have not ended yet.
+    pub fn sleepers(&self) -> usize {
+        self.time
+            .lock()
+            .map(|time| {
+                time.sleepers
+                    .iter()
+                    .filter(|(_, sender)| !sender.is_closed())
+                    .count()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Moves the clock `duration` forward. The waits end in order, and after each one the woken tasks get to run,
+    /// so a task that waits again before the target time is woken again within the same advance.
+    pub async fn advance(&self, duration: Duration) {
+        let target = self.elapsed() + duration;
+        loop {
+            let woken = self.wake_next(target);
+            for _ in 0..SETTLE_YIELDS {
+                tokio::task::yield_now().await;
+            }
+            if !woken {
+                break;
+            }
+        }
+    }
+
+    /// Moves the clock to the end of the first wait before `target`, or to `target` if there is none, and ends
+    /// every wait due by then. Returns whether a wait ended before `target`.
+    fn wake_next(&self, target: Duration) -> bool {
+        let mut time = match self.time.lock() {
+            Ok(time) => time,
+            Err(_) => return false,
+        };
+        let next = time
+            .sleepers
+            .iter()
+            .map(|(deadline, _)| *deadline)
+            .filter(|deadline| *deadline <= target)
+            .min();
+        time.elapsed = next.unwrap_or(target).max(time.elapsed);
+        let now = time.elapsed;
+        let (due, waiting) = std::mem::take(&mut time.sleepers)
+            .into_iter()
+            .partition(|(deadline, _)| *deadline <= now);
+        time.sleepers = waiting;
+        for (_, sender) in due {
+            let _ = sender.send(());
+        }
+        next.is_some()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.time
+            .lock()
+            .map(|time| time.start + time.elapsed)
+            .unwrap_or_else(|_| Instant::now())
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let (sender, receiver) = oneshot::channel();
+        if let Ok(mut time) = self.time.lock() {
+            let deadline = time.elapsed + duration;
+            if duration.is_zero() {
+                let _ = sender.send(());
+            } else {
+                time.sleepers.push((deadline, sender));
+            }
+        }
+        Box::pin(async move {
+            let _ = receiver.await;
+        })
+    }
+}
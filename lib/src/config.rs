@@ -0,0 +1,549 @@
+use crate::admin_api::DEFAULT_ADMIN_HOST;
+use crate::coordinator::Member;
+use crate::errors::Errors;
+use crate::logging::{log_filter, LogFormat};
+use crate::reconnect::ReconnectPolicy;
+use crate::stock_ledger::DEFAULT_MAX_MOVEMENTS;
+use crate::transport::UNIX_PREFIX;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+const CONFIG_FLAG: &str = "--config";
+const FLAG_PREFIX: &str = "--";
+const PEER_SEPARATOR: char = '@';
+const LIST_SEPARATOR: char = ',';
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Another node of the network, known by its id and the address where it accepts connections.
+/// The peers of an ecommerce can also give the address where they accept stores, so stores that
+/// discover the cluster through this ecommerce can be told about them.
+pub struct PeerConfig {
+    pub id: usize,
+    pub address: String,
+    #[serde(default)]
+    pub stores_address: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+/// Knobs that change how the ecommerce behaves, all of them with a sensible default.
+pub struct EcomTuning {
+    pub backorder_max_wait_secs: u64,
+    pub min_order_delay_secs: u64,
+    pub max_order_delay_secs: u64,
+    pub reconnect_initial_ms: u64,
+    pub reconnect_max_ms: u64,
+    pub reconnect_attempts: u32,
+}
+
+impl Default for EcomTuning {
+    fn default() -> Self {
+        EcomTuning {
+            backorder_max_wait_secs: 30,
+            min_order_delay_secs: 2,
+            max_order_delay_secs: 6,
+            reconnect_initial_ms: 200,
+            reconnect_max_ms: 10000,
+            reconnect_attempts: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+/// Configuration of the ecommerce binary. `ecoms_address` is where the other ecommerces connect to
+/// and `stores_address` is where the stores do. An ecommerce that is not in the `peers` of the others
+/// joins the running cluster through any of its `seeds`, the addresses of ecommerces already in it.
+/// The admin API and the metrics are only served if `admin_port` and `metrics_port` are set, on `admin_host`
+/// or on localhost if it is not. `log_level` filters the logs, for the whole process or for each module.
+/// Every random decision is drawn from `seed`, so a run can be repeated. Without it, a random seed is used.
+pub struct EcomConfig {
+    pub id: usize,
+    pub ecoms_address: String,
+    pub stores_address: String,
+    pub orders_file: String,
+    pub catalog_file: String,
+    pub peers: Vec<PeerConfig>,
+    pub seeds: Vec<String>,
+    pub admin_host: String,
+    pub admin_port: Option<u16>,
+    pub metrics_port: Option<u16>,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub seed: Option<u64>,
+    pub tuning: EcomTuning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+/// Knobs that change how the store behaves, all of them with a sensible default.
+pub struct StoreTuning {
+    pub min_dispatch_secs: u64,
+    pub max_dispatch_secs: u64,
+    pub physical_clients_delay_secs: u64,
+    pub drain_max_wait_secs: u64,
+    pub max_stock_movements: usize,
+    pub reconnect_initial_ms: u64,
+    pub reconnect_max_ms: u64,
+    pub reconnect_attempts: u32,
+}
+
+impl Default for StoreTuning {
+    fn default() -> Self {
+        StoreTuning {
+            min_dispatch_secs: 1,
+            max_dispatch_secs: 10,
+            physical_clients_delay_secs: 2,
+            drain_max_wait_secs: 10,
+            max_stock_movements: DEFAULT_MAX_MOVEMENTS,
+            reconnect_initial_ms: 200,
+            reconnect_max_ms: 10000,
+            reconnect_attempts: 10,
+        }
+    }
+}
+
+impl EcomTuning {
+    /// How connections with other ecommerces are retried. Configured peers are retried forever,
+    /// ecommerces that joined later only `reconnect_attempts` times.
+    pub fn reconnect_policy(&self) -> ReconnectPolicy {
+        reconnect_policy(
+            self.reconnect_initial_ms,
+            self.reconnect_max_ms,
+            self.reconnect_attempts,
+        )
+    }
+}
+
+impl StoreTuning {
+    /// How connections with ecommerces are retried. Configured ecommerces are retried forever,
+    /// discovered ones only `reconnect_attempts` times.
+    pub fn reconnect_policy(&self) -> ReconnectPolicy {
+        reconnect_policy(
+            self.reconnect_initial_ms,
+            self.reconnect_max_ms,
+            self.reconnect_attempts,
+        )
+    }
+}
+
+fn reconnect_policy(initial_ms: u64, max_ms: u64, attempts: u32) -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_delay: Duration::from_millis(initial_ms),
+        max_delay: Duration::from_millis(max_ms),
+        max_attempts: Some(attempts),
+        // The node seeds it once it knows its seed
+        seed: 0,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+/// Configuration of the store binary. Each entry of `ecommerces` has the address where that ecommerce accepts stores.
+/// Instead of listing every ecommerce, the store can be given `seeds`, addresses where some ecommerces accept stores,
+/// and it asks them for the rest of the cluster. The admin API and the metrics are only served if `admin_port`
+/// and `metrics_port` are set, on `admin_host` or on localhost if it is not. `log_level` filters the logs,
+/// for the whole process or for each module. Every random decision is drawn from `seed`, so a run can be repeated.
+/// Without it, a random seed is used.
+pub struct StoreConfig {
+    pub id: String,
+    pub stock_file: String,
+    pub orders_file: String,
+    pub catalog_file: String,
+    pub ecommerces: Vec<PeerConfig>,
+    pub seeds: Vec<String>,
+    pub admin_host: String,
+    pub admin_port: Option<u16>,
+    pub metrics_port: Option<u16>,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub seed: Option<u64>,
+    pub tuning: StoreTuning,
+}
+
+impl EcomConfig {
+    /// Builds the configuration from the command line: an optional `--config <file>` followed by
+    /// `--<setting> <value>` pairs that override the settings of the file.
+    pub fn from_args(args: &[String]) -> Result<EcomConfig, Errors> {
+        let (config_file, overrides) = parse_args(args)?;
+        let mut config: EcomConfig = load_file(config_file.as_deref())?;
+        for (setting, value) in overrides {
+            config.apply_override(&setting, &value)?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_toml(text: &str) -> Result<EcomConfig, Errors> {
+        toml::from_str(text).map_err(|e| Errors::InvalidConfig(e.to_string()))
+    }
+
+    /// Replaces a setting with the value given in the command line.
+    pub fn apply_override(&mut self, setting: &str, value: &str) -> Result<(), Errors> {
+        match setting {
+            "id" => self.id = parse_setting(setting, value)?,
+            "ecoms_address" => self.ecoms_address = value.to_string(),
+            "stores_address" => self.stores_address = value.to_string(),
+            "orders_file" => self.orders_file = value.to_string(),
+            "catalog_file" => self.catalog_file = value.to_string(),
+            "peers" => self.peers = parse_peers(setting, value)?,
+            "seeds" => self.seeds = parse_list(value),
+            "admin_host" => self.admin_host = value.to_string(),
+            "admin_port" => self.admin_port = Some(parse_setting(setting, value)?),
+            "metrics_port" => self.metrics_port = Some(parse_setting(setting, value)?),
+            "log_level" => self.log_level = value.to_string(),
+            "log_format" => self.log_format = value.parse()?,
+            "seed" => self.seed = Some(parse_setting(setting, value)?),
+            "tuning.backorder_max_wait_secs" => {
+                self.tuning.backorder_max_wait_secs = parse_setting(setting, value)?
+            }
+            "tuning.min_order_delay_secs" => {
+                self.tuning.min_order_delay_secs = parse_setting(setting, value)?
+            }
+            "tuning.max_order_delay_secs" => {
+                self.tuning.max_order_delay_secs = parse_setting(setting, value)?
+            }
+            "tuning.reconnect_initial_ms" => {
+                self.tuning.reconnect_initial_ms = parse_setting(setting, value)?
+            }
+            "tuning.reconnect_max_ms" => {
+                self.tuning.reconnect_max_ms = parse_setting(setting, value)?
+            }
+            "tuning.reconnect_attempts" => {
+                self.tuning.reconnect_attempts = parse_setting(setting, value)?
+            }
+            _ => return Err(invalid(setting, "unknown setting")),
+        }
+        Ok(())
+    }
+
+    /// Where the admin API listens, if it is enabled.
+    pub fn admin_address(&self) -> Option<String> {
+        admin_address(&self.admin_host, self.admin_port)
+    }
+
+    /// Where the metrics are served, if they are enabled.
+    pub fn metrics_address(&self) -> Option<String> {
+        admin_address(&self.admin_host, self.metrics_port)
+    }
+
+    /// Every ecom of the cluster known up front, this one included, with the addresses where it accepts ecommerces and stores.
+    pub fn members(&self) -> HashMap<usize, Member> {
+        let mut members: HashMap<usize, Member> = self
+            .peers
+            .iter()
+            .map(|peer| {
+                let member = Member {
+                    ecoms_address: peer.address.clone(),
+                    stores_address: peer.stores_address.clone(),
+                };
+                (peer.id, member)
+            })
+            .collect();
+        let me = Member {
+            ecoms_address: self.ecoms_address.clone(),
+            stores_address: self.stores_address.clone(),
+        };
+        members.insert(self.id, me);
+        members
+    }
+
+    /// Checks every setting, returning an error that names the first bad one.
+    pub fn validate(&self) -> Result<(), Errors> {
+        if self.id == 0 {
+            return Err(invalid("id", "must be set to a positive number"));
+        }
+        validate_address("ecoms_address", &self.ecoms_address)?;
+        validate_address("stores_address", &self.stores_address)?;
+        validate_file("orders_file", &self.orders_file)?;
+        validate_file("catalog_file", &self.catalog_file)?;
+        validate_peers("peers", &self.peers, Some(self.id))?;
+        validate_seeds(&self.seeds)?;
+        validate_admin_host(&self.admin_host)?;
+        log_filter(&self.log_level)?;
+        if self.tuning.min_order_delay_secs >= self.tuning.max_order_delay_secs {
+            return Err(invalid(
+                "tuning.min_order_delay_secs",
+                "must be lower than tuning.max_order_delay_secs",
+            ));
+        }
+        validate_reconnect(
+            self.tuning.reconnect_initial_ms,
+            self.tuning.reconnect_max_ms,
+        )?;
+        Ok(())
+    }
+}
+
+impl StoreConfig {
+    /// Builds the configuration from the command line: an optional `--config <file>` followed by
+    /// `--<setting> <value>` pairs that override the settings of the file.
+    pub fn from_args(args: &[String]) -> Result<StoreConfig, Errors> {
+        let (config_file, overrides) = parse_args(args)?;
+        let mut config: StoreConfig = load_file(config_file.as_deref())?;
+        for (setting, value) in overrides {
+            config.apply_override(&setting, &value)?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Where the admin API listens, if it is enabled.
+    pub fn admin_address(&self) -> Option<String> {
+        admin_address(&self.admin_host, self.admin_port)
+    }
+
+    /// Where the metrics are served, if they are enabled.
+    pub fn metrics_address(&self) -> Option<String> {
+        admin_address(&self.admin_host, self.metrics_port)
+    }
+
+    pub fn from_toml(text: &str) -> Result<StoreConfig, Errors> {
+        toml::from_str(text).map_err(|e| Errors::InvalidConfig(e.to_string()))
+    }
+
+    /// Replaces a setting with the value given in the command line.
+    pub fn apply_override(&mut self, setting: &str, value: &str) -> Result<(), Errors> {
+        match setting {
+            "id" => self.id = value.to_string(),
+            "stock_file" => self.stock_file = value.to_string(),
+            "orders_file" => self.orders_file = value.to_string(),
+            "catalog_file" => self.catalog_file = value.to_string(),
+            "ecommerces" => self.ecommerces = parse_peers(setting, value)?,
+            "seeds" => self.seeds = parse_list(value),
+            "admin_host" => self.admin_host = value.to_string(),
+            "admin_port" => self.admin_port = Some(parse_setting(setting, value)?),
+            "metrics_port" => self.metrics_port = Some(parse_setting(setting, value)?),
+            "log_level" => self.log_level = value.to_string(),
+            "log_format" => self.log_format = value.parse()?,
+            "seed" => self.seed = Some(parse_setting(setting, value)?),
+            "tuning.min_dispatch_secs" => {
+                self.tuning.min_dispatch_secs = parse_setting(setting, value)?
+            }
+            "tuning.max_dispatch_secs" => {
+                self.tuning.max_dispatch_secs = parse_setting(setting, value)?
+            }
+            "tuning.physical_clients_delay_secs" => {
+                self.tuning.physical_clients_delay_secs = parse_setting(setting, value)?
+            }
+            "tuning.drain_max_wait_secs" => {
+                self.tuning.drain_max_wait_secs = parse_setting(setting, value)?
+            }
+            "tuning.max_stock_movements" => {
+                self.tuning.max_stock_movements = parse_setting(setting, value)?
+            }
+            "tuning.reconnect_initial_ms" => {
+                self.tuning.reconnect_initial_ms = parse_setting(setting, value)?
+            }
+            "tuning.reconnect_max_ms" => {
+                self.tuning.reconnect_max_ms = parse_setting(setting, value)?
+            }
+            "tuning.reconnect_attempts" => {
+                self.tuning.reconnect_attempts = parse_setting(setting, value)?
+            }
+            _ => return Err(invalid(setting, "unknown setting")),
+        }
+        Ok(())
+    }
+
+    /// Checks every setting, returning an error that names the first bad one.
+    pub fn validate(&self) -> Result<(), Errors> {
+        // The id travels inside the messages, which are separated by commas
+        if self.id.is_empty() || self.id.contains(LIST_SEPARATOR) {
+            return Err(invalid("id", "must be set and can not have commas"));
+        }
+        validate_file("stock_file", &self.stock_file)?;
+        validate_file("orders_file", &self.orders_file)?;
+        validate_file("catalog_file", &self.catalog_file)?;
+        validate_peers("ecommerces", &self.ecommerces, None)?;
+        validate_seeds(&self.seeds)?;
+        validate_admin_host(&self.admin_host)?;
+        log_filter(&self.log_level)?;
+        if self.ecommerces.is_empty() && self.seeds.is_empty() {
+            return Err(invalid(
+                "ecommerces",
+                "either ecommerces or seeds must be set",
+            ));
+        }
+        if self.tuning.min_dispatch_secs >= self.tuning.max_dispatch_secs {
+            return Err(invalid(
+                "tuning.min_dispatch_secs",
+                "must be lower than tuning.max_dispatch_secs",
+            ));
+        }
+        validate_reconnect(
+            self.tuning.reconnect_initial_ms,
+            self.tuning.reconnect_max_ms,
+        )?;
+        Ok(())
+    }
+}
+
+type ParsedArgs = (Option<String>, Vec<(String, String)>);
+/// Splits the command line into the config file, if any, and the overridden settings.
+/// Settings can be written with dashes or underscores, so `--orders-file` and `--orders_file` are the same.
+fn parse_args(args: &[String]) -> Result<ParsedArgs, Errors> {
+    let mut config_file = None;
+    let mut overrides = vec![];
+    let mut iter = args.iter().skip(1);
+    while let Some(flag) = iter.next() {
+        let setting = match flag.strip_prefix(FLAG_PREFIX) {
+            Some(setting) if !setting.is_empty() => setting,
+            _ => return Err(invalid(flag, "expected --<setting> <value>")),
+        };
+        let value = match iter.next() {
+            Some(value) => value.clone(),
+            None => return Err(invalid(flag, "missing value")),
+        };
+        if flag == CONFIG_FLAG {
+            config_file = Some(value);
+        } else {
+            overrides.push((setting.replace('-', "_"), value));
+        }
+    }
+    Ok((config_file, overrides))
+}
+
+fn load_file<T: DeserializeOwned + Default>(config_file: Option<&str>) -> Result<T, Errors> {
+    let path = match config_file {
+        Some(path) => path,
+        None => return Ok(T::default()),
+    };
+    let text = fs::read_to_string(path).map_err(|_| invalid("config", "file does not exist"))?;
+    toml::from_str(&text).map_err(|e| Errors::InvalidConfig(format!("{path}: {e}")))
+}
+
+/// Parses a list of peers in the form `id@address,id@address`. Each peer can end with `@stores_address`.
+fn parse_peers(setting: &str, value: &str) -> Result<Vec<PeerConfig>, Errors> {
+    let mut peers = vec![];
+    for peer in parse_list(value) {
+        let fields: Vec<&str> = peer.split(PEER_SEPARATOR).collect();
+        if fields.len() != 2 && fields.len() != 3 {
+            return Err(invalid(setting, "expected a list of id@address"));
+        }
+        peers.push(PeerConfig {
+            id: parse_setting(setting, fields[0])?,
+            address: fields[1].to_string(),
+            stores_address: fields.get(2).unwrap_or(&"").to_string(),
+        });
+    }
+    Ok(peers)
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(LIST_SEPARATOR)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+fn parse_setting<T: FromStr>(setting: &str, value: &str) -> Result<T, Errors> {
+    <T as FromStr>::from_str(value)
+        .map_err(|_| invalid(setting, format!("invalid value '{value}'")))
+}
+
+/// An address is `ip:port`, or `unix:<file>` for a Unix domain socket. The file is a name in the directory the
+/// node runs in, since addresses travel inside the messages, where `,`, `@` and `/` separate the fields.
+fn validate_address(field: &str, address: &str) -> Result<(), Errors> {
+    if let Some(file) = address.strip_prefix(UNIX_PREFIX) {
+        if file.is_empty() || file.contains([LIST_SEPARATOR, PEER_SEPARATOR, '/']) {
+            return Err(invalid(
+                field,
+                format!("'{address}' is not unix:<file>, with a file name without ',', '@' or '/'"),
+            ));
+        }
+        return Ok(());
+    }
+    address
+        .parse::<SocketAddr>()
+        .map(|_| ())
+        .map_err(|_| invalid(field, format!("'{address}' is not an ip:port address")))
+}
+
+fn validate_file(field: &str, file: &str) -> Result<(), Errors> {
+    if file.is_empty() {
+        return Err(invalid(field, "must be set"));
+    }
+    if !Path::new(file).is_file() {
+        return Err(invalid(field, format!("file '{file}' does not exist")));
+    }
+    Ok(())
+}
+
+/// Every peer needs a valid address and an id that is not repeated, nor the one of the node itself.
+fn validate_peers(field: &str, peers: &[PeerConfig], my_id: Option<usize>) -> Result<(), Errors> {
+    for (i, peer) in peers.iter().enumerate() {
+        validate_address(&format!("{field}[{i}].address"), &peer.address)?;
+        if !peer.stores_address.is_empty() {
+            validate_address(
+                &format!("{field}[{i}].stores_address"),
+                &peer.stores_address,
+            )?;
+        }
+        if Some(peer.id) == my_id {
+            return Err(invalid(
+                &format!("{field}[{i}].id"),
+                "is the id of this node",
+            ));
+        }
+        if peers[..i].iter().any(|other| other.id == peer.id) {
+            return Err(invalid(
+                &format!("{field}[{i}].id"),
+                format!("id {} is repeated", peer.id),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_reconnect(initial_ms: u64, max_ms: u64) -> Result<(), Errors> {
+    if initial_ms == 0 {
+        return Err(invalid("tuning.reconnect_initial_ms", "must be positive"));
+    }
+    if initial_ms > max_ms {
+        return Err(invalid(
+            "tuning.reconnect_initial_ms",
+            "can not be bigger than tuning.reconnect_max_ms",
+        ));
+    }
+    Ok(())
+}
+
+fn admin_address(host: &str, port: Option<u16>) -> Option<String> {
+    let host = if host.is_empty() {
+        DEFAULT_ADMIN_HOST
+    } else {
+        host
+    };
+    port.map(|port| format!("{host}:{port}"))
+}
+
+fn validate_admin_host(host: &str) -> Result<(), Errors> {
+    if host.is_empty() || host.parse::<IpAddr>().is_ok() {
+        return Ok(());
+    }
+    Err(invalid(
+        "admin_host",
+        format!("'{host}' is not an ip address"),
+    ))
+}
+
+fn validate_seeds(seeds: &[String]) -> Result<(), Errors> {
+    for (i, seed) in seeds.iter().enumerate() {
+        validate_address(&format!("seeds[{i}]"), seed)?;
+    }
+    Ok(())
+}
+
+fn invalid(field: &str, reason: impl Display) -> Errors {
+    Errors::InvalidConfig(format!("{field}: {reason}"))
+}
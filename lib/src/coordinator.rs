@@ -1,38 +1,348 @@
 extern crate actix;
 
-use crate::abstract_ecom::{AbstractEcom, Election, NewLeader2, SendOrder};
-use crate::abstract_store::{AbstractStore, NewLeader, Order};
+use crate::abstract_ecom::{
+    AbstractEcom, AnnounceEcomMember, CloseConnection, Election, Leave, NewLeader2, SendExpired,
+    SendMembers, SendOrder,
+};
+use crate::abstract_store::{
+    AbstractStore, AnnounceStoreMember, Disconnect, GetStock, NewLeader, Order, ShowStock,
+};
+use crate::catalog::Catalog;
+use crate::clock::SharedClock;
+use crate::ecom::{connect_to_ecom, CLOSE_MSG};
+use crate::ecom_handler::Stop;
 use crate::errors::Errors;
-use actix::{Actor, Addr, AsyncContext, Context, Handler, Message, StreamHandler};
-use rand::rngs::ThreadRng;
+use crate::logging::order_span;
+use crate::metrics::{Counter, Histogram, Metrics};
+use crate::random::NodeRng;
+use crate::reconnect::ReconnectPolicy;
+use crate::sales_report::{Channel, LossReason, SalesReport};
+use crate::trace::TraceId;
+use crate::transport::Connection;
+use actix::{
+    fut::wrap_future, Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Context, Handler,
+    Message, ResponseFuture, StreamHandler,
+};
 use rand::seq::SliceRandom;
 use rand::Rng;
+use serde::Serialize;
 use std::clone::Clone;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::str::FromStr;
-use tokio::io::{split, AsyncBufReadExt, BufReader};
-use tokio::net::TcpStream;
+use std::time::{Duration, Instant};
+use tokio::io::{split, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::oneshot;
 use tokio_stream::wrappers::LinesStream;
+use tracing::{debug, error, info, warn};
 
 const MIN_SECS_LIMIT: u64 = 1;
 const MAX_SECS_LIMIT: u64 = 10;
+const BACKORDER_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Coordinator actor. It is in charge of handling the connection with the ecommerces and the stores,
 /// as well as redirecting the orders to the stores and handling the stock and election of the leader.
+/// The members are every ecommerce of the cluster, this one included, with the addresses where it accepts ecommerces and stores.
+/// The members learnt after joining the cluster are dialed following the `reconnect` policy.
+/// The stores the orders go to and their time limits are drawn from `rng`, the coordinator stream of the seed of the node,
+/// and the traces of the orders that arrive without one from `traces`, its trace stream.
+/// While it is the leader, it keeps the stock of the stores that disconnect in `detached_stores`, so a store that
+/// reconnects only has to send what changed meanwhile. While `intake_paused`, it takes no new orders from its clients.
+/// The `metrics` are shared with its AbstractStores, and `election_started` is when the election it started began.
+/// The waits of the backorders and the elections are measured on `clock`.
 pub struct Coordinator {
     pub online_orders: Vec<String>,
     pub active_stores: HashMap<String, Addr<AbstractStore>>,
-    pub active_ecoms: HashMap<usize, Addr<AbstractEcom>>,
-    pub rng: ThreadRng,
+    pub active_ecoms: HashMap<usize, EcomConnection>,
+    pub rng: NodeRng,
+    pub traces: NodeRng,
     pub id: usize,
     pub curr_leader: Option<usize>,
+    pub members: HashMap<usize, Member>,
+    pub reconnect: ReconnectPolicy,
+    pub detached_stores: HashMap<String, DetachedStore>,
+    pub backorders: HashMap<String, VecDeque<Backorder>>,
+    pub backorder_max_wait: Duration,
+    pub catalog: Catalog,
+    pub sales: SalesReport,
+    pub intake_paused: bool,
+    pub metrics: Metrics,
+    pub election_started: Option<Instant>,
+    pub clock: SharedClock,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+/// An ecommerce of the cluster, known by the address where it accepts other ecommerces and the one where it accepts stores.
+/// An address is empty while it is not known yet.
+pub struct Member {
+    pub ecoms_address: String,
+    pub stores_address: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// What the leader knew of a store when it disconnected: its stock and the session and number of the last notification
+/// applied to it.
+pub struct DetachedStore {
+    pub stock: HashMap<String, usize>,
+    pub last_seq: u64,
+    pub session: u64,
+}
+
+#[derive(Clone)]
+/// The connection with another ecommerce. Two ecommerces may dial each other at the same time, so of the
+/// connections between them the one dialed by the smallest id is _preferred_ and is the one both keep.
+pub struct EcomConnection {
+    pub addr: Addr<AbstractEcom>,
+    pub preferred: bool,
+}
+
+#[derive(Debug, Clone)]
+/// An order that no store could fill when it arrived. It waits in the queue of its product until
+/// a store reports new stock of it, or until it has waited longer than `backorder_max_wait`.
+/// `origin` is the ecommerce that took the order, None if it was this one.
+pub struct Backorder {
+    pub order: String,
+    pub quantity: usize,
+    pub since: Instant,
+    pub trace_id: TraceId,
+    pub origin: Option<usize>,
 }
 
 impl Actor for Coordinator {
     type Context = Context<Self>;
 
-    fn started(&mut self, _: &mut Self::Context) {
-        println!("¡Coordinator is alive!");
+    fn started(&mut self, ctx: &mut Self::Context) {
+        debug!(id = self.id, "Coordinador iniciado");
+        self.schedule_backorder_check(ctx);
+    }
+}
+
+/// The next ecom of the ring after `my_id`: the smallest id bigger than it, wrapping around to the smallest id
+/// of all. Returns None when there is no other ecom, so the node is a cluster of one.
+pub fn ring_successor(my_id: usize, members: &[usize]) -> Option<usize> {
+    let others = members.iter().copied().filter(|id| *id != my_id);
+    others
+        .clone()
+        .filter(|id| *id > my_id)
+        .min()
+        .or_else(|| others.min())
+}
+
+/// Appends to the order the time limit the store has to dispatch it.
+fn order_with_time_limit(rng: &mut NodeRng, order: &str) -> String {
+    format!(
+        "{},{}",
+        order,
+        rng.gen_range(MIN_SECS_LIMIT, MAX_SECS_LIMIT)
+    )
+}
+
+impl Coordinator {
+    /// Checks the backorders every `BACKORDER_CHECK_INTERVAL` of the clock, for as long as the coordinator runs.
+    fn schedule_backorder_check(&self, ctx: &mut Context<Self>) {
+        let sleep = wrap_future::<_, Self>(self.clock.sleep(BACKORDER_CHECK_INTERVAL));
+        ctx.spawn(sleep.map(|_, this, ctx| {
+            ctx.notify(ExpireBackorders);
+            this.schedule_backorder_check(ctx);
+        }));
+    }
+
+    /// Takes `new_leader_id` as the leader and tells every connected store.
+    /// If this ecommerce was the leader, its backorders are handed over to the new one.
+    fn change_leader(&mut self, new_leader_id: usize) {
+        if self.curr_leader == Some(self.id) && new_leader_id != self.id {
+            self.hand_over_backorders(new_leader_id);
+        }
+        self.curr_leader = Some(new_leader_id);
+        for store_addr in self.active_stores.values() {
+            let _ = store_addr.try_send(NewLeader {
+                leader_id: new_leader_id.to_string(),
+            });
+        }
+    }
+
+    /// Sends every backorder to the new leader as an order taken by its origin, so it keeps waiting there.
+    /// If the new leader is not connected, they keep waiting here.
+    fn hand_over_backorders(&mut self, new_leader_id: usize) {
+        let leader = match self.active_ecoms.get(&new_leader_id) {
+            Some(leader) => leader.addr.clone(),
+            None => return,
+        };
+        for (_, queue) in self.backorders.drain() {
+            for backorder in queue {
+                let _span = order_span(backorder.trace_id, &backorder.order).entered();
+                info!(leader = new_leader_id, "Backorder cedido al nuevo lider");
+                let sent = leader.try_send(SendOrder {
+                    order: backorder.order,
+                    trace_id: backorder.trace_id,
+                    origin: backorder.origin.unwrap_or(self.id),
+                });
+                self.metrics.sent(sent, "abstract_ecom");
+            }
+        }
+    }
+
+    /// Queues an order that no store can fill right now, under the product it asks for.
+    fn add_backorder(
+        &mut self,
+        order: String,
+        trace_id: TraceId,
+        origin: Option<usize>,
+    ) -> Result<(), Errors> {
+        let split: Vec<&str> = order.split(',').collect();
+        if split.len() < 2 {
+            return Err(Errors::CouldNotParse);
+        }
+        let product = split[0].to_string();
+        let quantity = <usize as FromStr>::from_str(split[1]).map_err(|_| Errors::CouldNotParse)?;
+
+        info!("Pedido guardado como backorder");
+        self.backorders
+            .entry(product)
+            .or_default()
+            .push_back(Backorder {
+                order,
+                quantity,
+                since: self.clock.now(),
+                trace_id,
+                origin,
+            });
+        Ok(())
+    }
+
+    /// Drops every backorder that waited longer than `backorder_max_wait`. Each one is reported as expired to the
+    /// ecommerce that took it, or here if it was this one or it is no longer connected.
+    fn expire_backorders(&mut self, ctx: &mut Context<Self>) {
+        let max_wait = self.backorder_max_wait;
+        let now = self.clock.now();
+        let mut expired = Vec::new();
+        for queue in self.backorders.values_mut() {
+            queue.retain(|backorder| {
+                let waited_too_long = now.saturating_duration_since(backorder.since) > max_wait;
+                if waited_too_long {
+                    expired.push(backorder.clone());
+                }
+                !waited_too_long
+            });
+        }
+        self.backorders.retain(|_, queue| !queue.is_empty());
+
+        for backorder in expired {
+            let _span = order_span(backorder.trace_id, &backorder.order).entered();
+            warn!(?max_wait, "Backorder expirado");
+            let origin = backorder
+                .origin
+                .filter(|origin| *origin != self.id)
+                .and_then(|origin| self.active_ecoms.get(&origin))
+                .map(|ecom| ecom.addr.clone());
+            if let Some(origin) = origin {
+                let sent = origin.try_send(SendExpired {
+                    order: backorder.order,
+                    trace_id: backorder.trace_id,
+                });
+                self.metrics.sent(sent, "abstract_ecom");
+            } else {
+                let msg = OrderExpired {
+                    order: backorder.order,
+                    trace_id: Some(backorder.trace_id),
+                };
+                let _ = <Self as Handler<OrderExpired>>::handle(self, msg, ctx);
+            }
+        }
+    }
+
+    /// Creates the AbstractEcom that handles the connection with another ecommerce, dialed by `dialer`.
+    /// If there already is a connection with it, only one of them is kept: the new one is closed if the current
+    /// one is preferred, otherwise the new one replaces it. Returns None if the new connection was closed.
+    /// If its id is bigger than the one of the leader, it becomes the new leader.
+    fn add_ecom(
+        &mut self,
+        new_ecom_id: usize,
+        stream: Connection,
+        dialer: usize,
+        closed: Option<oneshot::Sender<()>>,
+        coord_ctx: &mut Context<Self>,
+    ) -> Option<Addr<AbstractEcom>> {
+        let preferred = dialer == self.id.min(new_ecom_id);
+        if let Some(current) = self.active_ecoms.get(&new_ecom_id) {
+            if current.preferred && !preferred {
+                info!(
+                    ecom_id = new_ecom_id,
+                    "Ya hay una conexion con el ecommerce, se cierra la nueva"
+                );
+                actix::spawn(async move {
+                    let mut stream = stream;
+                    let _ = stream.write_all(CLOSE_MSG.as_bytes()).await;
+                });
+                return None;
+            }
+            info!(
+                ecom_id = new_ecom_id,
+                "Se reemplaza la conexion con el ecommerce"
+            );
+            let _ = current.addr.try_send(CloseConnection);
+        }
+
+        let abstract_ecom = AbstractEcom::create(|ctx| {
+            let (read, write_half) = split(stream);
+            AbstractEcom::add_stream(LinesStream::new(BufReader::new(read).lines()), ctx);
+            let write = Some(write_half);
+            AbstractEcom {
+                write,
+                id: new_ecom_id,
+                coord: coord_ctx.address(),
+                closed,
+                closing: false,
+                left: false,
+            }
+        });
+
+        // An ecom that connects before being announced is still part of the ring, its addresses are learnt later
+        let member = self.members.entry(new_ecom_id).or_default().clone();
+        if !member.stores_address.is_empty() {
+            // The ecom may be coming back, so the stores that dropped it connect to it again
+            self.announce_to_stores(new_ecom_id, &member.stores_address);
+        }
+        self.metrics
+            .inc(Counter::ConnectionsUp, &[("peer", "ecom")]);
+        self.active_ecoms.insert(
+            new_ecom_id,
+            EcomConnection {
+                addr: abstract_ecom.clone(),
+                preferred,
+            },
+        );
+
+        if let Some(leader) = self.curr_leader {
+            if new_ecom_id > leader {
+                info!(leader = new_ecom_id, "Cambio de lider");
+                self.change_leader(new_ecom_id);
+            }
+        }
+        Some(abstract_ecom)
+    }
+
+    /// The members of the cluster sorted by id, along with the current leader.
+    fn members_snapshot(&self) -> Members {
+        let mut members: Vec<(usize, Member)> = self
+            .members
+            .iter()
+            .map(|(id, member)| (*id, member.clone()))
+            .collect();
+        members.sort_unstable();
+        Members {
+            leader: self.curr_leader.unwrap_or(self.id),
+            members,
+        }
+    }
+
+    /// Tells every connected store that a new ecommerce joined, so they connect to it.
+    fn announce_to_stores(&self, ecom_id: usize, stores_address: &str) {
+        for addr in self.active_stores.values() {
+            let _ = addr.try_send(AnnounceStoreMember {
+                ecom_id,
+                stores_address: stores_address.to_string(),
+            });
+        }
     }
 }
 
@@ -42,18 +352,19 @@ impl Actor for Coordinator {
 /// to communicate with it. It creates a new _AbstractStore_ actor and stores it in the active_stores hashmap.
 pub struct NewStore {
     pub store_id: String,
-    pub stream: TcpStream,
+    pub stream: Connection,
 }
 
 impl Handler<NewStore> for Coordinator {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: NewStore, coord_ctx: &mut Self::Context) -> Result<(), Errors> {
-        println!(
-            "[COORDINATOR] Se recibio una nueva store con id [{}]",
-            msg.store_id
-        );
+        info!(store_id = %msg.store_id, "Se conecto una store");
 
+        let detached = self.detached_stores.remove(&msg.store_id);
+        let resumed = detached.is_some();
+        let detached = detached.unwrap_or_default();
+        let metrics = self.metrics.clone();
         let store_addr = AbstractStore::create(|ctx| {
             let (read, write_half) = split(msg.stream);
             AbstractStore::add_stream(LinesStream::new(BufReader::new(read).lines()), ctx);
@@ -61,9 +372,15 @@ impl Handler<NewStore> for Coordinator {
             AbstractStore {
                 write,
                 store_id: msg.store_id.clone(),
-                stock: HashMap::new(),
+                stock: detached.stock,
                 orders_buffer: vec![],
                 coordinator: coord_ctx.address(),
+                resumed,
+                last_seq: detached.last_seq,
+                session: detached.session,
+                metrics,
+                left: false,
+                draining: false,
             }
         });
 
@@ -72,10 +389,12 @@ impl Handler<NewStore> for Coordinator {
                 leader_id: leader_id.to_string(),
             });
         } else {
-            println!("[COORDINATOR] No hay lider para avisarle a la nueva AbstractStore");
+            warn!(store_id = %msg.store_id, "No hay lider para avisarle a la nueva store");
             return Err(Errors::NoActiveLeader);
         }
 
+        self.metrics
+            .inc(Counter::ConnectionsUp, &[("peer", "store")]);
         let cloned_id = msg.store_id.clone();
         self.active_stores.insert(cloned_id, store_addr);
         Ok(())
@@ -84,49 +403,69 @@ impl Handler<NewStore> for Coordinator {
 
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
-/// Message to notify the coordinator that a new order has arrived. It contains the order and the stores that have already been visited.
-/// It redirects the order to a store that has the product in stock. If no store has the product in stock, it returns an error.
+/// Message to notify the coordinator that a new order has arrived. It contains the order, the stores that have already been visited,
+/// the trace of the order, if it already has one, and the ecommerce that took it, if it was another one. It redirects the order to
+/// a store that has the product in stock. If the SKU of the product is not in the catalog, it returns an error.
 pub struct NewOrder {
     pub order: String,
     pub visited_stores: Vec<String>,
+    pub trace_id: Option<TraceId>,
+    pub origin: Option<usize>,
 }
 
 impl Handler<NewOrder> for Coordinator {
     type Result = Result<(), Errors>;
 
-    fn handle(&mut self, msg: NewOrder, ctx: &mut Self::Context) -> Result<(), Errors> {
+    fn handle(&mut self, mut msg: NewOrder, ctx: &mut Self::Context) -> Result<(), Errors> {
+        // Orders that arrive without a trace start a new one here
+        let trace_id = *msg
+            .trace_id
+            .get_or_insert_with(|| TraceId::from_rng(&mut self.traces));
+        let _span = order_span(trace_id, &msg.order).entered();
+        // Orders are counted only by the ecommerce that took them. The ones that already visited a store are being
+        // routed again, and the ones with an origin were forwarded by another ecommerce
+        if msg.visited_stores.is_empty() && msg.origin.is_none() {
+            self.metrics.inc(Counter::OrdersReceived, &[]);
+        }
+        // Products that are not in the catalog are rejected before bothering any store
+        let sku = msg.order.split(',').next().unwrap_or_default();
+        if !self.catalog.contains(sku) {
+            warn!("Pedido rechazado, el SKU no esta en el catalogo");
+            self.sales.record_lost(LossReason::UnknownProduct);
+            return Err(Errors::UnknownProductError);
+        }
+
         if self.active_stores.is_empty() {
-            println!("[COORDINATOR] No hay tiendas conectadas");
+            warn!("No hay tiendas conectadas");
             return Err(Errors::NoActiveStoresError);
         } else if self.curr_leader.is_none() {
-            println!("[COORDINATOR] No hay lider al que mandar los pedidos");
+            warn!("No hay lider al que mandar los pedidos");
             return Err(Errors::NoActiveLeader);
         }
 
         if let Some(id) = self.curr_leader {
             if self.id != id {
-                if let Some(ecom_addr) = self.active_ecoms.get_mut(&id) {
-                    let _ = ecom_addr.try_send(SendOrder {
+                if let Some(ecom) = self.active_ecoms.get(&id) {
+                    let sent = ecom.addr.try_send(SendOrder {
                         order: msg.order.clone(),
+                        trace_id,
+                        origin: msg.origin.unwrap_or(self.id),
                     });
+                    debug!(leader = id, "Pedido enviado al lider");
+                    self.metrics.sent(sent, "abstract_ecom");
                     return Ok(());
                 }
             }
         }
 
         if msg.visited_stores.len() >= self.active_stores.len() {
-            println!(
-                "[COORDINATOR] No hay tiendas con stock para el pedido [{}]",
-                msg.order
-            );
-            return Ok(());
+            info!("No hay tiendas con stock para el pedido");
+            return self.add_backorder(msg.order, trace_id, msg.origin);
         } else {
-            match self
-                .active_stores
-                .keys()
-                .collect::<Vec<&String>>()
-                .choose(&mut self.rng)
-            {
+            // Sorted, so the store drawn only depends on the seed and not on the order of the map
+            let mut store_ids: Vec<&String> = self.active_stores.keys().collect();
+            store_ids.sort();
+            match store_ids.choose(&mut self.rng) {
                 Some(id) => {
                     if msg.visited_stores.contains(id) {
                         ctx.notify(msg);
@@ -134,24 +473,20 @@ impl Handler<NewOrder> for Coordinator {
                     }
                     let store_addr = self.active_stores.get(*id);
                     if let Some(addr) = store_addr {
-                        let order_msg = format!(
-                            "{},{}",
-                            msg.order,
-                            self.rng.gen_range(MIN_SECS_LIMIT, MAX_SECS_LIMIT)
-                        );
-                        if addr
-                            .try_send(Order {
-                                order: order_msg.clone(),
-                                visited_stores: msg.visited_stores,
-                            })
-                            .is_err()
-                        {
-                            eprintln!("[COORDINATOR] Error al enviar mensaje Order con la orden [{}] al AbstractStore con id [{}]", order_msg, id);
+                        let order_msg = order_with_time_limit(&mut self.rng, &msg.order);
+                        let sent = addr.try_send(Order {
+                            order: order_msg,
+                            visited_stores: msg.visited_stores,
+                            trace_id,
+                            origin: msg.origin,
+                        });
+                        if !self.metrics.sent(sent, "abstract_store") {
+                            error!(store_id = %id, "No se pudo enviar el pedido a la store");
                         }
                     }
                 }
                 None => {
-                    println!("Error a la hora de conseguir un store id");
+                    error!("No se pudo elegir una store");
                     return Err(Errors::NotEnoughStockError);
                 }
             };
@@ -162,30 +497,53 @@ impl Handler<NewOrder> for Coordinator {
 
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
-/// Message to notify the coordinator that a store has disconnected. It contains the store id.
+/// Message to notify the coordinator that a store has disconnected. It contains the store id, the last stock known of it
+/// and the session and number of the last notification applied to that stock. Only the leader is told about the changes of the stock,
+/// so only the leader keeps it for when the store reconnects. A connection that was already replaced by another one is ignored.
 pub struct StoreDisconnected {
     pub store_id: String,
+    pub stock: HashMap<String, usize>,
+    pub last_seq: u64,
+    pub session: u64,
+    pub addr: Addr<AbstractStore>,
 }
 
 impl Handler<StoreDisconnected> for Coordinator {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: StoreDisconnected, _: &mut Self::Context) -> Self::Result {
-        let a = self.active_stores.remove(&msg.store_id);
-        if a.is_none() {
+        let is_current = self
+            .active_stores
+            .get(&msg.store_id)
+            .is_some_and(|current| *current == msg.addr);
+        if !is_current {
             return Err(Errors::StoreNotConnectedError);
         }
+        self.active_stores.remove(&msg.store_id);
+        self.metrics
+            .inc(Counter::ConnectionsDown, &[("peer", "store")]);
+        if self.curr_leader == Some(self.id) && !msg.stock.is_empty() {
+            let detached = DetachedStore {
+                stock: msg.stock,
+                last_seq: msg.last_seq,
+                session: msg.session,
+            };
+            self.detached_stores.insert(msg.store_id, detached);
+        }
         Ok(())
     }
 }
 
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
-/// Message to notify the coordinator that a new ecommerce has connected. It contains the ecommerce id and the stream to communicate with it.
-/// It creates a new AbstractEcom.
+/// Message to notify the coordinator that a new ecommerce has connected. It contains the ecommerce id and the stream to communicate with it,
+/// and whether this ecommerce dialed it. It creates a new AbstractEcom. If the connection was dialed by this ecommerce,
+/// `closed` is fired when the other side closes it, and dropped if the connection is closed because another one was kept.
 pub struct NewEcom {
     pub id: String,
-    pub stream: TcpStream,
+    pub stream: Connection,
+    pub dialed: bool,
+    pub closed: Option<oneshot::Sender<()>>,
 }
 
 impl Handler<NewEcom> for Coordinator {
@@ -194,62 +552,39 @@ impl Handler<NewEcom> for Coordinator {
     fn handle(&mut self, msg: NewEcom, coord_ctx: &mut Self::Context) -> Result<(), Errors> {
         let new_ecom_id =
             <usize as FromStr>::from_str(&msg.id).map_err(|_| Errors::CouldNotParse)?;
-
-        let abstract_ecom = AbstractEcom::create(|ctx| {
-            let (read, write_half) = split(msg.stream);
-            AbstractEcom::add_stream(LinesStream::new(BufReader::new(read).lines()), ctx);
-            let write = Some(write_half);
-            AbstractEcom {
-                write,
-                id: new_ecom_id,
-                coord: coord_ctx.address(),
-            }
-        });
-
-        if let Some(leader) = self.curr_leader {
-            if new_ecom_id > leader {
-                println!("[COORDINATOR] Cambio de lider a [{new_ecom_id}]");
-                self.curr_leader = Some(new_ecom_id);
-
-                // All abstract stores need to know that a new leader has been designated
-                for addr in self.active_stores.values() {
-                    let _ = addr.try_send(NewLeader {
-                        leader_id: new_ecom_id.to_string(),
-                    });
-                }
-            }
-        }
-
-        self.active_ecoms.insert(new_ecom_id, abstract_ecom);
-
+        let dialer = if msg.dialed { self.id } else { new_ecom_id };
+        self.add_ecom(new_ecom_id, msg.stream, dialer, msg.closed, coord_ctx);
         Ok(())
     }
 }
 
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
-/// Message to notify the coordinator that an ecom has disconnected. It contains the ecom id.
-/// It removes the coresponding ecom from his connected ones.
+/// Message to notify the coordinator that an ecom has disconnected. It contains the ecom id and the AbstractEcom of the connection.
+/// It removes the coresponding ecom from his connected ones. A connection that was already replaced by another one is ignored.
 pub struct EcomDisconnected {
     pub ecom_id: usize,
+    pub addr: Addr<AbstractEcom>,
 }
 
 impl Handler<EcomDisconnected> for Coordinator {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: EcomDisconnected, ctx: &mut Self::Context) -> Self::Result {
-        let a = self.active_ecoms.remove(&msg.ecom_id);
-        if a.is_none() {
-            return Err(Errors::StoreNotConnectedError);
+        match self.active_ecoms.get(&msg.ecom_id) {
+            None => return Err(Errors::StoreNotConnectedError),
+            Some(current) if current.addr != msg.addr => return Ok(()),
+            Some(_) => {
+                self.active_ecoms.remove(&msg.ecom_id);
+                self.metrics
+                    .inc(Counter::ConnectionsDown, &[("peer", "ecom")]);
+            }
         }
 
         if let Some(curr) = self.curr_leader {
             if curr == msg.ecom_id {
                 // Se busca un nuevo lider
-                ctx.notify(CoordElection {
-                    visited: vec![],
-                    ecom_id: self.id + 1,
-                });
+                ctx.notify(CoordElection { visited: vec![] });
 
                 self.curr_leader = None;
             }
@@ -260,12 +595,159 @@ impl Handler<EcomDisconnected> for Coordinator {
     }
 }
 
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// Message to notify the coordinator that the other ecommerce closed a connection because it kept another one,
+/// or because it is shutting down after handing over the leadership. Either way, unlike _EcomDisconnected_ no election is held.
+pub struct EcomConnectionClosed {
+    pub ecom_id: usize,
+    pub addr: Addr<AbstractEcom>,
+}
+
+impl Handler<EcomConnectionClosed> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: EcomConnectionClosed, _: &mut Self::Context) -> Self::Result {
+        let is_current = self
+            .active_ecoms
+            .get(&msg.ecom_id)
+            .is_some_and(|current| current.addr == msg.addr);
+        if is_current {
+            self.active_ecoms.remove(&msg.ecom_id);
+            self.metrics
+                .inc(Counter::ConnectionsDown, &[("peer", "ecom")]);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// Message sent when an ecommerce that is not connected yet joins the cluster through this one. It contains the id
+/// and the addresses of the new ecommerce, and the stream to communicate with it. The new ecommerce is answered with
+/// the members and the leader of the cluster, and announced to every other ecommerce and store.
+pub struct Join {
+    pub id: usize,
+    pub ecoms_address: String,
+    pub stores_address: String,
+    pub stream: Connection,
+}
+
+impl Handler<Join> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: Join, ctx: &mut Self::Context) -> Result<(), Errors> {
+        info!(
+            ecom_id = msg.id,
+            address = %msg.ecoms_address,
+            "Un ecommerce se une al cluster"
+        );
+        self.members.insert(
+            msg.id,
+            Member {
+                ecoms_address: msg.ecoms_address.clone(),
+                stores_address: msg.stores_address.clone(),
+            },
+        );
+        let joined = match self.add_ecom(msg.id, msg.stream, msg.id, None, ctx) {
+            Some(joined) => joined,
+            None => return Ok(()),
+        };
+        let _ = joined.try_send(SendMembers {
+            sender_id: self.id,
+            members: self.members_snapshot(),
+        });
+
+        for (id, ecom) in &self.active_ecoms {
+            if *id != msg.id {
+                let _ = ecom.addr.try_send(AnnounceEcomMember {
+                    id: msg.id,
+                    ecoms_address: msg.ecoms_address.clone(),
+                    stores_address: msg.stores_address.clone(),
+                });
+            }
+        }
+        self.announce_to_stores(msg.id, &msg.stores_address);
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// Message sent by an AbstractEcom when another ecommerce announces that a new one joined the cluster.
+/// The new ecommerce is added to the members and the connected stores are told about it.
+pub struct MemberAnnounced {
+    pub id: usize,
+    pub ecoms_address: String,
+    pub stores_address: String,
+}
+
+impl Handler<MemberAnnounced> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: MemberAnnounced, _: &mut Self::Context) -> Result<(), Errors> {
+        if msg.id == self.id {
+            return Ok(());
+        }
+        info!(ecom_id = msg.id, "Nuevo miembro del cluster");
+        self.announce_to_stores(msg.id, &msg.stores_address);
+        self.members.insert(
+            msg.id,
+            Member {
+                ecoms_address: msg.ecoms_address,
+                stores_address: msg.stores_address,
+            },
+        );
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// Message with the members and the leader of the cluster, received by an ecommerce after joining it.
+/// It connects to every member it is not connected to yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Members {
+    pub leader: usize,
+    pub members: Vec<(usize, Member)>,
+}
+
+impl Handler<Members> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: Members, ctx: &mut Self::Context) -> Result<(), Errors> {
+        for (id, member) in msg.members {
+            if id == self.id {
+                continue;
+            }
+            if !self.active_ecoms.contains_key(&id) && !member.ecoms_address.is_empty() {
+                actix::spawn(connect_to_ecom(
+                    member.ecoms_address.clone(),
+                    id.to_string(),
+                    ctx.address(),
+                    self.id.to_string(),
+                    self.reconnect,
+                ));
+            }
+            self.members.insert(id, member);
+        }
+
+        let leader = msg.leader.max(self.id);
+        if self.curr_leader != Some(leader) {
+            ctx.notify(ChangeLeader {
+                new_leader_id: leader,
+            });
+        }
+        Ok(())
+    }
+}
+
 /// Message that takes part in the election of a new ecom leader.
 /// It starts the process to notify the next ecom in the ring that an election is being held.
+/// The next ecom is looked for among the configured members that are connected.
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
 pub struct CoordElection {
-    pub ecom_id: usize,
     pub visited: Vec<usize>,
 }
 
@@ -276,28 +758,38 @@ impl Handler<CoordElection> for Coordinator {
         if msg.visited.contains(&self.id) {
             if let Some(max_id) = msg.visited.iter().max() {
                 for ecom in self.active_ecoms.values() {
-                    let _ = ecom.try_send(NewLeader2 {
+                    let _ = ecom.addr.try_send(NewLeader2 {
                         new_leader_id: *max_id,
                     });
                 }
+                ctx.notify(ChangeLeader {
+                    new_leader_id: *max_id,
+                });
             }
         } else {
+            if msg.visited.is_empty() && self.election_started.is_none() {
+                self.metrics.inc(Counter::ElectionsStarted, &[]);
+                self.election_started = Some(self.clock.now());
+            }
             let mut new_vec = msg.visited.clone();
             new_vec.push(self.id);
-            match self.active_ecoms.get(&msg.ecom_id) {
-                Some(addr) => {
-                    let _ = addr.try_send(Election { visited: new_vec });
+            let connected: Vec<usize> = self
+                .members
+                .keys()
+                .copied()
+                .filter(|id| self.active_ecoms.contains_key(id))
+                .collect();
+            let successor = ring_successor(self.id, &connected)
+                .and_then(|next_id| self.active_ecoms.get(&next_id));
+            match successor {
+                Some(ecom) => {
+                    let _ = ecom.addr.try_send(Election { visited: new_vec });
                 }
                 None => {
-                    if let Some(min_ecom_id) = self.active_ecoms.keys().min() {
-                        if let Some(min_ecom_addr) = self.active_ecoms.get(min_ecom_id) {
-                            let _ = min_ecom_addr.try_send(Election { visited: new_vec });
-                        }
-                    } else {
-                        ctx.notify(ChangeLeader {
-                            new_leader_id: self.id,
-                        });
-                    }
+                    // There is no other ecom to ask, so this one is the leader
+                    ctx.notify(ChangeLeader {
+                        new_leader_id: self.id,
+                    });
                 }
             }
         }
@@ -317,14 +809,75 @@ impl Handler<ChangeLeader> for Coordinator {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: ChangeLeader, _: &mut Self::Context) -> Self::Result {
-        self.curr_leader = Some(msg.new_leader_id);
-        for i in self.active_stores.keys() {
-            if let Some(store_addr) = self.active_stores.get(i) {
-                let _ = store_addr.try_send(NewLeader {
-                    leader_id: msg.new_leader_id.to_string(),
-                });
+        if let Some(started) = self.election_started.take() {
+            self.metrics.inc(Counter::ElectionsCompleted, &[]);
+            self.metrics.observe(
+                Histogram::ElectionSeconds,
+                &[],
+                self.clock
+                    .now()
+                    .saturating_duration_since(started)
+                    .as_secs_f64(),
+            );
+        }
+        self.change_leader(msg.new_leader_id);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// What the ecommerce leaves pending when it shuts down: the orders waiting for stock, and the stores that were
+/// disconnected from it while it was the leader.
+pub struct EcomPendingWork {
+    pub backorders: Vec<String>,
+    pub detached_stores: usize,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<EcomPendingWork, Errors>")]
+/// Message sent when the ecommerce is shutting down. If it is the leader it _abdicates_: the connected ecommerce with the
+/// biggest id, the one an election would choose, becomes the leader and every ecommerce and store is told so.
+/// Then the connections with the other ecommerces are closed with _BYE_, so they do not hold an election.
+pub struct ShutDown;
+
+impl Handler<ShutDown> for Coordinator {
+    type Result = Result<EcomPendingWork, Errors>;
+
+    fn handle(&mut self, _: ShutDown, _: &mut Self::Context) -> Self::Result {
+        if self.curr_leader == Some(self.id) {
+            if let Some(successor) = self.active_ecoms.keys().max().copied() {
+                info!(successor, "Se cede el liderazgo");
+                for ecom in self.active_ecoms.values() {
+                    let _ = ecom.addr.try_send(NewLeader2 {
+                        new_leader_id: successor,
+                    });
+                }
+                self.change_leader(successor);
             }
         }
+        for (_, ecom) in self.active_ecoms.drain() {
+            let _ = ecom.addr.try_send(Leave);
+        }
+
+        let backorders = self
+            .backorders
+            .values()
+            .flat_map(|queue| queue.iter().map(|backorder| backorder.order.clone()))
+            .collect();
+        Ok(EcomPendingWork {
+            backorders,
+            detached_stores: self.detached_stores.len(),
+        })
+    }
+}
+
+/// Stops the coordinator right away, as if its process was killed: unlike _ShutDown_, it does not abdicate
+/// nor close its connections.
+impl Handler<Stop> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: Stop, ctx: &mut Self::Context) -> Result<(), Errors> {
+        ctx.stop();
         Ok(())
     }
 }
@@ -351,6 +904,385 @@ impl Handler<GetLeader> for Coordinator {
     }
 }
 
+/// Message sent by an AbstractStore when its store reports new units of a product, be it a stock dump,
+/// a restock or a cancelled reservation. It contains the quantity of the product the store now has.
+/// Backorders of that product are routed to the store, oldest first, while the quantity covers them.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct StockReplenished {
+    pub store_id: String,
+    pub product: String,
+    pub quantity: usize,
+}
+
+impl Handler<StockReplenished> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: StockReplenished, ctx: &mut Self::Context) -> Result<(), Errors> {
+        self.expire_backorders(ctx);
+
+        let store_addr = match self.active_stores.get(&msg.store_id) {
+            Some(addr) => addr.clone(),
+            None => return Err(Errors::StoreNotConnectedError),
+        };
+        let mut queue = match self.backorders.remove(&msg.product) {
+            Some(queue) => queue,
+            None => return Ok(()),
+        };
+
+        let mut remaining = msg.quantity;
+        while let Some(backorder) = queue.front() {
+            if backorder.quantity > remaining {
+                break;
+            }
+            if let Some(backorder) = queue.pop_front() {
+                remaining -= backorder.quantity;
+                let order_msg = order_with_time_limit(&mut self.rng, &backorder.order);
+                let _span = order_span(backorder.trace_id, &backorder.order).entered();
+                info!(store_id = %msg.store_id, "Backorder redirigido a la store");
+                if store_addr
+                    .try_send(Order {
+                        order: order_msg,
+                        visited_stores: vec![],
+                        trace_id: backorder.trace_id,
+                        origin: backorder.origin,
+                    })
+                    .is_err()
+                {
+                    error!(store_id = %msg.store_id, "No se pudo redirigir el backorder a la store");
+                }
+            }
+        }
+
+        if !queue.is_empty() {
+            self.backorders.insert(msg.product, queue);
+        }
+        Ok(())
+    }
+}
+
+/// Message that drops the backorders that waited longer than the configured maximum.
+/// The coordinator sends it to itself every `BACKORDER_CHECK_INTERVAL` of its clock.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct ExpireBackorders;
+
+impl Handler<ExpireBackorders> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: ExpireBackorders, ctx: &mut Self::Context) -> Result<(), Errors> {
+        self.expire_backorders(ctx);
+        Ok(())
+    }
+}
+
+/// Message that tells the ecommerce that took an order that it expired as a backorder of the leader, so it is
+/// counted as lost in its sales report.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct OrderExpired {
+    pub order: String,
+    pub trace_id: Option<TraceId>,
+}
+
+impl Handler<OrderExpired> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: OrderExpired, _: &mut Self::Context) -> Result<(), Errors> {
+        let _span = msg
+            .trace_id
+            .map(|trace_id| order_span(trace_id, &msg.order).entered());
+        warn!("Pedido perdido, expiro esperando stock");
+        self.sales.record_lost(LossReason::BackorderExpired);
+        Ok(())
+    }
+}
+
+/// Message sent by an AbstractStore when its store approves or cancels orders. It is a single order, unless the store
+/// replays its offline log, which adds up `orders` of them. Approved sales are added to the sales report valued at the
+/// catalog price of the product.
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+pub struct OrderOutcome {
+    pub store_id: String,
+    pub product: String,
+    pub quantity: usize,
+    pub orders: u64,
+    pub channel: Channel,
+    pub approved: bool,
+}
+
+impl Handler<OrderOutcome> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: OrderOutcome, _: &mut Self::Context) -> Result<(), Errors> {
+        if msg.approved {
+            self.metrics.inc(
+                Counter::OrdersApproved,
+                &[("channel", msg.channel.as_str())],
+            );
+            let price_cents = self.catalog.check(&msg.product)?.price_cents;
+            self.sales.record_sale(
+                &msg.store_id,
+                msg.channel,
+                &msg.product,
+                msg.quantity,
+                msg.orders,
+                price_cents,
+            );
+        } else {
+            self.metrics.inc(Counter::OrdersCancelled, &[]);
+            self.sales
+                .record_cancellation(&msg.store_id, &msg.product, msg.quantity, msg.orders);
+        }
+        Ok(())
+    }
+}
+
+// ------------------------ ADMIN CONSOLE MESSAGES ------------------------
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message is used to _show the state_ of the ecommerce in the console: the leader and the connected stores and ecommerces.
+pub struct ShowState;
+
+impl Handler<ShowState> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: ShowState, _: &mut Self::Context) -> Result<(), Errors> {
+        match self.curr_leader {
+            Some(leader) => println!("Lider: [{leader}]"),
+            None => println!("No hay lider, hay una eleccion en curso"),
+        }
+        let mut stores: Vec<&String> = self.active_stores.keys().collect();
+        stores.sort();
+        println!("Stores conectadas: {:?}", stores);
+        let mut ecoms: Vec<&usize> = self.active_ecoms.keys().collect();
+        ecoms.sort();
+        println!("Ecommerce conectados: {:?}", ecoms);
+        if self.intake_paused {
+            println!("La toma de pedidos esta pausada");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// Asks every connected AbstractStore to print the stock of its store as this ecommerce knows it.
+pub struct ShowStoresStock;
+
+impl Handler<ShowStoresStock> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: ShowStoresStock, _: &mut Self::Context) -> Result<(), Errors> {
+        if self.active_stores.is_empty() {
+            println!("No hay tiendas conectadas");
+        }
+        for store in self.active_stores.values() {
+            let _ = store.try_send(ShowStock);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// Pauses or resumes the intake of orders. While it is paused, _IntakeOrder_ is rejected, but the orders
+/// that other ecommerces redirect to this one are still handled.
+pub struct SetIntakePaused {
+    pub paused: bool,
+}
+
+impl Handler<SetIntakePaused> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: SetIntakePaused, _: &mut Self::Context) -> Result<(), Errors> {
+        self.intake_paused = msg.paused;
+        if msg.paused {
+            info!("Se pausa la toma de pedidos");
+        } else {
+            info!("Se reanuda la toma de pedidos");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// A new order from the clients of the ecommerce, with the trace minted for it. It is handled as _NewOrder_, unless the intake is paused.
+pub struct IntakeOrder {
+    pub order: String,
+    pub trace_id: TraceId,
+}
+
+impl Handler<IntakeOrder> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: IntakeOrder, ctx: &mut Self::Context) -> Result<(), Errors> {
+        if self.intake_paused {
+            return Err(Errors::IntakePausedError);
+        }
+        let order = NewOrder {
+            order: msg.order,
+            visited_stores: vec![],
+            trace_id: Some(msg.trace_id),
+            origin: None,
+        };
+        <Self as Handler<NewOrder>>::handle(self, order, ctx)
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// Closes the connection with a store. The store reconnects on its own, following its reconnection policy.
+/// Returns an error if the store is not connected.
+pub struct DisconnectStore {
+    pub store_id: String,
+}
+
+impl Handler<DisconnectStore> for Coordinator {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: DisconnectStore, _: &mut Self::Context) -> Result<(), Errors> {
+        let store = self
+            .active_stores
+            .get(&msg.store_id)
+            .ok_or(Errors::StoreNotConnectedError)?;
+        info!(store_id = %msg.store_id, "Se desconecta la store");
+        let _ = store.try_send(Disconnect);
+        Ok(())
+    }
+}
+
+pub struct GetSalesReport;
+
+/// Returns the running sales report of the ecommerce
+impl Message for GetSalesReport {
+    type Result = Result<SalesReport, Errors>;
+}
+
+impl Handler<GetSalesReport> for Coordinator {
+    type Result = Result<SalesReport, Errors>;
+
+    fn handle(&mut self, _: GetSalesReport, _: &mut Self::Context) -> Result<SalesReport, Errors> {
+        Ok(self.sales.clone())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+/// The state of the ecommerce: its id, the leader if there is one, the connected stores and ecommerces
+/// and whether the intake of orders is paused.
+pub struct EcomState {
+    pub id: usize,
+    pub leader: Option<usize>,
+    pub stores: Vec<String>,
+    pub ecoms: Vec<usize>,
+    pub intake_paused: bool,
+}
+
+/// Message that answers with the state of the ecommerce.
+#[derive(Message)]
+#[rtype(result = "Result<EcomState, Errors>")]
+pub struct GetState;
+
+impl Handler<GetState> for Coordinator {
+    type Result = Result<EcomState, Errors>;
+
+    fn handle(&mut self, _: GetState, _: &mut Self::Context) -> Result<EcomState, Errors> {
+        let mut stores: Vec<String> = self.active_stores.keys().cloned().collect();
+        stores.sort();
+        let mut ecoms: Vec<usize> = self.active_ecoms.keys().copied().collect();
+        ecoms.sort_unstable();
+        Ok(EcomState {
+            id: self.id,
+            leader: self.curr_leader,
+            stores,
+            ecoms,
+            intake_paused: self.intake_paused,
+        })
+    }
+}
+
+/// Message that answers with the stock of every connected store as this ecommerce knows it, by store.
+#[derive(Message)]
+#[rtype(result = "Result<BTreeMap<String, BTreeMap<String, usize>>, Errors>")]
+pub struct GetStoresStock;
+
+impl Handler<GetStoresStock> for Coordinator {
+    type Result = ResponseFuture<Result<BTreeMap<String, BTreeMap<String, usize>>, Errors>>;
+
+    fn handle(&mut self, _: GetStoresStock, _: &mut Self::Context) -> Self::Result {
+        let stores = self.active_stores.clone();
+        Box::pin(async move {
+            let mut stock = BTreeMap::new();
+            for (store_id, addr) in stores {
+                // A store that disconnects meanwhile is left out
+                if let Ok(Ok(products)) = addr.send(GetStock).await {
+                    stock.insert(store_id, products.into_iter().collect());
+                }
+            }
+            Ok(stock)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+/// The orders of the ecommerce: the ones waiting for stock, and how many online orders the stores approved
+/// and cancelled and how many were lost before reaching a store.
+pub struct OrderStatus {
+    pub waiting: Vec<String>,
+    pub approved: u64,
+    pub cancelled: u64,
+    pub lost: u64,
+}
+
+/// Message that answers with the status of the orders of the ecommerce.
+#[derive(Message)]
+#[rtype(result = "Result<OrderStatus, Errors>")]
+pub struct GetOrderStatus;
+
+impl Handler<GetOrderStatus> for Coordinator {
+    type Result = Result<OrderStatus, Errors>;
+
+    fn handle(&mut self, _: GetOrderStatus, _: &mut Self::Context) -> Result<OrderStatus, Errors> {
+        let online = self
+            .sales
+            .totals_by_channel()
+            .get(&Channel::Online)
+            .copied()
+            .unwrap_or_default();
+        let mut waiting: Vec<String> = self
+            .backorders
+            .values()
+            .flatten()
+            .map(|backorder| backorder.order.clone())
+            .collect();
+        waiting.sort();
+        Ok(OrderStatus {
+            waiting,
+            approved: online.orders_approved,
+            cancelled: online.orders_cancelled,
+            lost: self.sales.lost_orders(LossReason::UnknownProduct)
+                + self.sales.lost_orders(LossReason::BackorderExpired),
+        })
+    }
+}
+
+/// Message that answers with the members and the leader of the cluster, used to tell a store that is
+/// discovering the cluster which ecommerces it has to connect to.
+#[derive(Message)]
+#[rtype(result = "Result<Members, Errors>")]
+pub struct GetMembers;
+
+impl Handler<GetMembers> for Coordinator {
+    type Result = Result<Members, Errors>;
+
+    fn handle(&mut self, _: GetMembers, _: &mut Self::Context) -> Result<Members, Errors> {
+        Ok(self.members_snapshot())
+    }
+}
+
 // ------------------------ TEST PURPOSE MESSAGES ------------------------ //
 pub struct _GetActiveStores;
 
@@ -369,3 +1301,53 @@ impl Handler<_GetActiveStores> for Coordinator {
         Ok(self.active_stores.clone())
     }
 }
+
+pub struct _GetMembers;
+
+impl Message for _GetMembers {
+    type Result = Result<HashMap<usize, Member>, String>;
+}
+
+impl Handler<_GetMembers> for Coordinator {
+    type Result = Result<HashMap<usize, Member>, String>;
+
+    fn handle(
+        &mut self,
+        _: _GetMembers,
+        _: &mut Self::Context,
+    ) -> Result<HashMap<usize, Member>, String> {
+        Ok(self.members.clone())
+    }
+}
+
+pub struct _GetLeader;
+
+impl Message for _GetLeader {
+    type Result = Result<Option<usize>, String>;
+}
+
+impl Handler<_GetLeader> for Coordinator {
+    type Result = Result<Option<usize>, String>;
+
+    fn handle(&mut self, _: _GetLeader, _: &mut Self::Context) -> Result<Option<usize>, String> {
+        Ok(self.curr_leader)
+    }
+}
+
+pub struct _GetBackorders;
+
+impl Message for _GetBackorders {
+    type Result = Result<HashMap<String, VecDeque<Backorder>>, String>;
+}
+
+impl Handler<_GetBackorders> for Coordinator {
+    type Result = Result<HashMap<String, VecDeque<Backorder>>, String>;
+
+    fn handle(
+        &mut self,
+        _: _GetBackorders,
+        _: &mut Self::Context,
+    ) -> Result<HashMap<String, VecDeque<Backorder>>, String> {
+        Ok(self.backorders.clone())
+    }
+}
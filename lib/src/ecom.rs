@@ -1,59 +1,169 @@
 use std::str::FromStr;
 
 use crate::{
-    coordinator::{Coordinator, NewEcom},
+    coordinator::{Coordinator, GetMembers, Join, Member, Members, NewEcom, NewStore},
     errors::Errors,
+    logging::connection_span,
+    reconnect::{connect_with_backoff, ReconnectPolicy},
+    transport::{connect, Listener},
 };
 use actix::Addr;
-use tokio::io::BufReader;
+use tokio::sync::oneshot;
+use tokio::time::sleep;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    time::Duration,
 };
-use tokio_stream::{wrappers::LinesStream, StreamExt};
+use tracing::{debug, info, warn, Instrument};
+
+const JOIN_MSG: &str = "JOIN";
+const MEMBERS_MSG: &str = "MEMBERS";
+/// Sent by a store instead of its id to ask an ecommerce for the members of the cluster.
+pub const DISCOVER_MSG: &str = "DISCOVER";
+/// Sent before closing a connection with an ecommerce that is already connected through another one.
+pub const CLOSE_MSG: &str = "CLOSE\n";
+/// Sent before closing the connections with the other ecommerces when shutting down.
+pub const BYE_MSG: &str = "BYE\n";
 
 /// Function to spawn a task for each ecommerce connection attempt. It receives a vector of tuples containing the ip and id of each ecommerce.
 /// It also receives the coordinator address, the address of the current node of the network and its id.
+/// The configured ecommerces are retried forever, so an ecommerce started before its peers connects to them once they are up.
 pub async fn ecom_network(
     ips_ecoms: Vec<(String, String)>,
     coord: Addr<Coordinator>,
     my_id: String,
+    policy: ReconnectPolicy,
 ) -> Result<(), Errors> {
     for (ip, id) in ips_ecoms {
-        let coord_clone = coord.clone();
-        let my_id_clone = my_id.clone();
-
         // Spawn a task for each connection attempt
-        tokio::spawn(async move {
-            let ecom_connection = TcpStream::connect(&ip).await;
-            if let Ok(mut stream) = ecom_connection {
-                let id_msg = format!("{}\n", my_id_clone);
-                let _ = stream.write(&id_msg.into_bytes()).await;
-                let _ = coord_clone.send(NewEcom { id, stream }).await;
-            }
-        });
+        tokio::spawn(connect_to_ecom(
+            ip,
+            id,
+            coord.clone(),
+            my_id.clone(),
+            policy.forever(),
+        ));
     }
     Ok(())
 }
 
+/// Connects to another ecommerce, introduces itself with its id and tells the coordinator to create the AbstractEcom.
+/// When the other ecommerce closes the connection it is dialed again, retrying with the backoff of `policy`
+/// until the retries run out. If the connection is closed because another one with the ecommerce was kept,
+/// it is not dialed again.
+pub async fn connect_to_ecom(
+    ip: String,
+    id: String,
+    coord: Addr<Coordinator>,
+    my_id: String,
+    policy: ReconnectPolicy,
+) {
+    let span = connection_span("ecom", &id);
+    dial_ecom(ip, id, coord, my_id, policy)
+        .instrument(span)
+        .await
+}
+
+async fn dial_ecom(
+    ip: String,
+    id: String,
+    coord: Addr<Coordinator>,
+    my_id: String,
+    policy: ReconnectPolicy,
+) {
+    let mut backoff = policy.backoff(&id);
+    loop {
+        let mut stream = match connect_with_backoff(&ip, &mut backoff).await {
+            Ok(stream) => stream,
+            Err(_) => {
+                warn!(address = %ip, "Se deja de intentar conectar con el ecommerce");
+                return;
+            }
+        };
+        let id_msg = format!("{}\n", my_id);
+        let (closed_sender, closed_receiver) = oneshot::channel();
+        if stream.write_all(id_msg.as_bytes()).await.is_ok() {
+            let new_ecom = NewEcom {
+                id: id.clone(),
+                stream,
+                dialed: true,
+                closed: Some(closed_sender),
+            };
+            if coord.send(new_ecom).await.is_err() {
+                // The coordinator is gone, there is nobody to connect for
+                return;
+            }
+            if !was_lost(closed_receiver).await {
+                return;
+            }
+        }
+        match backoff.next_delay() {
+            Some(delay) => sleep(delay).await,
+            None => return,
+        }
+    }
+}
+
+/// Waits until a connection dialed with an ecommerce is closed. Returns whether it has to be dialed again,
+/// which is not the case if it was closed because another connection with the ecommerce was kept.
+async fn was_lost(closed: oneshot::Receiver<()>) -> bool {
+    if closed.await.is_err() {
+        info!("Se mantiene otra conexion con el ecommerce");
+        return false;
+    }
+    warn!("Se perdio la conexion con el ecommerce, reconectando");
+    true
+}
+
+/// Dials the ecommerce again once the connection made with it in another way is lost.
+async fn redial_when_lost(
+    closed: oneshot::Receiver<()>,
+    ip: String,
+    id: String,
+    coord: Addr<Coordinator>,
+    my_id: String,
+    policy: ReconnectPolicy,
+) {
+    if was_lost(closed).await {
+        dial_ecom(ip, id, coord, my_id, policy).await
+    }
+}
+
 /// This function accepts connections from ecommerces and spawns a task for each one.
+/// The first line is the id of the ecommerce, or a JOIN message if the ecommerce is joining the cluster.
 pub async fn ecom_connection_listener(
     addr: String,
     coord: Addr<Coordinator>,
 ) -> Result<(), Errors> {
-    let listener = TcpListener::bind(&addr)
-        .await
-        .map_err(|_| Errors::ConnectionError)?;
+    let listener = Listener::bind(&addr).await?;
 
-    while let Ok(tuple) = listener.accept().await {
-        let mut lines = LinesStream::new(BufReader::new(tuple.0).lines());
-        let id = lines.next().await;
-        if let Some(Ok(ecom_id)) = id {
-            let stream = lines.into_inner().into_inner().into_inner();
+    while let Ok(mut stream) = listener.accept().await {
+        let first_line = match read_line(&mut stream).await {
+            Ok(line) => line,
+            Err(_) => continue,
+        };
+        let split: Vec<&str> = first_line.split(',').collect();
+        if split[0] == JOIN_MSG && split.len() == 4 {
+            match <usize as FromStr>::from_str(split[1]) {
+                Ok(id) => {
+                    let _ = coord
+                        .send(Join {
+                            id,
+                            ecoms_address: split[2].to_string(),
+                            stores_address: split[3].to_string(),
+                            stream,
+                        })
+                        .await;
+                }
+                Err(_) => warn!(line = %first_line, "Mensaje JOIN invalido"),
+            }
+        } else {
             let _ = coord
                 .send(NewEcom {
-                    id: ecom_id,
+                    id: first_line,
                     stream,
+                    dialed: false,
+                    closed: None,
                 })
                 .await;
         }
@@ -61,6 +171,178 @@ pub async fn ecom_connection_listener(
     Ok(())
 }
 
+/// This async function is responsible for discovering and connecting with new stores.
+/// With each connection the coordinator is told to create a new AbstractStore actor instance.
+/// A store that is discovering the cluster sends DISCOVER instead of its id, and is answered with the members.
+pub async fn discover_stores(
+    addr: String,
+    coord: Addr<Coordinator>,
+    my_id: usize,
+) -> Result<(), Errors> {
+    let listener = Listener::bind(&addr).await?;
+    while let Ok(mut stream) = listener.accept().await {
+        let timeout_duration = Duration::from_secs(10);
+        match tokio::time::timeout(timeout_duration, read_line(&mut stream)).await {
+            Ok(Ok(line)) if line == DISCOVER_MSG => {
+                if let Ok(Ok(members)) = coord.send(GetMembers).await {
+                    let answer = members_msg(my_id, &members);
+                    if stream.write_all(answer.as_bytes()).await.is_err() {
+                        warn!("No se pudo responder el pedido de miembros");
+                    }
+                }
+            }
+            Ok(Ok(store_id)) => {
+                // The coordinator is told to create a new AbstractStore
+                debug!(store_id, "Se recibio el id de una store");
+                let res = coord.try_send(NewStore { store_id, stream });
+                if res.is_err() {
+                    warn!("No se pudo mandar el mensaje para crear una nueva store");
+                }
+            }
+            Ok(Err(e)) => warn!(error = ?e, "No se pudo leer el id de la store"),
+            Err(_) => warn!("Tiempo de lectura del id de la store agotado"),
+        }
+    }
+    Ok(())
+}
+
+/// Joins a running cluster through the first seed that answers. The seed replies with the members and the leader
+/// of the cluster, and the coordinator then connects to every other member. The connection with the seed is kept
+/// as one dialed by this ecommerce, so when it is lost the seed is dialed again following `policy`.
+pub async fn join_cluster(
+    seeds: Vec<String>,
+    coord: Addr<Coordinator>,
+    my_id: usize,
+    ecoms_address: String,
+    stores_address: String,
+    policy: ReconnectPolicy,
+) -> Result<(), Errors> {
+    for seed in seeds {
+        let mut stream = match connect(&seed).await {
+            Ok(stream) => stream,
+            Err(_) => {
+                warn!(seed, "No se pudo conectar con la semilla");
+                continue;
+            }
+        };
+        let join_msg = format!("{JOIN_MSG},{my_id},{ecoms_address},{stores_address}\n");
+        if stream.write_all(join_msg.as_bytes()).await.is_err() {
+            continue;
+        }
+        let answer = match read_line(&mut stream).await {
+            Ok(answer) => answer,
+            Err(_) => continue,
+        };
+        let (seed_id, members) = match members_from_msg(&answer) {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                warn!(seed, answer, "Respuesta invalida de la semilla");
+                continue;
+            }
+        };
+        info!(seed_id, "Unido al cluster");
+        let (closed_sender, closed_receiver) = oneshot::channel();
+        let _ = coord
+            .send(NewEcom {
+                id: seed_id.to_string(),
+                stream,
+                dialed: true,
+                closed: Some(closed_sender),
+            })
+            .await;
+        let _ = coord.send(members).await;
+        let span = connection_span("ecom", &seed_id.to_string());
+        tokio::spawn(
+            redial_when_lost(
+                closed_receiver,
+                seed,
+                seed_id.to_string(),
+                coord,
+                my_id.to_string(),
+                policy,
+            )
+            .instrument(span),
+        );
+        return Ok(());
+    }
+    Err(Errors::ConnectionError)
+}
+
+/// Asks the ecommerce that accepts stores in `seed` for the members of the cluster, introducing itself with
+/// a DISCOVER message instead of the id of the store.
+pub async fn discover_members(seed: &str) -> Result<Members, Errors> {
+    let mut stream = connect(seed).await?;
+    stream
+        .write_all(format!("{DISCOVER_MSG}\n").as_bytes())
+        .await
+        .map_err(|_| Errors::ConnectionError)?;
+    let answer = read_line(&mut stream).await?;
+    let (_, members) = members_from_msg(&answer)?;
+    Ok(members)
+}
+
+/// Reads a single line from the stream one byte at a time, so nothing that comes after it is lost
+/// when the stream is handed to an actor.
+pub async fn read_line(stream: &mut (impl AsyncRead + Unpin)) -> Result<String, Errors> {
+    let mut line = vec![];
+    let mut byte = [0; 1];
+    loop {
+        let read = stream
+            .read(&mut byte)
+            .await
+            .map_err(|_| Errors::ConnectionError)?;
+        if read == 0 {
+            return Err(Errors::ConnectionError);
+        }
+        if byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+    }
+    String::from_utf8(line).map_err(|_| Errors::CouldNotParse)
+}
+
+/// Creates the message with the members of the cluster, in the form
+/// `MEMBERS,sender_id,leader,id@ecoms_address@stores_address/id@ecoms_address@stores_address`.
+pub fn members_msg(sender_id: usize, members: &Members) -> String {
+    let list: Vec<String> = members
+        .members
+        .iter()
+        .map(|(id, member)| format!("{id}@{}@{}", member.ecoms_address, member.stores_address))
+        .collect();
+    format!(
+        "{MEMBERS_MSG},{sender_id},{},{}\n",
+        members.leader,
+        list.join("/")
+    )
+}
+
+/// Parses the message created by `members_msg`, returning the id of the sender and the members of the cluster.
+pub fn members_from_msg(msg: &str) -> Result<(usize, Members), Errors> {
+    let split: Vec<&str> = msg.trim_end().split(',').collect();
+    if split.len() != 4 || split[0] != MEMBERS_MSG {
+        return Err(Errors::CouldNotParse);
+    }
+    let sender_id = <usize as FromStr>::from_str(split[1]).map_err(|_| Errors::CouldNotParse)?;
+    let leader = <usize as FromStr>::from_str(split[2]).map_err(|_| Errors::CouldNotParse)?;
+    let mut members = vec![];
+    for member in split[3].split('/').filter(|m| !m.is_empty()) {
+        let fields: Vec<&str> = member.split('@').collect();
+        if fields.len() != 3 {
+            return Err(Errors::CouldNotParse);
+        }
+        let id = <usize as FromStr>::from_str(fields[0]).map_err(|_| Errors::CouldNotParse)?;
+        members.push((
+            id,
+            Member {
+                ecoms_address: fields[1].to_string(),
+                stores_address: fields[2].to_string(),
+            },
+        ));
+    }
+    Ok((sender_id, Members { leader, members }))
+}
+
 /// This function is responsible for creating a vec with the visited ecoms received in a String
 pub fn vec_from_election_msg(msg: String) -> Vec<usize> {
     let mut vec: Vec<usize> = vec![];
@@ -75,14 +357,8 @@ pub fn vec_from_election_msg(msg: String) -> Vec<usize> {
     vec
 }
 
-/// Creates the election message from the election message received by the previous ecom in the ring
-pub fn election_from_vec(visited: Vec<usize>, my_id: String) -> String {
-    let mut msg = "ELECTION,".to_string();
-    for ecom in visited {
-        msg = msg + &ecom.to_string();
-        msg += "/";
-    }
-    msg += &my_id;
-    msg += "\n";
-    msg
+/// Creates the election message with the ecoms visited so far, the last one being the sender.
+pub fn election_from_vec(visited: Vec<usize>) -> String {
+    let ids: Vec<String> = visited.iter().map(|id| id.to_string()).collect();
+    format!("ELECTION,{}\n", ids.join("/"))
 }
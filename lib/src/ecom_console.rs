@@ -0,0 +1,62 @@
+use crate::errors::Errors;
+use std::str::FromStr;
+
+const STATE_INPUT: &str = "L";
+const STOCK_INPUT: &str = "S";
+const ORDER_INPUT: &str = "O";
+const ELECTION_INPUT: &str = "E";
+const PAUSE_INPUT: &str = "P";
+const RESUME_INPUT: &str = "R";
+const DISCONNECT_INPUT: &str = "D";
+
+/// The usage of every command of the admin console of the ecommerce.
+pub const USAGE: [&str; 7] = [
+    "L: muestra el lider y las stores y ecommerce conectados",
+    "S: muestra el stock de cada store conectada",
+    "O,producto,cantidad: hace un pedido",
+    "E: arranca una eleccion de lider",
+    "P: pausa la toma de pedidos",
+    "R: reanuda la toma de pedidos",
+    "D,store: desconecta una store",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A command typed in the admin console of the ecommerce.
+pub enum EcomCommand {
+    ShowState,
+    ShowStock,
+    Order { product: String, quantity: usize },
+    Election,
+    Pause,
+    Resume,
+    DisconnectStore { store_id: String },
+}
+
+impl EcomCommand {
+    /// Parses a line of the console. Commands with arguments separate them with commas, as in `O,ZAP-001,2`.
+    pub fn parse(input: &str) -> Result<Self, Errors> {
+        let split: Vec<&str> = input.trim().split(',').map(str::trim).collect();
+        match (split[0], split.len()) {
+            (STATE_INPUT, 1) => Ok(EcomCommand::ShowState),
+            (STOCK_INPUT, 1) => Ok(EcomCommand::ShowStock),
+            (ORDER_INPUT, 3) if !split[1].is_empty() => {
+                let quantity =
+                    <usize as FromStr>::from_str(split[2]).map_err(|_| Errors::CouldNotParse)?;
+                if quantity == 0 {
+                    return Err(Errors::CouldNotParse);
+                }
+                Ok(EcomCommand::Order {
+                    product: split[1].to_string(),
+                    quantity,
+                })
+            }
+            (ELECTION_INPUT, 1) => Ok(EcomCommand::Election),
+            (PAUSE_INPUT, 1) => Ok(EcomCommand::Pause),
+            (RESUME_INPUT, 1) => Ok(EcomCommand::Resume),
+            (DISCONNECT_INPUT, 2) if !split[1].is_empty() => Ok(EcomCommand::DisconnectStore {
+                store_id: split[1].to_string(),
+            }),
+            _ => Err(Errors::CouldNotParse),
+        }
+    }
+}
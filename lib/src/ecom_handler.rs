@@ -1,49 +1,89 @@
 use crate::errors::Errors;
+use crate::logging::{connection_span, order_span};
+use crate::metrics::{Counter, Metrics};
 use crate::store::Store;
-use crate::store::{NewLeader, ReserveProduct};
+use crate::store::{
+    Acknowledge, EcomConnectionClosed, NewLeader, NewMember, ReserveProduct, ShareStock,
+};
+use crate::trace::split_trace;
+use crate::transport::Connection;
 use actix::dev::ContextFutureSpawner;
 use actix::fut::{wrap_future, ActorFutureExt};
 use actix::{Actor, ActorContext, Addr, Context, Handler, Message, StreamHandler};
 use std::str::FromStr;
 use tokio::io::AsyncWriteExt;
-use tokio::{io::WriteHalf, net::TcpStream};
+use tokio::io::WriteHalf;
+use tokio::sync::oneshot;
+use tracing::{debug, info, warn};
 
 extern crate actix;
 
 /// Will become the actor that is responsible for the connection between ecommerces and stores,
-/// from the side of the stores.
+/// from the side of the stores. `closed` tells the task that dialed the ecommerce when the ecommerce closes the connection.
+/// The `metrics` are the ones of the store.
 pub struct EcomHandler {
-    pub ecom: Option<WriteHalf<TcpStream>>,
+    pub ecom: Option<WriteHalf<Connection>>,
     pub ecom_id: String,
     pub store: Addr<Store>,
+    pub closed: Option<oneshot::Sender<()>>,
+    pub metrics: Metrics,
 }
 
 impl Actor for EcomHandler {
     type Context = Context<Self>;
 
     fn started(&mut self, _ctx: &mut Self::Context) {
-        println!("¡EcomHandler is alive with id [{}]!", self.ecom_id);
+        let _span = connection_span("ecom", &self.ecom_id).entered();
+        info!("Conexion con el ecommerce establecida");
+        self.metrics
+            .inc(Counter::ConnectionsUp, &[("peer", "ecom")]);
     }
 }
 
 /// Handles the supported messages received from the ecommerce.
 impl StreamHandler<Result<String, std::io::Error>> for EcomHandler {
     fn handle(&mut self, read: Result<String, std::io::Error>, _: &mut Self::Context) {
+        let _span = connection_span("ecom", &self.ecom_id).entered();
         if let Ok(line) = read {
-            let split: Vec<&str> = line.split(',').collect();
+            debug!(line, "Mensaje recibido");
+            let (fields, trace_id) = split_trace(&line);
+            let split: Vec<&str> = fields.split(',').collect();
             match split[0] {
                 "LEADER" => {
-                    let _ = self.store.try_send(NewLeader {
+                    let sent = self.store.try_send(NewLeader {
                         ecom_id: split[1].to_string(),
                     });
+                    self.metrics.sent(sent, "store");
+                }
+                "ACK" if split.len() == 2 => {
+                    if let Ok(seq) = <u64 as FromStr>::from_str(split[1]) {
+                        let sent = self.store.try_send(Acknowledge {
+                            ecom_id: self.ecom_id.clone(),
+                            seq,
+                        });
+                        self.metrics.sent(sent, "store");
+                    }
+                }
+                "SHARE" => {
+                    let sent = self.store.try_send(ShareStock);
+                    self.metrics.sent(sent, "store");
+                }
+                "MEMBER" if split.len() == 3 => {
+                    let sent = self.store.try_send(NewMember {
+                        ecom_id: split[1].to_string(),
+                        address: split[2].to_string(),
+                    });
+                    self.metrics.sent(sent, "store");
                 }
                 _ => {
+                    // Orders from ecommerces that do not send traces start a new one in the store
+                    let _span = trace_id.map(|trace_id| order_span(trace_id, fields).entered());
                     let quantity =
                         <usize as FromStr>::from_str(split[1]).map_err(|_| Errors::CouldNotParse);
                     let time_limit =
                         <usize as FromStr>::from_str(split[2]).map_err(|_| Errors::CouldNotParse);
                     if quantity.is_err() || time_limit.is_err() {
-                        eprintln!("No se pudo parsear la linea [{line}]", line = line);
+                        warn!("No se pudo parsear el pedido");
                         return;
                     }
 
@@ -53,9 +93,11 @@ impl StreamHandler<Result<String, std::io::Error>> for EcomHandler {
                                 product: split[0].to_string(),
                                 quantity: amount,
                                 time_limit: time,
+                                trace_id,
                             };
-                            if self.store.try_send(reserved_prod).is_err() {
-                                eprintln!("[ONLINE_SALES] No se pudo enviar el pedido [{line}] a la store");
+                            let sent = self.store.try_send(reserved_prod);
+                            if !self.metrics.sent(sent, "store") {
+                                warn!("No se pudo enviar el pedido a la store");
                             }
                         }
                     }
@@ -63,6 +105,21 @@ impl StreamHandler<Result<String, std::io::Error>> for EcomHandler {
             }
         }
     }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        let _span = connection_span("ecom", &self.ecom_id).entered();
+        info!("El ecommerce cerro la conexion");
+        self.metrics
+            .inc(Counter::ConnectionsDown, &[("peer", "ecom")]);
+        let sent = self.store.try_send(EcomConnectionClosed {
+            ecom_id: self.ecom_id.clone(),
+        });
+        self.metrics.sent(sent, "store");
+        if let Some(closed) = self.closed.take() {
+            let _ = closed.send(());
+        }
+        ctx.stop();
+    }
 }
 
 /// Receives a String and sends it to the ecommerce
@@ -81,10 +138,17 @@ impl Handler<Answer> for EcomHandler {
             .take()
             .expect("No debería poder llegar otro mensaje antes de que vuelva por usar ctx.wait");
         wrap_future::<_, Self>(async move {
-            write
+            // The ecommerce may be gone already, its handler stops once the read side ends
+            if write
                 .write_all(format!("{}\n", msg.answer).as_bytes())
                 .await
-                .expect(&msg.answer);
+                .is_err()
+            {
+                warn!(
+                    answer = msg.answer,
+                    "No se pudo enviar la respuesta al ecommerce"
+                );
+            }
             write
         })
         .map(|write, this, _| this.ecom = Some(write))
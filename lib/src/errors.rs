@@ -19,6 +19,18 @@ pub enum Errors {
     StoreNotConnectedError,
     NoActiveLeader,
     NoStockError,
+    DuplicateProductError,
+    NotEnoughReservedError,
+    StockInvariantError,
+    UnknownProductError,
+    StoreDrainingError,
+    IntakePausedError,
+    ReservationNotFoundError,
+    UnknownRouteError,
+    UnknownNodeError,
+    NodeDownError,
+    InvalidConfig(String),
+    InvalidCommand(String),
 }
 
 // -------------------- TEST PURPOSE TRAITS --------------------
@@ -26,6 +38,12 @@ pub enum Errors {
 // Implements the comparison between two Errors for testing purposes
 impl PartialEq for Errors {
     fn eq(&self, other: &Self) -> bool {
+        if let (Errors::InvalidConfig(a), Errors::InvalidConfig(b)) = (self, other) {
+            return a == b;
+        }
+        if let (Errors::InvalidCommand(a), Errors::InvalidCommand(b)) = (self, other) {
+            return a == b;
+        }
         matches!(
             (self, other),
             (
@@ -33,6 +51,22 @@ impl PartialEq for Errors {
                 Errors::StoreNotConnectedError
             ) | (Errors::NotEnoughStockError, Errors::NotEnoughStockError)
                 | (Errors::ProductNotFoundError, Errors::ProductNotFoundError)
+                | (Errors::DuplicateProductError, Errors::DuplicateProductError)
+                | (
+                    Errors::NotEnoughReservedError,
+                    Errors::NotEnoughReservedError
+                )
+                | (Errors::StockInvariantError, Errors::StockInvariantError)
+                | (Errors::UnknownProductError, Errors::UnknownProductError)
+                | (Errors::StoreDrainingError, Errors::StoreDrainingError)
+                | (Errors::IntakePausedError, Errors::IntakePausedError)
+                | (
+                    Errors::ReservationNotFoundError,
+                    Errors::ReservationNotFoundError
+                )
+                | (Errors::UnknownRouteError, Errors::UnknownRouteError)
+                | (Errors::UnknownNodeError, Errors::UnknownNodeError)
+                | (Errors::NodeDownError, Errors::NodeDownError)
         )
     }
 }
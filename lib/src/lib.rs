@@ -1,7 +1,25 @@
 pub mod abstract_ecom;
 pub mod abstract_store;
+pub mod admin_api;
+pub mod catalog;
+pub mod clock;
+pub mod config;
 pub mod coordinator;
 pub mod ecom;
+pub mod ecom_console;
 pub mod ecom_handler;
 pub mod errors;
+pub mod logging;
+pub mod metrics;
+pub mod offline_log;
+pub mod outbox;
+pub mod random;
+pub mod reconnect;
+pub mod sales_report;
+pub mod shutdown;
+pub mod simulation;
+pub mod stock_ledger;
 pub mod store;
+pub mod store_console;
+pub mod trace;
+pub mod transport;
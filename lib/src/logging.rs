@@ -0,0 +1,68 @@
+use crate::errors::Errors;
+use crate::trace::TraceId;
+use serde::Deserialize;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use tracing::{info_span, Span};
+use tracing_subscriber::EnvFilter;
+
+/// Level of the logs when no filter is configured.
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// How each log line is written: readable text, or one JSON object per line to aggregate the logs of a cluster.
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Errors;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(Errors::InvalidConfig(format!(
+                "log_format: '{format}' is not text or json"
+            ))),
+        }
+    }
+}
+
+/// Parses a filter such as `info` or `warn,lib::coordinator=debug`, with a level for the whole
+/// process and, optionally, one for each module. An empty filter logs at `DEFAULT_LOG_LEVEL`.
+pub fn log_filter(filter: &str) -> Result<EnvFilter, Errors> {
+    let filter = if filter.is_empty() {
+        DEFAULT_LOG_LEVEL
+    } else {
+        filter
+    };
+    EnvFilter::try_new(filter)
+        .map_err(|e| Errors::InvalidConfig(format!("log_level: '{filter}' {e}")))
+}
+
+/// Sends the logs of the process to stderr, so they do not mix with the answers of the terminal.
+pub fn init_logging(filter: &str, format: LogFormat) -> Result<(), Errors> {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(log_filter(filter)?)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    let result = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+    result.map_err(|e| Errors::InvalidConfig(format!("log_level: {e}")))
+}
+
+/// Span of everything done for a connection with another node, `peer` being `store` or `ecom`.
+pub fn connection_span(peer: &str, id: &str) -> Span {
+    info_span!("connection", peer, id)
+}
+
+/// Span of everything done for an order, known by its trace and the `product,quantity` line that travels between the nodes.
+pub fn order_span(trace_id: TraceId, order: &str) -> Span {
+    info_span!("order", %trace_id, order)
+}
@@ -0,0 +1,296 @@
+use crate::admin_api::{serve_http, HttpRequest, HttpResponse};
+use crate::errors::Errors;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use tracing::error;
+
+const METRICS_PATH: &str = "/metrics";
+/// Buckets in seconds, from a quick answer to a dispatch past the longest time limit.
+const SECONDS_BUCKETS: [f64; 9] = [0.01, 0.05, 0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+/// Buckets of the time a reservation took relative to its time limit. Above 1 the reservation missed it.
+const RATIO_BUCKETS: [f64; 7] = [0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 4.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// The counters collected by the actors of both binaries. Each process only exposes the ones its actors count.
+pub enum Counter {
+    OrdersReceived,
+    OrdersRouted,
+    OrdersApproved,
+    OrdersCancelled,
+    MailboxDrops,
+    ElectionsStarted,
+    ElectionsCompleted,
+    ConnectionsUp,
+    ConnectionsDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// The histograms collected by the actors of both binaries.
+pub enum Histogram {
+    ReservationSeconds,
+    DispatchDeadlineRatio,
+    ElectionSeconds,
+}
+
+const COUNTERS: [Counter; 9] = [
+    Counter::OrdersReceived,
+    Counter::OrdersRouted,
+    Counter::OrdersApproved,
+    Counter::OrdersCancelled,
+    Counter::MailboxDrops,
+    Counter::ElectionsStarted,
+    Counter::ElectionsCompleted,
+    Counter::ConnectionsUp,
+    Counter::ConnectionsDown,
+];
+
+const HISTOGRAMS: [Histogram; 3] = [
+    Histogram::ReservationSeconds,
+    Histogram::DispatchDeadlineRatio,
+    Histogram::ElectionSeconds,
+];
+
+impl Counter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Counter::OrdersReceived => "orders_received_total",
+            Counter::OrdersRouted => "orders_routed_total",
+            Counter::OrdersApproved => "orders_approved_total",
+            Counter::OrdersCancelled => "orders_cancelled_total",
+            Counter::MailboxDrops => "mailbox_drops_total",
+            Counter::ElectionsStarted => "elections_started_total",
+            Counter::ElectionsCompleted => "elections_completed_total",
+            Counter::ConnectionsUp => "connections_up_total",
+            Counter::ConnectionsDown => "connections_down_total",
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Counter::OrdersReceived => {
+                "Orders taken by the ecommerce or reservations asked to the store"
+            }
+            Counter::OrdersRouted => "Orders sent to a store",
+            Counter::OrdersApproved => "Orders approved, by channel",
+            Counter::OrdersCancelled => "Online orders cancelled by the store",
+            Counter::MailboxDrops => "Messages that could not be delivered to an actor, by actor",
+            Counter::ElectionsStarted => "Leader elections started by this ecommerce",
+            Counter::ElectionsCompleted => {
+                "Leader elections started by this ecommerce that chose a leader"
+            }
+            Counter::ConnectionsUp => "Connections established, by peer",
+            Counter::ConnectionsDown => "Connections lost or closed, by peer",
+        }
+    }
+}
+
+impl Histogram {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Histogram::ReservationSeconds => "reservation_seconds",
+            Histogram::DispatchDeadlineRatio => "dispatch_deadline_ratio",
+            Histogram::ElectionSeconds => "election_seconds",
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Histogram::ReservationSeconds => {
+                "Time a reservation stays open until it is dispatched or cancelled"
+            }
+            Histogram::DispatchDeadlineRatio => {
+                "Time a reservation took over its time limit, above 1 it was late"
+            }
+            Histogram::ElectionSeconds => "Time from starting an election to knowing the leader",
+        }
+    }
+
+    fn buckets(&self) -> &'static [f64] {
+        match self {
+            Histogram::DispatchDeadlineRatio => &RATIO_BUCKETS,
+            _ => &SECONDS_BUCKETS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Observations {
+    /// How many observations fell in each bucket, not cumulative.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    counters: BTreeMap<(Counter, String), u64>,
+    histograms: BTreeMap<(Histogram, String), Observations>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// The metrics of a process, shared by its actors. Each series is known by its metric and its labels.
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn inc(&self, counter: Counter, labels: &[(&str, &str)]) {
+        if let Ok(mut registry) = self.registry.lock() {
+            *registry
+                .counters
+                .entry((counter, label_set(labels)))
+                .or_default() += 1;
+        }
+    }
+
+    pub fn observe(&self, histogram: Histogram, labels: &[(&str, &str)], value: f64) {
+        if let Ok(mut registry) = self.registry.lock() {
+            let buckets = histogram.buckets();
+            let observations = registry
+                .histograms
+                .entry((histogram, label_set(labels)))
+                .or_default();
+            observations.buckets.resize(buckets.len(), 0);
+            if let Some(i) = buckets.iter().position(|bound| value <= *bound) {
+                observations.buckets[i] += 1;
+            }
+            observations.sum += value;
+            observations.count += 1;
+        }
+    }
+
+    /// Counts a message that could not be sent to `actor`. Returns whether it was sent.
+    pub fn sent<E>(&self, result: Result<(), E>, actor: &str) -> bool {
+        if result.is_err() {
+            self.inc(Counter::MailboxDrops, &[("actor", actor)]);
+        }
+        result.is_ok()
+    }
+
+    pub fn counter(&self, counter: Counter, labels: &[(&str, &str)]) -> u64 {
+        self.registry
+            .lock()
+            .ok()
+            .and_then(|registry| {
+                registry
+                    .counters
+                    .get(&(counter, label_set(labels)))
+                    .copied()
+            })
+            .unwrap_or_default()
+    }
+
+    /// How many values were observed by a histogram.
+    pub fn observations(&self, histogram: Histogram, labels: &[(&str, &str)]) -> u64 {
+        self.registry
+            .lock()
+            .ok()
+            .and_then(|registry| {
+                registry
+                    .histograms
+                    .get(&(histogram, label_set(labels)))
+                    .map(|observations| observations.count)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let registry = match self.registry.lock() {
+            Ok(registry) => registry,
+            Err(_) => return String::new(),
+        };
+        let mut text = String::new();
+        for counter in COUNTERS {
+            let _ = writeln!(text, "# HELP {} {}", counter.name(), counter.help());
+            let _ = writeln!(text, "# TYPE {} counter", counter.name());
+            for ((_, labels), value) in registry.counters.iter().filter(|((c, _), _)| *c == counter)
+            {
+                let _ = writeln!(text, "{}{} {value}", counter.name(), braces(labels));
+            }
+        }
+        for histogram in HISTOGRAMS {
+            let name = histogram.name();
+            let _ = writeln!(text, "# HELP {name} {}", histogram.help());
+            let _ = writeln!(text, "# TYPE {name} histogram");
+            let series = registry
+                .histograms
+                .iter()
+                .filter(|((h, _), _)| *h == histogram);
+            for ((_, labels), observations) in series {
+                let mut cumulative = 0;
+                for (bound, count) in histogram.buckets().iter().zip(&observations.buckets) {
+                    cumulative += count;
+                    let le = with_label(labels, "le", &bound.to_string());
+                    let _ = writeln!(text, "{name}_bucket{{{le}}} {cumulative}");
+                }
+                let le = with_label(labels, "le", "+Inf");
+                let _ = writeln!(text, "{name}_bucket{{{le}}} {}", observations.count);
+                let _ = writeln!(text, "{name}_sum{} {}", braces(labels), observations.sum);
+                let _ = writeln!(
+                    text,
+                    "{name}_count{} {}",
+                    braces(labels),
+                    observations.count
+                );
+            }
+        }
+        text
+    }
+}
+
+/// Answers `GET /metrics` with every metric of the process.
+pub fn metrics_response(metrics: &Metrics, request: &HttpRequest) -> HttpResponse {
+    if request.method != "GET" || request.path != METRICS_PATH {
+        return HttpResponse::from_error(Errors::UnknownRouteError);
+    }
+    HttpResponse::text(metrics.render())
+}
+
+/// Serves the metrics in `address`, if it is set. It only returns if it can not listen.
+pub async fn serve_metrics(address: Option<String>, metrics: Metrics) -> Result<(), Errors> {
+    let address = match address {
+        Some(address) => address,
+        None => return Ok(()),
+    };
+    let result = serve_http(address, move |request| {
+        let response = metrics_response(&metrics, &request);
+        async move { response }
+    })
+    .await;
+    if let Err(e) = &result {
+        error!(error = ?e, "No se pudo levantar el endpoint de metricas");
+    }
+    result
+}
+
+/// The labels as they are written between braces, in the order they are given.
+fn label_set(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(name, value)| format!("{name}=\"{}\"", value.replace('"', "\\\"")))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn braces(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{labels}}}")
+    }
+}
+
+fn with_label(labels: &str, name: &str, value: &str) -> String {
+    let label = format!("{name}=\"{value}\"");
+    if labels.is_empty() {
+        label
+    } else {
+        format!("{labels},{label}")
+    }
+}
@@ -0,0 +1,132 @@
+use crate::sales_report::Channel;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+const APPROVED_MSG: &str = "APPROVED";
+const CANCELLED_MSG: &str = "CANCELLED";
+const STOCK_MSG: &str = "STOCK";
+const ORDERS_PREFIX: &str = "orders=";
+
+/// How many orders a stock notification adds up: the `orders=` field of the compacted lines of the log,
+/// or one for a notification of a single order.
+pub fn orders_in(fields: &[&str]) -> u64 {
+    fields
+        .iter()
+        .find_map(|field| field.strip_prefix(ORDERS_PREFIX))
+        .and_then(|orders| <u64 as FromStr>::from_str(orders).ok())
+        .unwrap_or(1)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The units of the orders added up in a line of the log, and how many orders they were.
+struct Compacted {
+    units: usize,
+    orders: u64,
+}
+
+impl Compacted {
+    fn add(&mut self, units: usize, orders: u64) {
+        self.units += units;
+        self.orders += orders;
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The stock events of the store that could not be told to the leader while the store was disconnected.
+/// They are kept compacted: the units of a product sold through each channel, and the cancelled units of each product,
+/// are added up, so the replay on reconnection sends one line per product instead of one per event. Each line
+/// carries how many orders it adds up as its last field, `orders=<n>`, so the sales report of the leader counts them.
+/// A restocked product is replayed first with `STOCK`, counting the units sold that are replayed after it,
+/// so once the sales are applied the leader ends up with the current stock.
+pub struct OfflineLog {
+    approved: BTreeMap<(String, Channel), Compacted>,
+    cancelled: BTreeMap<String, Compacted>,
+    restocked: BTreeMap<String, usize>,
+}
+
+impl OfflineLog {
+    pub fn new() -> Self {
+        OfflineLog::default()
+    }
+
+    /// Keeps an answer of the store to the ecommerce if it is a stock event (`APPROVED`, `CANCELLED` or `STOCK`).
+    /// Returns whether the answer was kept.
+    pub fn record(&mut self, answer: &str) -> bool {
+        let split: Vec<&str> = answer.split(',').collect();
+        if split.len() < 3 {
+            return false;
+        }
+        let quantity = match <usize as FromStr>::from_str(split[2]) {
+            Ok(quantity) => quantity,
+            Err(_) => return false,
+        };
+        let product = split[1].to_string();
+        let orders = orders_in(&split);
+        match split[0] {
+            APPROVED_MSG => {
+                let channel = Channel::from_wire(split.get(3).copied());
+                self.approved
+                    .entry((product, channel))
+                    .or_default()
+                    .add(quantity, orders);
+                true
+            }
+            CANCELLED_MSG => {
+                self.cancelled
+                    .entry(product)
+                    .or_default()
+                    .add(quantity, orders);
+                true
+            }
+            STOCK_MSG => {
+                let sold: usize = self
+                    .approved
+                    .iter()
+                    .filter(|((sold_product, _), _)| *sold_product == product)
+                    .map(|(_, sold)| sold.units)
+                    .sum();
+                self.restocked
+                    .insert(product, quantity.saturating_add(sold));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.approved.is_empty() && self.cancelled.is_empty() && self.restocked.is_empty()
+    }
+
+    /// The lines the log would send if it were replayed now, without emptying it.
+    pub fn lines(&self) -> Vec<String> {
+        self.clone().replay()
+    }
+
+    /// Empties the log, returning its events as the lines that are sent to the ecommerce.
+    pub fn replay(&mut self) -> Vec<String> {
+        let restocked = std::mem::take(&mut self.restocked)
+            .into_iter()
+            .map(|(product, quantity)| format!("{STOCK_MSG},{product},{quantity}"));
+        let approved =
+            std::mem::take(&mut self.approved)
+                .into_iter()
+                .map(|((product, channel), sold)| {
+                    format!(
+                        "{APPROVED_MSG},{product},{},{},{ORDERS_PREFIX}{}",
+                        sold.units,
+                        channel.as_str(),
+                        sold.orders
+                    )
+                });
+        let cancelled =
+            std::mem::take(&mut self.cancelled)
+                .into_iter()
+                .map(|(product, cancelled)| {
+                    format!(
+                        "{CANCELLED_MSG},{product},{},{ORDERS_PREFIX}{}",
+                        cancelled.units, cancelled.orders
+                    )
+                });
+        restocked.chain(approved).chain(cancelled).collect()
+    }
+}
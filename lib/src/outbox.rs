@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SEQ_MSG: &str = "SEQ";
+const ACK_MSG: &str = "ACK";
+/// The answers of the store that change what the leader knows of its stock, and so must reach it.
+const NOTIFICATIONS: [&str; 3] = ["APPROVED", "CANCELLED", "STOCK"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The stock notifications that the store sent to the leader and the leader did not acknowledge yet.
+/// Each one is numbered, and the leader acknowledges every notification up to a number, so the ones
+/// still pending are sent again when the leader changes or reconnects.
+/// The numbers start from 1 on each run of the store, so they go along with the `session` of the run.
+pub struct Outbox {
+    session: u64,
+    next_seq: u64,
+    pending: BTreeMap<u64, String>,
+}
+
+impl Outbox {
+    /// An empty outbox for a new run of the store. The session is taken from the time it starts.
+    pub fn new() -> Self {
+        let session = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos() as u64)
+            .unwrap_or_default();
+        Outbox {
+            session,
+            ..Outbox::default()
+        }
+    }
+
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    /// Whether an answer of the store is a stock notification that has to go through the outbox.
+    pub fn is_notification(answer: &str) -> bool {
+        let kind = answer.split(',').next().unwrap_or_default();
+        NOTIFICATIONS.contains(&kind)
+    }
+
+    /// Keeps a notification until it is acknowledged. Returns the line to send, with its number.
+    pub fn push(&mut self, answer: String) -> String {
+        self.next_seq += 1;
+        let line = sequenced(self.session, self.next_seq, &answer);
+        self.pending.insert(self.next_seq, answer);
+        line
+    }
+
+    /// Drops every notification up to `seq`, which the leader already has.
+    pub fn ack(&mut self, seq: u64) {
+        self.pending = self.pending.split_off(&seq.saturating_add(1));
+    }
+
+    /// The lines of the notifications that were not acknowledged, in the order they were sent.
+    pub fn pending(&self) -> Vec<String> {
+        self.pending
+            .iter()
+            .map(|(seq, answer)| sequenced(self.session, *seq, answer))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// A notification with the session of the store and its number, as it is sent to the ecommerce.
+pub fn sequenced(session: u64, seq: u64, answer: &str) -> String {
+    format!("{SEQ_MSG},{session},{seq},{answer}")
+}
+
+/// Splits a line sent by the store into its session, its number and the notification, if it has one.
+pub fn parse_sequenced(line: &str) -> Option<(u64, u64, &str)> {
+    let mut split = line.splitn(4, ',');
+    if split.next() != Some(SEQ_MSG) {
+        return None;
+    }
+    let session = <u64 as FromStr>::from_str(split.next()?).ok()?;
+    let seq = <u64 as FromStr>::from_str(split.next()?).ok()?;
+    Some((session, seq, split.next()?))
+}
+
+/// The acknowledgement the ecommerce sends for every notification up to `seq`.
+pub fn ack_msg(seq: u64) -> String {
+    format!("{ACK_MSG},{seq}")
+}
@@ -0,0 +1,24 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// The generator every random decision of a node draws from.
+pub type NodeRng = StdRng;
+
+/// The seed of the node: the configured one or, if there is none, a random one that can be
+/// configured later to repeat the run.
+pub fn node_seed(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(rand::random)
+}
+
+/// The generator of one part of the node, such as `coordinator` or `reserves_manager`. Each part draws
+/// from its own stream of the seed, so the decisions of one do not change with how many the others take.
+pub fn stream_rng(seed: u64, stream: &str) -> NodeRng {
+    NodeRng::seed_from_u64(seed ^ stream_hash(stream))
+}
+
+/// FNV-1a, which unlike the hasher of the std does not change between runs.
+fn stream_hash(stream: &str) -> u64 {
+    stream.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
@@ -0,0 +1,109 @@
+use crate::errors::Errors;
+use crate::random::{stream_rng, NodeRng};
+use crate::transport::{connect, Connection};
+use rand::Rng;
+use std::time::Duration;
+use tokio::time::sleep;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How a connection that could not be made, or that was lost, is retried: the delay before the first retry,
+/// the biggest delay between retries, how many retries are made before giving up (`None` keeps trying forever)
+/// and the seed of the node, that the jitter is drawn from.
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub max_attempts: Option<u32>,
+    pub seed: u64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(10),
+            max_attempts: Some(10),
+            seed: 0,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The same policy, but without a limit of attempts.
+    pub fn forever(self) -> Self {
+        ReconnectPolicy {
+            max_attempts: None,
+            ..self
+        }
+    }
+
+    /// The same policy, drawing its jitter from `seed`.
+    pub fn seeded(self, seed: u64) -> Self {
+        ReconnectPolicy { seed, ..self }
+    }
+
+    /// The backoff of the connection with `peer`. Each peer gets its own jitter, the same on every run with the same seed.
+    pub fn backoff(&self, peer: &str) -> Backoff {
+        Backoff {
+            policy: *self,
+            attempts: 0,
+            rng: stream_rng(self.seed, &format!("reconnect/{peer}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Jittered exponential backoff. Every retry doubles the delay of the previous one, up to the max delay,
+/// and waits a random time between half of it and all of it, so peers that lost each other do not retry in lockstep.
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    attempts: u32,
+    rng: NodeRng,
+}
+
+impl Backoff {
+    /// The time to wait before the next retry, or None if the retries ran out.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if let Some(max_attempts) = self.policy.max_attempts {
+            if self.attempts >= max_attempts {
+                return None;
+            }
+        }
+        let initial = self.policy.initial_delay.as_millis() as u64;
+        let max = self.policy.max_delay.as_millis() as u64;
+        let factor = 1_u64.checked_shl(self.attempts.min(32)).unwrap_or(u64::MAX);
+        let delay = initial.saturating_mul(factor).min(max);
+        self.attempts += 1;
+
+        let half = delay / 2;
+        let jittered = half + self.rng.gen_range(0, delay - half + 1);
+        Some(Duration::from_millis(jittered))
+    }
+
+    /// Starts over from the initial delay, after a connection was made.
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+/// Connects to `address`, retrying with the given backoff until it works or the retries run out.
+pub async fn connect_with_backoff(
+    address: &str,
+    backoff: &mut Backoff,
+) -> Result<Connection, Errors> {
+    loop {
+        match connect(address).await {
+            Ok(stream) => {
+                backoff.reset();
+                return Ok(stream);
+            }
+            Err(_) => match backoff.next_delay() {
+                Some(delay) => sleep(delay).await,
+                None => return Err(Errors::ConnectionError),
+            },
+        }
+    }
+}
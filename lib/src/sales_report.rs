@@ -0,0 +1,219 @@
+use crate::errors::Errors;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// Where a sale was made: in the shop floor or through an ecommerce.
+pub enum Channel {
+    Physical,
+    Online,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Physical => "physical",
+            Channel::Online => "online",
+        }
+    }
+
+    /// Parses the channel sent in the messages between stores and ecommerces. Anything that is not `physical` is an online sale.
+    pub fn from_wire(channel: Option<&str>) -> Channel {
+        match channel {
+            Some("physical") => Channel::Physical,
+            _ => Channel::Online,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// Why an online order was lost before reaching any store.
+pub enum LossReason {
+    UnknownProduct,
+    BackorderExpired,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+/// Running totals of one group of sales.
+pub struct SalesTotals {
+    pub orders_approved: u64,
+    pub units_sold: u64,
+    pub revenue_cents: u64,
+    pub orders_cancelled: u64,
+    pub units_cancelled: u64,
+}
+
+impl SalesTotals {
+    fn add(&mut self, other: &SalesTotals) {
+        self.orders_approved += other.orders_approved;
+        self.units_sold += other.units_sold;
+        self.revenue_cents += other.revenue_cents;
+        self.orders_cancelled += other.orders_cancelled;
+        self.units_cancelled += other.units_cancelled;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// The totals of a product sold by a store through a channel.
+pub struct SalesRow {
+    pub store_id: String,
+    pub channel: Channel,
+    pub product: String,
+    #[serde(flatten)]
+    pub totals: SalesTotals,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Running aggregates of the units and revenue of every sale, by store, channel and product,
+/// together with the count of online orders that were lost.
+pub struct SalesReport {
+    sales: BTreeMap<(String, Channel, String), SalesTotals>,
+    lost_orders: BTreeMap<LossReason, u64>,
+}
+
+#[derive(Serialize)]
+struct SalesReportJson<'a> {
+    rows: Vec<SalesRow>,
+    by_store: HashMap<String, SalesTotals>,
+    by_channel: HashMap<&'a str, SalesTotals>,
+    by_product: HashMap<String, SalesTotals>,
+    lost_orders: &'a BTreeMap<LossReason, u64>,
+}
+
+impl SalesReport {
+    pub fn new() -> Self {
+        SalesReport::default()
+    }
+
+    /// Records `orders` approved sales that add up to `quantity` units at `price_cents` each.
+    pub fn record_sale(
+        &mut self,
+        store_id: &str,
+        channel: Channel,
+        product: &str,
+        quantity: usize,
+        orders: u64,
+        price_cents: u64,
+    ) {
+        let totals = self.entry(store_id, channel, product);
+        totals.orders_approved += orders;
+        totals.units_sold += quantity as u64;
+        totals.revenue_cents = totals
+            .revenue_cents
+            .saturating_add(price_cents.saturating_mul(quantity as u64));
+    }
+
+    /// Records `orders` online orders, of `quantity` units in all, that the store cancelled because they could not be
+    /// dispatched in time.
+    pub fn record_cancellation(
+        &mut self,
+        store_id: &str,
+        product: &str,
+        quantity: usize,
+        orders: u64,
+    ) {
+        let totals = self.entry(store_id, Channel::Online, product);
+        totals.orders_cancelled += orders;
+        totals.units_cancelled += quantity as u64;
+    }
+
+    /// Records an online order that never reached a store.
+    pub fn record_lost(&mut self, reason: LossReason) {
+        *self.lost_orders.entry(reason).or_default() += 1;
+    }
+
+    pub fn rows(&self) -> Vec<SalesRow> {
+        self.sales
+            .iter()
+            .map(|((store_id, channel, product), totals)| SalesRow {
+                store_id: store_id.clone(),
+                channel: *channel,
+                product: product.clone(),
+                totals: *totals,
+            })
+            .collect()
+    }
+
+    pub fn totals_by_store(&self) -> HashMap<String, SalesTotals> {
+        self.group_by(|(store_id, _, _)| store_id.clone())
+    }
+
+    pub fn totals_by_channel(&self) -> HashMap<Channel, SalesTotals> {
+        self.group_by(|(_, channel, _)| *channel)
+    }
+
+    pub fn totals_by_product(&self) -> HashMap<String, SalesTotals> {
+        self.group_by(|(_, _, product)| product.clone())
+    }
+
+    /// Orders lost for the given reason.
+    pub fn lost_orders(&self, reason: LossReason) -> u64 {
+        self.lost_orders.get(&reason).copied().unwrap_or_default()
+    }
+
+    /// One line per store, channel and product, preceded by a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "store_id,channel,product,orders_approved,units_sold,revenue_cents,orders_cancelled,units_cancelled\n".to_string();
+        for row in self.rows() {
+            csv += &format!(
+                "{},{},{},{},{},{},{},{}\n",
+                row.store_id,
+                row.channel.as_str(),
+                row.product,
+                row.totals.orders_approved,
+                row.totals.units_sold,
+                row.totals.revenue_cents,
+                row.totals.orders_cancelled,
+                row.totals.units_cancelled
+            );
+        }
+        csv
+    }
+
+    /// The rows of the report along with its totals by store, channel and product and the lost orders.
+    pub fn to_json(&self) -> Result<String, Errors> {
+        let report = SalesReportJson {
+            rows: self.rows(),
+            by_store: self.totals_by_store(),
+            by_channel: self
+                .totals_by_channel()
+                .into_iter()
+                .map(|(channel, totals)| (channel.as_str(), totals))
+                .collect(),
+            by_product: self.totals_by_product(),
+            lost_orders: &self.lost_orders,
+        };
+        serde_json::to_string_pretty(&report).map_err(|_| Errors::CouldNotParse)
+    }
+
+    /// Writes the report as `<name>.csv` and `<name>.json` inside `dir`, creating it if needed.
+    pub fn write_files(&self, dir: &str, name: &str) -> Result<(), Errors> {
+        fs::create_dir_all(dir).map_err(|_| Errors::WriteError)?;
+        let base = Path::new(dir).join(name);
+        fs::write(base.with_extension("csv"), self.to_csv()).map_err(|_| Errors::WriteError)?;
+        fs::write(base.with_extension("json"), self.to_json()?).map_err(|_| Errors::WriteError)?;
+        Ok(())
+    }
+
+    fn entry(&mut self, store_id: &str, channel: Channel, product: &str) -> &mut SalesTotals {
+        self.sales
+            .entry((store_id.to_string(), channel, product.to_string()))
+            .or_default()
+    }
+
+    fn group_by<K, F>(&self, key: F) -> HashMap<K, SalesTotals>
+    where
+        K: std::hash::Hash + Eq,
+        F: Fn(&(String, Channel, String)) -> K,
+    {
+        let mut groups: HashMap<K, SalesTotals> = HashMap::new();
+        for (sales_key, totals) in &self.sales {
+            groups.entry(key(sales_key)).or_default().add(totals);
+        }
+        groups
+    }
+}
@@ -0,0 +1,72 @@
+use crate::errors::Errors;
+use std::fs;
+use std::path::Path;
+use tokio::signal::ctrl_c;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Exit status of a process that was shut down with work still pending.
+pub const PENDING_EXIT_CODE: i32 = 3;
+
+/// Waits until the process is asked to stop, with SIGINT (Ctrl+C) or SIGTERM. Returns the name of the signal.
+pub async fn shutdown_signal() -> &'static str {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(_) => {
+            let _ = ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+    tokio::select! {
+        _ = ctrl_c() => "SIGINT",
+        _ = terminate.recv() => "SIGTERM",
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// What a process left pending when it was shut down, as a count for each kind of work.
+/// A process that left nothing pending exits with status 0, otherwise with `PENDING_EXIT_CODE`.
+pub struct ShutdownSummary {
+    pending: Vec<(String, usize)>,
+}
+
+impl ShutdownSummary {
+    pub fn new() -> Self {
+        ShutdownSummary::default()
+    }
+
+    /// Adds the count of a kind of work that was left pending.
+    pub fn add(&mut self, what: &str, count: usize) {
+        self.pending.push((what.to_string(), count));
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.pending.iter().all(|(_, count)| *count == 0)
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.is_clean() {
+            0
+        } else {
+            PENDING_EXIT_CODE
+        }
+    }
+
+    /// One line for each kind of work, in the order they were added.
+    pub fn lines(&self) -> Vec<String> {
+        self.pending
+            .iter()
+            .map(|(what, count)| format!("{what}: {count}"))
+            .collect()
+    }
+}
+
+/// Writes the lines that could not be delivered before shutting down, one per line, in `dir/name.txt`.
+pub fn write_pending_lines(dir: &str, name: &str, lines: &[String]) -> Result<(), Errors> {
+    fs::create_dir_all(dir).map_err(|_| Errors::WriteError)?;
+    let mut content = lines.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    fs::write(Path::new(dir).join(name).with_extension("txt"), content)
+        .map_err(|_| Errors::WriteError)
+}
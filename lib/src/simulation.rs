@@ -0,0 +1,502 @@
+use crate::catalog::Catalog;
+use crate::clock::VirtualClock;
+use crate::config::{EcomTuning, StoreTuning};
+use crate::coordinator::{
+    _GetLeader, Coordinator, GetStoresStock, IntakeOrder, Member, NewEcom, NewStore,
+};
+use crate::ecom_handler::Stop;
+use crate::errors::Errors;
+use crate::metrics::Metrics;
+use crate::offline_log::OfflineLog;
+use crate::outbox::Outbox;
+use crate::random::{stream_rng, NodeRng};
+use crate::sales_report::SalesReport;
+use crate::stock_ledger::StockLedger;
+use crate::store::{
+    reserves_manager, Connections, GetConnections, GetStock, LocalProductOrder, NewEcomHandler,
+    ProductStock, Store,
+};
+use crate::trace::TraceId;
+use crate::transport::{duplex, Connection};
+use actix::{Actor, Addr};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// How many times the harness yields after each step, so the messages it caused get through every actor and link.
+const SETTLE_YIELDS: usize = 200;
+/// The virtual time moves in steps of this size, so what happens at one instant settles before the next one.
+const TICK: Duration = Duration::from_millis(100);
+const RESERVE_CHANNEL_SIZE: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A node of the simulated cluster: an ecommerce or a store, by its id.
+pub enum Node {
+    Ecom(usize),
+    Store(String),
+}
+
+#[derive(Debug, Clone, Default)]
+/// What the simulated cluster is made of: the ids of its ecommerces, the stores with the initial stock of each product,
+/// the catalog, the seed every random decision is drawn from and the tuning of the nodes.
+pub struct ClusterConfig {
+    pub ecoms: Vec<usize>,
+    pub stores: BTreeMap<String, BTreeMap<String, ProductStock>>,
+    pub catalog: Catalog,
+    pub seed: u64,
+    pub ecom_tuning: EcomTuning,
+    pub store_tuning: StoreTuning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A step of a script run on the cluster with `Cluster::run()`.
+pub enum Step {
+    /// A client of the ecommerce orders `quantity` units of the product.
+    Order {
+        ecom: usize,
+        product: String,
+        quantity: usize,
+    },
+    /// A customer buys `quantity` units of the product in person at the store.
+    Sale {
+        store: String,
+        product: String,
+        quantity: usize,
+    },
+    Advance(Duration),
+    Kill(Node),
+    Restart(Node),
+    Partition(Vec<Node>),
+    Heal,
+}
+
+/// A store of the cluster that is running, along with the task that dispatches its reservations.
+struct RunningStore {
+    addr: Addr<Store>,
+    reserves: JoinHandle<Result<(), Errors>>,
+}
+
+/// A whole cluster running inside the current actix System. Every ecommerce and store is an actor, and they talk over
+/// in-memory connections relayed by the harness, so it can cut them as a lost TCP connection. Every node reads the time
+/// from the same virtual clock, which only moves with `advance()`. A killed node loses its state, as a crashed process
+/// would, and starts again from the configuration when it is restarted. `partition()` splits the cluster in two until `heal()`.
+pub struct Cluster {
+    config: ClusterConfig,
+    clock: VirtualClock,
+    rng: NodeRng,
+    ecoms: BTreeMap<usize, Addr<Coordinator>>,
+    stores: BTreeMap<String, RunningStore>,
+    links: HashMap<(Node, Node), JoinHandle<()>>,
+    partition: Option<BTreeSet<Node>>,
+}
+
+impl Cluster {
+    /// Starts every node of the configuration and connects each ecommerce with the others and with every store.
+    pub async fn start(config: ClusterConfig) -> Result<Cluster, Errors> {
+        let mut cluster = Cluster {
+            rng: stream_rng(config.seed, "simulation"),
+            config,
+            clock: VirtualClock::new(),
+            ecoms: BTreeMap::new(),
+            stores: BTreeMap::new(),
+            links: HashMap::new(),
+            partition: None,
+        };
+        for node in cluster.nodes() {
+            cluster.start_node(&node)?;
+        }
+        cluster.link().await;
+        Ok(cluster)
+    }
+
+    /// The clock every node of the cluster reads the time from.
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+
+    /// Every node of the configuration, running or not.
+    pub fn nodes(&self) -> Vec<Node> {
+        let ecoms = self.config.ecoms.iter().map(|id| Node::Ecom(*id));
+        let stores = self.config.stores.keys().map(|id| Node::Store(id.clone()));
+        ecoms.chain(stores).collect()
+    }
+
+    pub fn is_running(&self, node: &Node) -> bool {
+        match node {
+            Node::Ecom(id) => self.ecoms.contains_key(id),
+            Node::Store(id) => self.stores.contains_key(id),
+        }
+    }
+
+    /// Lets the nodes handle every message caused by the last step.
+    pub async fn settle(&self) {
+        for _ in 0..SETTLE_YIELDS {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Moves the clock `duration` forward, settling the cluster at every tick.
+    pub async fn advance(&self, duration: Duration) {
+        let target = self.clock.elapsed() + duration;
+        while self.clock.elapsed() < target {
+            let step = TICK.min(target - self.clock.elapsed());
+            self.clock.advance(step).await;
+            self.settle().await;
+        }
+    }
+
+    /// A client of the ecommerce orders `quantity` units of `product`. Returns the error of the ecommerce, if any.
+    pub async fn order(
+        &mut self,
+        ecom: usize,
+        product: &str,
+        quantity: usize,
+    ) -> Result<(), Errors> {
+        let addr = self.ecom(ecom)?.clone();
+        let order = IntakeOrder {
+            order: format!("{product},{quantity}"),
+            trace_id: TraceId::from_rng(&mut self.rng),
+        };
+        let result = addr.send(order).await.map_err(|_| Errors::ActorMsgError)?;
+        self.settle().await;
+        result
+    }
+
+    /// A customer buys `quantity` units of `product` in person at the store. Returns the error of the store, if any.
+    pub async fn sale(
+        &mut self,
+        store: &str,
+        product: &str,
+        quantity: usize,
+    ) -> Result<(), Errors> {
+        let sale = LocalProductOrder {
+            product: product.to_string(),
+            quantity,
+        };
+        let result = self
+            .store(store)?
+            .addr
+            .send(sale)
+            .await
+            .map_err(|_| Errors::ActorMsgError)?;
+        self.settle().await;
+        result
+    }
+
+    /// Kills the node: its actor stops right away and every connection with it is lost.
+    /// Killing a node that is not running does nothing.
+    pub async fn kill(&mut self, node: &Node) -> Result<(), Errors> {
+        self.check_configured(node)?;
+        info!(?node, "Simulacion: se cae el nodo");
+        match node {
+            Node::Ecom(id) => {
+                if let Some(addr) = self.ecoms.remove(id) {
+                    let _ = addr.send(Stop).await;
+                }
+            }
+            Node::Store(id) => {
+                if let Some(store) = self.stores.remove(id) {
+                    let _ = store.addr.send(Stop).await;
+                    store.reserves.abort();
+                }
+            }
+        }
+        self.cut(|a, b| a == node || b == node);
+        self.settle().await;
+        Ok(())
+    }
+
+    /// Starts the node again from its configuration and connects it with the nodes it can reach.
+    /// A node that is still running is killed first.
+    pub async fn restart(&mut self, node: &Node) -> Result<(), Errors> {
+        self.kill(node).await?;
+        info!(?node, "Simulacion: se levanta el nodo");
+        self.start_node(node)?;
+        self.link().await;
+        Ok(())
+    }
+
+    /// Splits the cluster in two: the nodes of `side` can only reach each other, and the rest of the nodes
+    /// can only reach the rest. A previous partition is replaced.
+    pub async fn partition(&mut self, side: &[Node]) {
+        info!(?side, "Simulacion: se parte el cluster");
+        let side: BTreeSet<Node> = side.iter().cloned().collect();
+        self.cut(|a, b| side.contains(a) != side.contains(b));
+        self.partition = Some(side);
+        // The links cut within the other side of a previous partition are made again
+        self.link().await;
+    }
+
+    /// Ends the partition, connecting again the nodes that were on different sides.
+    pub async fn heal(&mut self) {
+        info!("Simulacion: se une el cluster");
+        self.partition = None;
+        self.link().await;
+    }
+
+    /// Runs the steps of the script in order, with the result of each one.
+    pub async fn run(&mut self, script: Vec<Step>) -> Vec<Result<(), Errors>> {
+        let mut results = vec![];
+        for step in script {
+            let result = match step {
+                Step::Order {
+                    ecom,
+                    product,
+                    quantity,
+                } => self.order(ecom, &product, quantity).await,
+                Step::Sale {
+                    store,
+                    product,
+                    quantity,
+                } => self.sale(&store, &product, quantity).await,
+                Step::Advance(duration) => {
+                    self.advance(duration).await;
+                    Ok(())
+                }
+                Step::Kill(node) => self.kill(&node).await,
+                Step::Restart(node) => self.restart(&node).await,
+                Step::Partition(side) => {
+                    self.partition(&side).await;
+                    Ok(())
+                }
+                Step::Heal => {
+                    self.heal().await;
+                    Ok(())
+                }
+            };
+            results.push(result);
+        }
+        results
+    }
+
+    /// The leader as the ecommerce sees it, None while it holds an election.
+    pub async fn leader(&self, ecom: usize) -> Result<Option<usize>, Errors> {
+        self.ecom(ecom)?
+            .send(_GetLeader)
+            .await
+            .map_err(|_| Errors::ActorMsgError)?
+            .map_err(|_| Errors::ActorMsgError)
+    }
+
+    /// The stock of every product of the store.
+    pub async fn stock(&self, store: &str) -> Result<BTreeMap<String, ProductStock>, Errors> {
+        self.store(store)?
+            .addr
+            .send(GetStock)
+            .await
+            .map_err(|_| Errors::ActorMsgError)?
+    }
+
+    /// The stock of every store connected to the ecommerce, as the ecommerce knows it.
+    pub async fn stores_stock(
+        &self,
+        ecom: usize,
+    ) -> Result<BTreeMap<String, BTreeMap<String, usize>>, Errors> {
+        self.ecom(ecom)?
+            .send(GetStoresStock)
+            .await
+            .map_err(|_| Errors::ActorMsgError)?
+    }
+
+    /// The leader of the store and the ecommerces it is connected to.
+    pub async fn connections(&self, store: &str) -> Result<Connections, Errors> {
+        self.store(store)?
+            .addr
+            .send(GetConnections)
+            .await
+            .map_err(|_| Errors::ActorMsgError)?
+    }
+
+    fn check_configured(&self, node: &Node) -> Result<(), Errors> {
+        let configured = match node {
+            Node::Ecom(id) => self.config.ecoms.contains(id),
+            Node::Store(id) => self.config.stores.contains_key(id),
+        };
+        if !configured {
+            return Err(Errors::UnknownNodeError);
+        }
+        Ok(())
+    }
+
+    fn ecom(&self, id: usize) -> Result<&Addr<Coordinator>, Errors> {
+        self.check_configured(&Node::Ecom(id))?;
+        self.ecoms.get(&id).ok_or(Errors::NodeDownError)
+    }
+
+    fn store(&self, id: &str) -> Result<&RunningStore, Errors> {
+        self.check_configured(&Node::Store(id.to_string()))?;
+        self.stores.get(id).ok_or(Errors::NodeDownError)
+    }
+
+    /// Starts the actor of the node as the binaries do, with its initial state taken from the configuration.
+    fn start_node(&mut self, node: &Node) -> Result<(), Errors> {
+        let seed = self.config.seed;
+        match node {
+            Node::Ecom(id) => {
+                let tuning = &self.config.ecom_tuning;
+                let coordinator = Coordinator {
+                    online_orders: vec![],
+                    active_stores: HashMap::new(),
+                    active_ecoms: HashMap::new(),
+                    rng: stream_rng(seed, &format!("ecom/{id}/coordinator")),
+                    traces: stream_rng(seed, &format!("ecom/{id}/trace")),
+                    id: *id,
+                    curr_leader: Some(*id),
+                    // The members are connected by the harness, so their addresses are left empty
+                    members: self
+                        .config
+                        .ecoms
+                        .iter()
+                        .map(|id| (*id, Member::default()))
+                        .collect(),
+                    reconnect: tuning.reconnect_policy().seeded(seed),
+                    detached_stores: HashMap::new(),
+                    backorders: HashMap::new(),
+                    backorder_max_wait: Duration::from_secs(tuning.backorder_max_wait_secs),
+                    catalog: self.config.catalog.clone(),
+                    sales: SalesReport::new(),
+                    intake_paused: false,
+                    metrics: Metrics::new(),
+                    election_started: None,
+                    clock: self.clock.shared(),
+                };
+                self.ecoms.insert(*id, coordinator.start());
+            }
+            Node::Store(id) => {
+                let mut stock = StockLedger::new();
+                for (product, product_stock) in &self.config.stores[id] {
+                    stock.add_product(product.clone(), product_stock.clone())?;
+                }
+                let (reserve_sender, mut reserve_receiver) = mpsc::channel(RESERVE_CHANNEL_SIZE);
+                let store = Store {
+                    id: id.clone(),
+                    stock,
+                    reserve_sender,
+                    active_ecoms: HashMap::new(),
+                    connection: true,
+                    leader: 0,
+                    catalog: self.config.catalog.clone(),
+                    sales: SalesReport::new(),
+                    members_sender: None,
+                    offline_log: OfflineLog::new(),
+                    outbox: Outbox::new(),
+                    reservations: HashMap::new(),
+                    next_reservation_id: 0,
+                    draining: None,
+                    metrics: Metrics::new(),
+                    clock: self.clock.shared(),
+                    traces: stream_rng(seed, &format!("store/{id}/trace")),
+                };
+                let addr = store.start();
+                let store_addr = addr.clone();
+                let tuning = self.config.store_tuning.clone();
+                let rng = stream_rng(seed, &format!("store/{id}/reserves_manager"));
+                let clock = self.clock.shared();
+                let reserves = actix::spawn(async move {
+                    reserves_manager(store_addr, &mut reserve_receiver, &tuning, rng, clock).await
+                });
+                self.stores
+                    .insert(id.clone(), RunningStore { addr, reserves });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the nodes can reach each other: both are running and on the same side of the partition, if any.
+    fn reachable(&self, a: &Node, b: &Node) -> bool {
+        let same_side = match &self.partition {
+            Some(side) => side.contains(a) == side.contains(b),
+            None => true,
+        };
+        same_side && self.is_running(a) && self.is_running(b)
+    }
+
+    /// Connects every pair of nodes that can reach each other and are not connected: each ecommerce with the others,
+    /// the one with the smallest id dialing, and every store with each ecommerce.
+    async fn link(&mut self) {
+        let mut pairs = vec![];
+        for (i, a) in self.config.ecoms.iter().enumerate() {
+            for b in &self.config.ecoms[i + 1..] {
+                pairs.push((Node::Ecom(*a.min(b)), Node::Ecom(*a.max(b))));
+            }
+        }
+        for store in self.config.stores.keys() {
+            for ecom in &self.config.ecoms {
+                pairs.push((Node::Ecom(*ecom), Node::Store(store.clone())));
+            }
+        }
+
+        for (a, b) in pairs {
+            let linked = self
+                .links
+                .get(&(a.clone(), b.clone()))
+                .is_some_and(|relay| !relay.is_finished());
+            if linked || !self.reachable(&a, &b) {
+                continue;
+            }
+            let (a_end, b_end, relay) = relayed_connection();
+            self.links.insert((a.clone(), b.clone()), relay);
+            match (&a, &b) {
+                (Node::Ecom(dialer), Node::Ecom(listener)) => {
+                    let _ = self.ecoms[dialer]
+                        .send(NewEcom {
+                            id: listener.to_string(),
+                            stream: a_end,
+                            dialed: true,
+                            closed: None,
+                        })
+                        .await;
+                    let _ = self.ecoms[listener]
+                        .send(NewEcom {
+                            id: dialer.to_string(),
+                            stream: b_end,
+                            dialed: false,
+                            closed: None,
+                        })
+                        .await;
+                }
+                (Node::Ecom(ecom), Node::Store(store)) => {
+                    let _ = self.stores[store]
+                        .addr
+                        .send(NewEcomHandler {
+                            stream: b_end,
+                            ecom_id: ecom.to_string(),
+                            closed: None,
+                        })
+                        .await;
+                    let _ = self.ecoms[ecom]
+                        .send(NewStore {
+                            store_id: store.clone(),
+                            stream: a_end,
+                        })
+                        .await;
+                }
+                _ => {}
+            }
+        }
+        self.settle().await;
+    }
+
+    /// Cuts the links between the pairs of nodes for which `cut` is true. Both ends see the connection lost.
+    fn cut(&mut self, cut: impl Fn(&Node, &Node) -> bool) {
+        self.links.retain(|(a, b), relay| {
+            if cut(a, b) {
+                relay.abort();
+            }
+            !cut(a, b)
+        });
+    }
+}
+
+/// Two ends of a connection that go through a relay task. Aborting the task drops the ends of the relay,
+/// so both nodes see the connection closed, as with a lost TCP connection.
+fn relayed_connection() -> (Connection, Connection, JoinHandle<()>) {
+    let (a_end, mut a_relay) = duplex();
+    let (mut b_relay, b_end) = duplex();
+    let relay = actix::spawn(async move {
+        let _ = tokio::io::copy_bidirectional(&mut a_relay, &mut b_relay).await;
+    });
+    (a_end, b_end, relay)
+}
@@ -0,0 +1,197 @@
+use crate::errors::Errors;
+use crate::store::ProductStock;
+use std::collections::{HashMap, VecDeque};
+
+/// How many movements the ledger keeps if it is not told otherwise.
+pub const DEFAULT_MAX_MOVEMENTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The reason why the stock of a product changed.
+pub enum MovementReason {
+    /// The product was added to the ledger with its initial stock.
+    InitialStock,
+    /// A physical customer bought units of the product.
+    LocalSale,
+    /// Units were reserved for an online order.
+    Reservation,
+    /// An online order was cancelled and its reserved units were released.
+    ReservationCancelled,
+    /// An online order was dispatched, so its reserved units left the store.
+    Dispatch,
+    /// The safety stock of the product was changed.
+    SafetyStockChange,
+    /// New units of the product arrived at the store.
+    Restock,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single change applied to the stock of a product, with the quantity involved and the reason behind it.
+pub struct StockMovement {
+    pub product: String,
+    pub quantity: usize,
+    pub reason: MovementReason,
+}
+
+#[derive(Debug, Clone)]
+/// Every mutation of the stock of the store goes through the ledger. It makes sure that the reserved quantity of a product
+/// never exceeds its available quantity, uses checked arithmetic so no operation can underflow, and records each movement.
+/// Only the last `max_movements` movements are kept, the older ones are already folded into the stock.
+pub struct StockLedger {
+    products: HashMap<String, ProductStock>,
+    movements: VecDeque<StockMovement>,
+    max_movements: usize,
+}
+
+impl Default for StockLedger {
+    fn default() -> Self {
+        StockLedger::with_max_movements(DEFAULT_MAX_MOVEMENTS)
+    }
+}
+
+impl StockLedger {
+    pub fn new() -> Self {
+        StockLedger::default()
+    }
+
+    pub fn with_max_movements(max_movements: usize) -> Self {
+        StockLedger {
+            products: HashMap::new(),
+            movements: VecDeque::new(),
+            max_movements,
+        }
+    }
+
+    /// Adds a product with its initial stock. Fails if the product is already in the ledger
+    /// or if the stock reserves more units than it has.
+    pub fn add_product(&mut self, product: String, stock: ProductStock) -> Result<(), Errors> {
+        if self.products.contains_key(&product) {
+            return Err(Errors::DuplicateProductError);
+        }
+        if stock.reserved_quantity > stock.available_quantity {
+            return Err(Errors::StockInvariantError);
+        }
+        self.record(
+            &product,
+            stock.available_quantity,
+            MovementReason::InitialStock,
+        );
+        self.products.insert(product, stock);
+        Ok(())
+    }
+
+    /// Sells units to a physical customer. The sale can take the safety stock, but not the reserved units.
+    pub fn sell(&mut self, product: &str, quantity: usize) -> Result<(), Errors> {
+        let stock = self.product_mut(product)?;
+        if free_quantity(stock)? < quantity {
+            return Err(Errors::NotEnoughStockError);
+        }
+        stock.available_quantity = stock
+            .available_quantity
+            .checked_sub(quantity)
+            .ok_or(Errors::StockInvariantError)?;
+        self.record(product, quantity, MovementReason::LocalSale);
+        Ok(())
+    }
+
+    /// Reserves units for an online order. The reservation can not take the safety stock.
+    pub fn reserve(&mut self, product: &str, quantity: usize) -> Result<(), Errors> {
+        let stock = self.product_mut(product)?;
+        let needed = quantity
+            .checked_add(stock.safety_stock)
+            .ok_or(Errors::NotEnoughStockError)?;
+        if free_quantity(stock)? < needed {
+            return Err(Errors::NotEnoughStockError);
+        }
+        stock.reserved_quantity = stock
+            .reserved_quantity
+            .checked_add(quantity)
+            .ok_or(Errors::StockInvariantError)?;
+        self.record(product, quantity, MovementReason::Reservation);
+        Ok(())
+    }
+
+    /// Releases units reserved for an online order that was cancelled.
+    pub fn cancel_reservation(&mut self, product: &str, quantity: usize) -> Result<(), Errors> {
+        let stock = self.product_mut(product)?;
+        stock.reserved_quantity = stock
+            .reserved_quantity
+            .checked_sub(quantity)
+            .ok_or(Errors::NotEnoughReservedError)?;
+        self.record(product, quantity, MovementReason::ReservationCancelled);
+        Ok(())
+    }
+
+    /// Takes out of the store the units reserved for an online order that was dispatched.
+    pub fn dispatch(&mut self, product: &str, quantity: usize) -> Result<(), Errors> {
+        let stock = self.product_mut(product)?;
+        let reserved = stock
+            .reserved_quantity
+            .checked_sub(quantity)
+            .ok_or(Errors::NotEnoughReservedError)?;
+        let available = stock
+            .available_quantity
+            .checked_sub(quantity)
+            .ok_or(Errors::StockInvariantError)?;
+        stock.reserved_quantity = reserved;
+        stock.available_quantity = available;
+        self.record(product, quantity, MovementReason::Dispatch);
+        Ok(())
+    }
+
+    /// Adds units of a product that is already in the ledger.
+    pub fn restock(&mut self, product: &str, quantity: usize) -> Result<(), Errors> {
+        let stock = self.product_mut(product)?;
+        stock.available_quantity = stock
+            .available_quantity
+            .checked_add(quantity)
+            .ok_or(Errors::StockInvariantError)?;
+        self.record(product, quantity, MovementReason::Restock);
+        Ok(())
+    }
+
+    /// Changes the amount of units of a product that online reservations can not take.
+    pub fn set_safety_stock(&mut self, product: &str, quantity: usize) -> Result<(), Errors> {
+        let stock = self.product_mut(product)?;
+        stock.safety_stock = quantity;
+        self.record(product, quantity, MovementReason::SafetyStockChange);
+        Ok(())
+    }
+
+    pub fn get(&self, product: &str) -> Option<&ProductStock> {
+        self.products.get(product)
+    }
+
+    pub fn products(&self) -> &HashMap<String, ProductStock> {
+        &self.products
+    }
+
+    /// The most recent movements, oldest first.
+    pub fn movements(&self) -> &VecDeque<StockMovement> {
+        &self.movements
+    }
+
+    fn product_mut(&mut self, product: &str) -> Result<&mut ProductStock, Errors> {
+        self.products
+            .get_mut(product)
+            .ok_or(Errors::ProductNotFoundError)
+    }
+
+    fn record(&mut self, product: &str, quantity: usize, reason: MovementReason) {
+        self.movements.push_back(StockMovement {
+            product: product.to_string(),
+            quantity,
+            reason,
+        });
+        while self.movements.len() > self.max_movements {
+            self.movements.pop_front();
+        }
+    }
+}
+
+/// Units of the product that are neither reserved nor sold.
+fn free_quantity(stock: &ProductStock) -> Result<usize, Errors> {
+    stock
+        .available_quantity
+        .checked_sub(stock.reserved_quantity)
+        .ok_or(Errors::StockInvariantError)
+}
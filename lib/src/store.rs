@@ -1,38 +1,264 @@
 extern crate actix;
 
+use crate::catalog::Catalog;
+use crate::clock::SharedClock;
+use crate::config::StoreTuning;
 use crate::ecom_handler::{Answer, EcomHandler, Stop};
 use crate::errors::Errors;
-use actix::{Actor, Addr, AsyncContext, Context, Handler, Message, StreamHandler};
-use std::collections::HashMap;
+use crate::logging::order_span;
+use crate::metrics::{Counter, Histogram, Metrics};
+use crate::offline_log::OfflineLog;
+use crate::outbox::Outbox;
+use crate::random::NodeRng;
+use crate::sales_report::{Channel, SalesReport};
+use crate::stock_ledger::StockLedger;
+use crate::trace::{split_trace, with_trace, TraceId};
+use crate::transport::Connection;
+use actix::fut::{wrap_future, ActorFutureExt};
+use actix::{
+    Actor, ActorContext, Addr, AsyncContext, Context, Handler, Message, SpawnHandle, StreamHandler,
+};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tokio::io::{split, AsyncBufReadExt, BufReader};
-use tokio::net::TcpStream;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
 use tokio_stream::wrappers::LinesStream;
+use tracing::{debug, error, info, warn, Instrument};
 
-#[derive(Debug, Clone)]
-/// The product stock is represented by a tuple of two `usize`, the first one is the available quantity and the second one is the reserved quantity.
+#[derive(Debug, Clone, Serialize)]
+/// The product stock is represented by three `usize`: the available quantity, the reserved quantity and the safety stock.
+/// The safety stock is the amount of units that online reservations can not take, so they are left for in-person customers.
 pub struct ProductStock {
     pub available_quantity: usize,
     pub reserved_quantity: usize,
+    pub safety_stock: usize,
 }
 
-/// The store is represented by a `HashMap` of products and their stock, a sender to the reserves manager,
-/// a hashmap of the ecommerces that are connected to the store and a `bool` that indicates
-/// if the store is connected to the coordinator. The leader is represented by a `String` and the leader write by an `Option<WriteHalf<TcpStream>>`.
+/// The Store actor: the stock of a physical store, its sales to in-person customers and the online orders it reserves
+/// for the ecommerces connected to it. Every change of the stock is told to the leader.
 pub struct Store {
-    pub stock: HashMap<String, ProductStock>,
+    pub id: String,
+    pub stock: StockLedger,
+    /// Where each new reservation is sent, for the reserves manager to dispatch it.
     pub reserve_sender: Sender<String>,
     pub active_ecoms: HashMap<String, Addr<EcomHandler>>,
+    /// Whether the store was told to connect to the network.
     pub connection: bool,
     pub leader: usize,
+    /// The price of each product, so every sale can be added up in the sales report.
+    pub catalog: Catalog,
+    pub sales: SalesReport,
+    /// Told about every ecommerce that joins or leaves the cluster, so the store connects to it or drops it.
+    pub members_sender: Option<Sender<MemberEvent>>,
+    /// The stock events that could not be told to the leader, replayed when the store reconnects.
+    pub offline_log: OfflineLog,
+    /// The stock events sent to the leader that it did not acknowledge yet.
+    pub outbox: Outbox,
+    /// The online orders waiting to be dispatched, by id.
+    pub reservations: HashMap<u64, Reservation>,
+    pub next_reservation_id: u64,
+    /// Set while the store drains before leaving the network, it takes no new reservations meanwhile.
+    pub draining: Option<SpawnHandle>,
+    /// Shared with its EcomHandlers.
+    pub metrics: Metrics,
+    /// Gives the time the reservations are open and the drain waits for.
+    pub clock: SharedClock,
+    /// The trace stream of the seed of the node, for the online orders that arrive without a trace.
+    pub traces: NodeRng,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// Units of a product reserved for an online order until it is dispatched or cancelled, along with the seconds
+/// the order has to be dispatched, when it was reserved and the trace of the order.
+pub struct Reservation {
+    pub product: String,
+    pub quantity: usize,
+    pub time_limit: usize,
+    #[serde(skip)]
+    pub since: Instant,
+    pub trace_id: TraceId,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+/// The connections of the store: its leader, whether it is connected to it, the connected ecommerces and whether it is draining.
+pub struct Connections {
+    pub leader: String,
+    pub leader_connected: bool,
+    pub ecoms: Vec<String>,
+    pub draining: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A change in the ecommerces of the cluster, as seen by the store.
+pub enum MemberEvent {
+    /// An ecommerce joined the cluster and accepts stores in `address`.
+    Joined { ecom_id: String, address: String },
+    /// An ecommerce could not be reached again after its connection was closed.
+    Left { ecom_id: String },
+}
+
+impl Store {
+    /// Tells the task that connects the store with the ecommerces about a change in the cluster.
+    fn notify_member_event(&self, event: MemberEvent) -> Result<(), Errors> {
+        if let Some(sender) = &self.members_sender {
+            sender.try_send(event).map_err(|_| Errors::ChannelError)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a sale to the sales report, valued at the price of the product in the catalog.
+    fn record_sale(&mut self, channel: Channel, product: &str, quantity: usize) {
+        let price_cents = self
+            .catalog
+            .get(product)
+            .map(|entry| entry.price_cents)
+            .unwrap_or_default();
+        self.sales
+            .record_sale(&self.id, channel, product, quantity, 1, price_cents);
+    }
+
+    fn connections(&self) -> Connections {
+        let leader = self.leader.to_string();
+        let mut ecoms: Vec<String> = self.active_ecoms.keys().cloned().collect();
+        ecoms.sort();
+        Connections {
+            leader_connected: self.active_ecoms.contains_key(&leader),
+            leader,
+            ecoms,
+            draining: self.draining.is_some(),
+        }
+    }
+
+    /// Sends a line to the leader. Returns false if the leader is not connected.
+    fn send_to_leader(&self, answer: String) -> bool {
+        match self.active_ecoms.get(&self.leader.to_string()) {
+            Some(ecom_addr) => {
+                let sent = ecom_addr.try_send(Answer { answer });
+                self.metrics.sent(sent, "ecom_handler");
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gives back the units of a reservation that will not be dispatched, and tells the leader within the trace of the order.
+    fn cancel_reservation(
+        &mut self,
+        product: &str,
+        quantity: usize,
+        trace_id: TraceId,
+    ) -> Result<(), Errors> {
+        self.stock.cancel_reservation(product, quantity)?;
+        self.metrics.inc(Counter::OrdersCancelled, &[]);
+        self.sales
+            .record_cancellation(&self.id, product, quantity, 1);
+        self.notify_leader(with_trace(
+            &format!("CANCELLED,{product},{quantity}"),
+            trace_id,
+        ));
+        Ok(())
+    }
+
+    /// Records how long a reservation was open, labelled by whether it was dispatched or cancelled.
+    fn close_reservation(&self, reservation: &Reservation, outcome: &str) {
+        self.metrics.observe(
+            Histogram::ReservationSeconds,
+            &[("outcome", outcome)],
+            self.open_for(reservation).as_secs_f64(),
+        );
+    }
+
+    /// How long a reservation has been open.
+    fn open_for(&self, reservation: &Reservation) -> Duration {
+        self.clock
+            .now()
+            .saturating_duration_since(reservation.since)
+    }
+
+    /// Sends `FinishDrain` to the store once `wait` has passed on its clock.
+    fn finish_drain_after(&self, ctx: &mut Context<Self>, wait: Duration) -> SpawnHandle {
+        let sleep = wrap_future::<_, Self>(self.clock.sleep(wait));
+        ctx.spawn(sleep.map(|_, _, ctx| ctx.notify(FinishDrain)))
+    }
+
+    /// Ends the wait for the reservations: the ones still open are cancelled. If the leader still has to acknowledge
+    /// notifications, the store waits up to `OUTBOX_FLUSH_WAIT` for them before leaving the network.
+    fn finish_drain(&mut self, ctx: &mut Context<Self>) {
+        for (_, reservation) in std::mem::take(&mut self.reservations) {
+            self.close_reservation(&reservation, "cancelled");
+            info!(
+                product = %reservation.product,
+                quantity = reservation.quantity,
+                trace_id = %reservation.trace_id,
+                "Se cancela la reserva para desconectarse"
+            );
+            if let Err(e) = self.cancel_reservation(
+                &reservation.product,
+                reservation.quantity,
+                reservation.trace_id,
+            ) {
+                error!(error = ?e, "No se pudo cancelar la reserva");
+            }
+        }
+        if !self.outbox.is_empty() && self.active_ecoms.contains_key(&self.leader.to_string()) {
+            info!(
+                pending = self.outbox.pending().len(),
+                "Esperando que el lider confirme las notificaciones"
+            );
+            self.draining = Some(self.finish_drain_after(ctx, OUTBOX_FLUSH_WAIT));
+            return;
+        }
+        self.leave_network();
+    }
+
+    /// Sends `answer` to every connected ecommerce.
+    fn tell_ecoms(&self, answer: &str) {
+        for addr in self.active_ecoms.values() {
+            let _ = addr.try_send(Answer {
+                answer: answer.to_string(),
+            });
+        }
+    }
+
+    /// Tells every ecommerce that the store leaves with _BYE_, so they stop sending it orders, and closes the connections.
+    fn leave_network(&mut self) {
+        for addr in self.active_ecoms.values() {
+            let _ = addr.try_send(Answer {
+                answer: BYE_MSG.to_string(),
+            });
+            let _ = addr.try_send(Stop);
+        }
+        self.active_ecoms.clear();
+        self.draining = None;
+        info!("Store desconectada");
+    }
+
+    /// Sends a stock notification to the leader through the outbox. If the leader is not connected,
+    /// it is kept in the offline log instead.
+    fn notify_leader(&mut self, answer: String) {
+        if !self.active_ecoms.contains_key(&self.leader.to_string()) {
+            if self.offline_log.record(&answer) {
+                info!(
+                    answer,
+                    "Sin conexion con el lider, se guarda para cuando se reconecte"
+                );
+            }
+            return;
+        }
+        let line = self.outbox.push(answer);
+        self.send_to_leader(line);
+    }
 }
 
 impl Actor for Store {
     type Context = Context<Self>;
 
     fn started(&mut self, _: &mut Self::Context) {
-        println!("¡Store is alive!");
+        debug!(id = %self.id, "Store iniciada");
     }
 }
 
@@ -50,27 +276,30 @@ impl Handler<LocalProductOrder> for Store {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: LocalProductOrder, ctx: &mut Self::Context) -> Result<(), Errors> {
-        if let Some(product) = self.stock.get_mut(&msg.product) {
-            if product.available_quantity - product.reserved_quantity >= msg.quantity {
-                product.available_quantity -= msg.quantity;
-
-                // The physical sale needs to be sent to the ecommerce so they can update their stock.
-                let ans_msg = format!("APPROVED,{},{}", msg.product, msg.quantity);
-                let ans = AnswerEcom { answer: ans_msg };
-                ctx.notify(ans);
-                Ok(())
-            } else {
-                Err(Errors::NotEnoughStockError)
-            }
-        } else {
-            Err(Errors::ProductNotFoundError)
-        }
+        self.stock.sell(&msg.product, msg.quantity)?;
+        self.record_sale(Channel::Physical, &msg.product, msg.quantity);
+        self.metrics.inc(
+            Counter::OrdersApproved,
+            &[("channel", Channel::Physical.as_str())],
+        );
+
+        // The physical sale needs to be sent to the ecommerce so they can update their stock.
+        let ans_msg = format!(
+            "APPROVED,{},{},{}",
+            msg.product,
+            msg.quantity,
+            Channel::Physical.as_str()
+        );
+        let ans = AnswerEcom { answer: ans_msg };
+        ctx.notify(ans);
+        Ok(())
     }
 }
 
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
 /// The store can communicate with the coordinator for multiple reasons, this message is used to send a message to the coordinator.
+/// The stock notifications go through the outbox, or to the offline log if the leader is not connected.
 pub struct AnswerEcom {
     pub answer: String,
 }
@@ -79,8 +308,10 @@ impl Handler<AnswerEcom> for Store {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: AnswerEcom, _: &mut Self::Context) -> Result<(), Errors> {
-        if let Some(ecom_addr) = self.active_ecoms.get(&self.leader.to_string()) {
-            let _ = ecom_addr.try_send(Answer { answer: msg.answer });
+        if Outbox::is_notification(&msg.answer) {
+            self.notify_leader(msg.answer);
+        } else {
+            self.send_to_leader(msg.answer);
         }
 
         Ok(())
@@ -89,17 +320,31 @@ impl Handler<AnswerEcom> for Store {
 
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
-/// This message can be used to _**kill**_ the connection of the store to the network.
+/// This message can be used to _**kill**_ the connection of the store to the network right away, without draining it.
 pub struct KillConnection {}
 
 impl Handler<KillConnection> for Store {
     type Result = Result<(), Errors>;
 
-    fn handle(&mut self, _: KillConnection, _: &mut Self::Context) -> Result<(), Errors> {
+    fn handle(&mut self, _: KillConnection, ctx: &mut Self::Context) -> Result<(), Errors> {
         for addr in self.active_ecoms.values() {
             let _ = addr.try_send(Stop);
         }
         self.active_ecoms.clear();
+        // A drain cut short by the kill is over, so the store takes reservations again once it reconnects
+        if let Some(timeout) = self.draining.take() {
+            ctx.cancel_future(timeout);
+        }
+        Ok(())
+    }
+}
+
+/// Stops the store right away, as if its process was killed: nothing is drained and the ecommerces are not told.
+impl Handler<Stop> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: Stop, ctx: &mut Self::Context) -> Result<(), Errors> {
+        ctx.stop();
         Ok(())
     }
 }
@@ -113,6 +358,11 @@ impl Handler<Connect> for Store {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, _: Connect, ctx: &mut Self::Context) -> Result<(), Errors> {
+        // Connecting again while draining keeps the store in the network
+        if let Some(timeout) = self.draining.take() {
+            ctx.cancel_future(timeout);
+            self.tell_ecoms(UNDRAIN_MSG);
+        }
         self.connection = true;
         let ask_learder_msg = AnswerEcom {
             answer: "LEADER,?,?".to_string(),
@@ -122,87 +372,273 @@ impl Handler<Connect> for Store {
     }
 }
 
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message _drains_ the store before disconnecting it. The ecommerces are told with _DRAIN_ so they stop sending it orders,
+/// and the store takes no new reservations and waits up to `max_wait` for the open ones to be dispatched. The ones still open
+/// by then are cancelled, and the store leaves the network.
+pub struct Drain {
+    pub max_wait: Duration,
+}
+
+impl Handler<Drain> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: Drain, ctx: &mut Self::Context) -> Result<(), Errors> {
+        if self.draining.is_some() {
+            return Ok(());
+        }
+        self.tell_ecoms(DRAIN_MSG);
+        if self.reservations.is_empty() {
+            self.finish_drain(ctx);
+            return Ok(());
+        }
+        info!(
+            reservations = self.reservations.len(),
+            "Esperando las reservas antes de desconectarse"
+        );
+        self.draining = Some(self.finish_drain_after(ctx, msg.max_wait));
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// Sent when the store waited enough while draining, for its reservations or for the leader to acknowledge its notifications.
+pub struct FinishDrain;
+
+impl Handler<FinishDrain> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: FinishDrain, ctx: &mut Self::Context) -> Result<(), Errors> {
+        if self.draining.take().is_some() {
+            if self.reservations.is_empty() {
+                self.leave_network();
+            } else {
+                self.finish_drain(ctx);
+            }
+        }
+        Ok(())
+    }
+}
+
 // ------------------------ E-COMMERCE PURPOSE MESSAGES ------------------------
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
 /// With this message we _reserve_ a quantity of a product for a certain time limit.  
-/// Returns an error if the product is not in the stock or if the quantity asked is not available.  
+/// Returns an error if the product is not in the stock or if the quantity asked is not available without taking the safety stock.  
 /// The quantity is represented by a `usize`, the product with a `String` and the time limit with a `usize`.
+/// The trace of the order goes with the reservation until it is dispatched, and one is started if the order has none.
 pub struct ReserveProduct {
     pub product: String,
     pub quantity: usize,
     pub time_limit: usize,
+    pub trace_id: Option<TraceId>,
 }
 
 impl Handler<ReserveProduct> for Store {
     type Result = Result<(), Errors>;
     fn handle(&mut self, msg: ReserveProduct, _: &mut Context<Self>) -> Result<(), Errors> {
-        if let Some(product) = self.stock.get_mut(&msg.product) {
-            if product.available_quantity - product.reserved_quantity >= msg.quantity {
-                // The quantity asked is reserved
-                product.reserved_quantity += msg.quantity;
-
-                // We notify the reserves manager that a new reserve was made
-                let reserve = format!("{},{},{}", msg.product, msg.quantity, msg.time_limit);
-                match self.reserve_sender.try_send(reserve) {
-                    Ok(_) => {}
-                    Err(_) => eprintln!(
-                        "[STORE] Error a la hora de enviar la nueva reserva al 'reserves_manager'"
-                    ),
-                }
-                Ok(())
-            } else {
-                eprintln!("[STORE] No hay stock suficiente del producto.");
-                Err(Errors::NotEnoughStockError)
+        let order = format!("{},{}", msg.product, msg.quantity);
+        let trace_id = msg
+            .trace_id
+            .unwrap_or_else(|| TraceId::from_rng(&mut self.traces));
+        let _span = order_span(trace_id, &order).entered();
+        self.metrics.inc(Counter::OrdersReceived, &[]);
+        if self.draining.is_some() {
+            // The order was sent before the ecommerce knew the store drains, so the leader is told it is cancelled
+            info!("La store se esta desconectando, se cancela el pedido");
+            self.notify_leader(with_trace(&format!("CANCELLED,{order}"), trace_id));
+            return Err(Errors::StoreDrainingError);
+        }
+        // The quantity asked is reserved. Online reservations can not take the units kept as safety stock
+        match self.stock.reserve(&msg.product, msg.quantity) {
+            Ok(_) => {}
+            Err(Errors::ProductNotFoundError) => {
+                warn!("No se encontro el producto en el stock");
+                return Err(Errors::ProductNotFoundError);
+            }
+            Err(e) => {
+                info!("No hay stock suficiente del producto");
+                return Err(e);
             }
+        }
+
+        let reservation_id = self.next_reservation_id;
+        self.next_reservation_id += 1;
+        self.reservations.insert(
+            reservation_id,
+            Reservation {
+                product: msg.product.clone(),
+                quantity: msg.quantity,
+                time_limit: msg.time_limit,
+                since: self.clock.now(),
+                trace_id,
+            },
+        );
+
+        // We notify the reserves manager that a new reserve was made
+        let reserve = with_trace(
+            &format!("{order},{},{reservation_id}", msg.time_limit),
+            trace_id,
+        );
+        let sent = self.reserve_sender.try_send(reserve);
+        if !self.metrics.sent(sent, "reserves_manager") {
+            error!(
+                reservation_id,
+                "No se pudo enviar la nueva reserva al administrador de reservas"
+            );
         } else {
-            eprintln!("[STORE] No se encontro el producto en el stock.");
-            Err(Errors::ProductNotFoundError)
+            debug!(reservation_id, "Reserva creada");
         }
+        Ok(())
     }
 }
 
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
 /// Once reserved a product, we can _cancel_ the reservation and effectively subtract the reserved quantity from the stock.
+/// A reservation that was already cancelled while draining the store is ignored.
 pub struct DispatchProduct {
     pub product: String,
     pub quantity: usize,
     pub cancel_order: bool,
+    pub reservation_id: u64,
+    pub trace_id: TraceId,
 }
 
 impl Handler<DispatchProduct> for Store {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, msg: DispatchProduct, ctx: &mut Context<Self>) -> Result<(), Errors> {
-        if msg.cancel_order {
-            // We remove the reserved products
-            if let Some(product) = self.stock.get_mut(&msg.product) {
-                product.reserved_quantity -= msg.quantity;
+        let _span =
+            order_span(msg.trace_id, &format!("{},{}", msg.product, msg.quantity)).entered();
+        let reservation = match self.reservations.remove(&msg.reservation_id) {
+            Some(reservation) => reservation,
+            None => {
+                debug!(
+                    reservation_id = msg.reservation_id,
+                    "La reserva ya no esta abierta"
+                );
+                return Ok(());
             }
+        };
+        let outcome = if msg.cancel_order {
+            "cancelled"
+        } else {
+            "dispatched"
+        };
+        self.close_reservation(&reservation, outcome);
+        if reservation.time_limit > 0 {
+            self.metrics.observe(
+                Histogram::DispatchDeadlineRatio,
+                &[],
+                self.open_for(&reservation).as_secs_f64() / reservation.time_limit as f64,
+            );
+        }
 
-            // We tell ecom that the order was cancelled
-            let answer = format!("CANCELLED,{},{}", msg.product, msg.quantity);
-            let ans_msg = AnswerEcom { answer };
-            ctx.notify(ans_msg);
-            Ok(())
-        } else if let Some(product) = self.stock.get_mut(&msg.product) {
-            // We discount the products that have been dispatched
-            product.reserved_quantity -= msg.quantity;
-            product.available_quantity -= msg.quantity;
-
-            // We tell ecom that the order was approved
-            let answer = format!("APPROVED,{},{}", msg.product, msg.quantity);
-            let ans_msg = AnswerEcom { answer };
-            ctx.notify(ans_msg);
-            Ok(())
+        let result = if msg.cancel_order {
+            // We remove the reserved products and tell ecom that the order was cancelled
+            self.cancel_reservation(&msg.product, msg.quantity, msg.trace_id)
         } else {
-            Err(Errors::ProductNotFoundError)
+            // We discount the products that have been dispatched
+            self.stock.dispatch(&msg.product, msg.quantity).map(|_| {
+                self.record_sale(Channel::Online, &msg.product, msg.quantity);
+                self.metrics.inc(
+                    Counter::OrdersApproved,
+                    &[("channel", Channel::Online.as_str())],
+                );
+
+                // We tell ecom that the order was approved
+                let answer = format!(
+                    "APPROVED,{},{},{}",
+                    msg.product,
+                    msg.quantity,
+                    Channel::Online.as_str()
+                );
+                self.notify_leader(with_trace(&answer, msg.trace_id));
+            })
+        };
+
+        // The last open reservation ends the drain
+        if self.reservations.is_empty() {
+            if let Some(timeout) = self.draining.take() {
+                ctx.cancel_future(timeout);
+                self.finish_drain(ctx);
+            }
         }
+        result
+    }
+}
+
+/// This async function receives online orders from the Store actor. For each order it will create a task that will sleep
+/// for a random number of seconds drawn from `rng`, simulating the time that is waited for the reserved product to be dispatched.
+/// The seconds are waited on `clock`.
+pub async fn reserves_manager(
+    store: Addr<Store>,
+    reserve_receiver: &mut Receiver<String>,
+    tuning: &StoreTuning,
+    mut rng: NodeRng,
+    clock: SharedClock,
+) -> Result<(), Errors> {
+    while let Some(msg) = reserve_receiver.recv().await {
+        // Seconds that will simulate how long the product is reserved
+        let seconds: u64 = rng.gen_range(tuning.min_dispatch_secs, tuning.max_dispatch_secs);
+        let duration_to_dispatch = Duration::from_secs(seconds);
+
+        // Creation of DispatchProduct message
+        let (fields, trace_id) = split_trace(&msg);
+        let trace_id = trace_id.unwrap_or_else(|| TraceId::from_rng(&mut rng));
+        let split: Vec<&str> = fields.split(',').collect();
+        let product = split[0].to_string();
+        let quantity = <usize as FromStr>::from_str(split[1]).map_err(|_| Errors::CouldNotParse)?;
+        let time_limit = <u64 as FromStr>::from_str(split[2]).map_err(|_| Errors::CouldNotParse)?;
+        let reservation_id =
+            <u64 as FromStr>::from_str(split[3]).map_err(|_| Errors::CouldNotParse)?;
+        let cancel_order = time_limit < seconds;
+        let dispatch_msg = DispatchProduct {
+            product,
+            quantity,
+            cancel_order,
+            reservation_id,
+            trace_id,
+        };
+
+        // Cloned store address to notify when to dispatch the product
+        let cloned_addr = store.clone();
+        let wait = clock.sleep(duration_to_dispatch);
+
+        // Tasks will run concurrently waiting for each product to be ready to be dispatched
+        let span = order_span(trace_id, &format!("{},{}", split[0], split[1]));
+        tokio::spawn(
+            async move {
+                wait.await;
+                match cloned_addr.try_send(dispatch_msg) {
+                    Ok(_) => debug!(reservation_id, "Reserva lista para despachar"),
+                    Err(_) => error!(
+                        reservation_id,
+                        "No se pudo avisar que la reserva esta lista"
+                    ),
+                };
+            }
+            .instrument(span),
+        );
     }
+    Ok(())
 }
 
 // ------------------------ STATE CHECKING PURPOSE MESSAGES ------------------------
+/// Sent after replaying the offline log. An ecommerce that lost the stock of the store answers asking for all of it.
+pub const RESYNC_MSG: &str = "RESYNC";
+/// Sent to every ecommerce when the store leaves the network after draining.
+pub const BYE_MSG: &str = "BYE";
+/// Sent to every ecommerce when the store starts draining, so no more orders are routed to it.
+pub const DRAIN_MSG: &str = "DRAIN";
+/// Sent to every ecommerce when the store connects again while draining, so orders are routed to it again.
+pub const UNDRAIN_MSG: &str = "UNDRAIN";
+/// How long a drained store waits for the leader to acknowledge its notifications before leaving the network.
+pub const OUTBOX_FLUSH_WAIT: Duration = Duration::from_secs(2);
+
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
 /// This message is used to _show the state_ of the store in the console.
@@ -212,16 +648,68 @@ impl Handler<ShowState> for Store {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, _: ShowState, _: &mut Context<Self>) -> Result<(), Errors> {
-        for (product, product_stock) in &self.stock {
+        for (product, product_stock) in self.stock.products() {
             println!(
-                "Product: [{product}] has a quantity of [{}]  and has [{}] reserved.",
-                product_stock.available_quantity, product_stock.reserved_quantity
+                "Product: [{product}] has a quantity of [{}], has [{}] reserved and keeps [{}] as safety stock.",
+                product_stock.available_quantity,
+                product_stock.reserved_quantity,
+                product_stock.safety_stock
             );
         }
         Ok(())
     }
 }
 
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message is used to _show the open reservations_ of the store in the console, by id.
+pub struct ShowReservations;
+
+impl Handler<ShowReservations> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: ShowReservations, _: &mut Context<Self>) -> Result<(), Errors> {
+        if self.reservations.is_empty() {
+            println!("No hay reservas abiertas");
+        }
+        let mut reservations: Vec<(&u64, &Reservation)> = self.reservations.iter().collect();
+        reservations.sort_by_key(|(id, _)| **id);
+        for (id, reservation) in reservations {
+            println!(
+                "Reservation: [{id}] of [{}] units of [{}]",
+                reservation.quantity, reservation.product
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message is used to _show the connections_ of the store in the console: the leader and each connected ecommerce.
+pub struct ShowConnections;
+
+impl Handler<ShowConnections> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, _: ShowConnections, _: &mut Context<Self>) -> Result<(), Errors> {
+        let connections = self.connections();
+        let state = if connections.leader_connected {
+            "conectado"
+        } else {
+            "desconectado"
+        };
+        println!("Lider: [{}] ({state})", connections.leader);
+        for ecom_id in connections.ecoms {
+            println!("Ecommerce [{ecom_id}]: conectado");
+        }
+        if connections.draining {
+            println!("La store se esta desconectando");
+        }
+        Ok(())
+    }
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<(), Errors>")]
 /// This message is used to _share the state_ of the store with the coordinator through the message _AnswerEcom_.
@@ -231,7 +719,7 @@ impl Handler<ShareStock> for Store {
     type Result = Result<(), Errors>;
 
     fn handle(&mut self, _: ShareStock, ctx: &mut Context<Self>) -> Result<(), Errors> {
-        for (product, product_stock) in &self.stock {
+        for (product, product_stock) in self.stock.products() {
             let prod_str = format!("STOCK,{},{}", product, product_stock.available_quantity);
             ctx.notify(AnswerEcom { answer: prod_str });
         }
@@ -243,8 +731,9 @@ impl Handler<ShareStock> for Store {
 #[rtype(result = "Result<(), Errors>")]
 /// This message is used to _share the state_ of the store with the coordinator through the message _AnswerEcom_.
 pub struct NewEcomHandler {
-    pub stream: TcpStream,
+    pub stream: Connection,
     pub ecom_id: String,
+    pub closed: Option<oneshot::Sender<()>>,
 }
 
 impl Handler<NewEcomHandler> for Store {
@@ -259,9 +748,16 @@ impl Handler<NewEcomHandler> for Store {
                 ecom: write,
                 ecom_id: msg.ecom_id.clone(),
                 store: store_ctx.address(),
+                closed: msg.closed,
+                metrics: self.metrics.clone(),
             }
         });
 
+        if self.draining.is_some() {
+            let _ = ecom_addr.try_send(Answer {
+                answer: DRAIN_MSG.to_string(),
+            });
+        }
         self.active_ecoms.insert(msg.ecom_id, ecom_addr);
         Ok(())
     }
@@ -285,14 +781,319 @@ impl Handler<NewLeader> for Store {
             <usize as FromStr>::from_str(&msg.ecom_id).map_err(|_| Errors::CouldNotParse)?;
 
         self.leader = new_ecom_id;
-        ctx.notify(ShareStock);
+        // The notifications the previous leader did not acknowledge may have never reached it. A leader that did not
+        // have the stock of the store drops them once the stock is shared, since it already counts them
+        for line in self.outbox.pending() {
+            self.send_to_leader(line);
+        }
+        if self.offline_log.is_empty() {
+            ctx.notify(ShareStock);
+        } else {
+            // The leader may still have the stock of the store from before it disconnected, so only what changed is sent
+            for answer in self.offline_log.replay() {
+                ctx.notify(AnswerEcom { answer });
+            }
+            ctx.notify(AnswerEcom {
+                answer: RESYNC_MSG.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message tells the store that an ecommerce _acknowledged_ every notification up to `seq`.
+/// Only the acknowledgements of the leader empty the outbox. A drained store leaves the network once its outbox is empty.
+pub struct Acknowledge {
+    pub ecom_id: String,
+    pub seq: u64,
+}
+
+impl Handler<Acknowledge> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: Acknowledge, ctx: &mut Context<Self>) -> Result<(), Errors> {
+        if msg.ecom_id != self.leader.to_string() {
+            return Ok(());
+        }
+        self.outbox.ack(msg.seq);
+        if self.outbox.is_empty() && self.reservations.is_empty() {
+            if let Some(timeout) = self.draining.take() {
+                ctx.cancel_future(timeout);
+                self.leave_network();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message tells the store that a new ecommerce _joined_ the cluster. It contains its id and the address
+/// where it accepts stores, which is handed to the task that connects the store with the ecommerces.
+pub struct NewMember {
+    pub ecom_id: String,
+    pub address: String,
+}
+
+impl Handler<NewMember> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: NewMember, _: &mut Context<Self>) -> Result<(), Errors> {
+        if self.active_ecoms.contains_key(&msg.ecom_id) {
+            return Ok(());
+        }
+        self.notify_member_event(MemberEvent::Joined {
+            ecom_id: msg.ecom_id,
+            address: msg.address,
+        })
+    }
+}
 
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message tells the store that the connection with an ecommerce was _closed_ from the side of the ecommerce.
+/// The ecommerce stops being an active one until the connection is made again.
+pub struct EcomConnectionClosed {
+    pub ecom_id: String,
+}
+
+impl Handler<EcomConnectionClosed> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: EcomConnectionClosed, _: &mut Context<Self>) -> Result<(), Errors> {
+        info!(ecom_id = %msg.ecom_id, "El ecommerce cerro la conexion con la store");
+        self.active_ecoms.remove(&msg.ecom_id);
         Ok(())
     }
 }
 
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message _restocks_ a product: new units of it arrived at the store. The leader is told the new stock.
+/// Returns an error if the product is not in the stock.
+pub struct Restock {
+    pub product: String,
+    pub quantity: usize,
+}
+
+impl Handler<Restock> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: Restock, _: &mut Context<Self>) -> Result<(), Errors> {
+        self.stock.restock(&msg.product, msg.quantity)?;
+        let available = self
+            .stock
+            .get(&msg.product)
+            .map(|stock| stock.available_quantity)
+            .unwrap_or_default();
+        info!(
+            product = %msg.product,
+            quantity = msg.quantity,
+            available,
+            "Llegaron unidades del producto"
+        );
+        self.notify_leader(format!("STOCK,{},{available}", msg.product));
+        Ok(())
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message _cancels an open reservation_ before it is dispatched. Its units are given back and the leader is told.
+/// Returns an error if there is no open reservation with that id.
+pub struct CancelReservation {
+    pub reservation_id: u64,
+}
+
+impl Handler<CancelReservation> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: CancelReservation, ctx: &mut Context<Self>) -> Result<(), Errors> {
+        let reservation = self
+            .reservations
+            .remove(&msg.reservation_id)
+            .ok_or(Errors::ReservationNotFoundError)?;
+        self.close_reservation(&reservation, "cancelled");
+        info!(
+            reservation_id = msg.reservation_id,
+            product = %reservation.product,
+            quantity = reservation.quantity,
+            "Se cancela la reserva"
+        );
+        let result = self.cancel_reservation(
+            &reservation.product,
+            reservation.quantity,
+            reservation.trace_id,
+        );
+
+        // The last open reservation ends the drain
+        if self.reservations.is_empty() {
+            if let Some(timeout) = self.draining.take() {
+                ctx.cancel_future(timeout);
+                self.finish_drain(ctx);
+            }
+        }
+        result
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), Errors>")]
+/// This message _changes the safety stock_ of a product, the units that online reservations can not take.
+/// Returns an error if the product is not in the stock.
+pub struct SetSafetyStock {
+    pub product: String,
+    pub quantity: usize,
+}
+
+impl Handler<SetSafetyStock> for Store {
+    type Result = Result<(), Errors>;
+
+    fn handle(&mut self, msg: SetSafetyStock, _: &mut Context<Self>) -> Result<(), Errors> {
+        self.stock.set_safety_stock(&msg.product, msg.quantity)
+    }
+}
+
+pub struct GetSalesReport;
+
+/// Returns the running sales report of the store
+impl Message for GetSalesReport {
+    type Result = Result<SalesReport, Errors>;
+}
+
+impl Handler<GetSalesReport> for Store {
+    type Result = Result<SalesReport, Errors>;
+
+    fn handle(&mut self, _: GetSalesReport, _: &mut Context<Self>) -> Result<SalesReport, Errors> {
+        Ok(self.sales.clone())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The work of the store that is not finished yet: whether it is still draining, the reservations still open,
+/// the notifications the leader did not acknowledge and the stock events kept while there was no leader.
+pub struct PendingWork {
+    pub draining: bool,
+    pub open_reservations: usize,
+    pub unacknowledged: Vec<String>,
+    pub offline: Vec<String>,
+}
+
+pub struct GetPendingWork;
+
+/// Returns the work of the store that is not finished yet, to know when it can be shut down
+impl Message for GetPendingWork {
+    type Result = Result<PendingWork, Errors>;
+}
+
+impl Handler<GetPendingWork> for Store {
+    type Result = Result<PendingWork, Errors>;
+
+    fn handle(&mut self, _: GetPendingWork, _: &mut Context<Self>) -> Result<PendingWork, Errors> {
+        Ok(PendingWork {
+            draining: self.draining.is_some(),
+            open_reservations: self.reservations.len(),
+            unacknowledged: self.outbox.pending(),
+            offline: self.offline_log.lines(),
+        })
+    }
+}
+
+pub struct GetStock;
+
+/// Returns the stock of every product of the store, sorted by product
+impl Message for GetStock {
+    type Result = Result<BTreeMap<String, ProductStock>, Errors>;
+}
+
+impl Handler<GetStock> for Store {
+    type Result = Result<BTreeMap<String, ProductStock>, Errors>;
+
+    fn handle(
+        &mut self,
+        _: GetStock,
+        _: &mut Context<Self>,
+    ) -> Result<BTreeMap<String, ProductStock>, Errors> {
+        Ok(self
+            .stock
+            .products()
+            .iter()
+            .map(|(product, stock)| (product.clone(), stock.clone()))
+            .collect())
+    }
+}
+
+pub struct GetReservations;
+
+/// Returns the open reservations of the store, by id
+impl Message for GetReservations {
+    type Result = Result<BTreeMap<u64, Reservation>, Errors>;
+}
+
+impl Handler<GetReservations> for Store {
+    type Result = Result<BTreeMap<u64, Reservation>, Errors>;
+
+    fn handle(
+        &mut self,
+        _: GetReservations,
+        _: &mut Context<Self>,
+    ) -> Result<BTreeMap<u64, Reservation>, Errors> {
+        Ok(self
+            .reservations
+            .iter()
+            .map(|(id, reservation)| (*id, reservation.clone()))
+            .collect())
+    }
+}
+
+pub struct GetConnections;
+
+/// Returns the leader of the store and the ecommerces it is connected to
+impl Message for GetConnections {
+    type Result = Result<Connections, Errors>;
+}
+
+impl Handler<GetConnections> for Store {
+    type Result = Result<Connections, Errors>;
+
+    fn handle(&mut self, _: GetConnections, _: &mut Context<Self>) -> Result<Connections, Errors> {
+        Ok(self.connections())
+    }
+}
+
 // ------------------------ TEST PURPOSE MESSAGES ------------------------
 
+pub struct _GetOutbox;
+/// Returns the notifications that the leader did not acknowledge yet
+impl Message for _GetOutbox {
+    type Result = Result<Outbox, Errors>;
+}
+
+impl Handler<_GetOutbox> for Store {
+    type Result = Result<Outbox, Errors>;
+
+    fn handle(&mut self, _: _GetOutbox, _: &mut Context<Self>) -> Result<Outbox, Errors> {
+        Ok(self.outbox.clone())
+    }
+}
+
+pub struct _GetOfflineLog;
+/// Returns the stock events kept while the leader was not connected
+impl Message for _GetOfflineLog {
+    type Result = Result<OfflineLog, Errors>;
+}
+
+impl Handler<_GetOfflineLog> for Store {
+    type Result = Result<OfflineLog, Errors>;
+
+    fn handle(&mut self, _: _GetOfflineLog, _: &mut Context<Self>) -> Result<OfflineLog, Errors> {
+        Ok(self.offline_log.clone())
+    }
+}
+
 pub struct _GetStock;
 /// Returns the stock of the store (hashmap)
 impl Message for _GetStock {
@@ -307,7 +1108,7 @@ impl Handler<_GetStock> for Store {
         _: _GetStock,
         _: &mut Self::Context,
     ) -> Result<HashMap<String, ProductStock>, String> {
-        Ok(self.stock.clone())
+        Ok(self.stock.products().clone())
     }
 }
 
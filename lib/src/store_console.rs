@@ -0,0 +1,123 @@
+use crate::errors::Errors;
+use std::str::FromStr;
+
+const CONNECT_INPUT: &str = "C";
+const KILL_INPUT: &str = "K";
+const STOCK_INPUT: &str = "S";
+const SAFETY_INPUT: &str = "F";
+const RESTOCK_INPUT: &str = "A";
+const RESERVATIONS_INPUT: &str = "R";
+const CANCEL_INPUT: &str = "X";
+const SALE_INPUT: &str = "V";
+const CONNECTIONS_INPUT: &str = "L";
+const HELP_INPUTS: [&str; 2] = ["H", "help"];
+
+/// The usage of every command of the terminal of the store.
+pub const USAGE: [&str; 10] = [
+    "C: conecta la store a la red",
+    "K: desconecta la store de la red, despues de drenarla",
+    "S: muestra el stock",
+    "F,producto,cantidad: cambia el stock de seguridad de un producto",
+    "A,producto,cantidad: agrega unidades de un producto",
+    "R: muestra las reservas abiertas",
+    "X,reserva: cancela una reserva abierta",
+    "V,producto,cantidad: vende a un cliente fisico",
+    "L: muestra el lider y los ecommerce conectados",
+    "H o help: muestra esta ayuda",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A command typed in the terminal of the store.
+pub enum StoreCommand {
+    Connect,
+    Disconnect,
+    ShowStock,
+    SetSafetyStock { product: String, quantity: usize },
+    Restock { product: String, quantity: usize },
+    ShowReservations,
+    CancelReservation { reservation_id: u64 },
+    Sale { product: String, quantity: usize },
+    ShowConnections,
+    Help,
+}
+
+impl StoreCommand {
+    /// Parses a line of the terminal. Commands with arguments separate them with commas, as in `A,ZAP-001,10`.
+    /// The error tells what is wrong with the line.
+    pub fn parse(input: &str) -> Result<Self, Errors> {
+        let split: Vec<&str> = input.trim().split(',').map(str::trim).collect();
+        let command = split[0];
+        let args = &split[1..];
+        match command {
+            CONNECT_INPUT => no_args(command, args, StoreCommand::Connect),
+            KILL_INPUT => no_args(command, args, StoreCommand::Disconnect),
+            STOCK_INPUT => no_args(command, args, StoreCommand::ShowStock),
+            RESERVATIONS_INPUT => no_args(command, args, StoreCommand::ShowReservations),
+            CONNECTIONS_INPUT => no_args(command, args, StoreCommand::ShowConnections),
+            help if HELP_INPUTS.contains(&help) => no_args(command, args, StoreCommand::Help),
+            SAFETY_INPUT => {
+                let (product, quantity) = product_and_quantity(command, args, true)?;
+                Ok(StoreCommand::SetSafetyStock { product, quantity })
+            }
+            RESTOCK_INPUT => {
+                let (product, quantity) = product_and_quantity(command, args, false)?;
+                Ok(StoreCommand::Restock { product, quantity })
+            }
+            SALE_INPUT => {
+                let (product, quantity) = product_and_quantity(command, args, false)?;
+                Ok(StoreCommand::Sale { product, quantity })
+            }
+            CANCEL_INPUT => match args {
+                [id] => <u64 as FromStr>::from_str(id)
+                    .map(|reservation_id| StoreCommand::CancelReservation { reservation_id })
+                    .map_err(|_| {
+                        Errors::InvalidCommand(format!("la reserva [{id}] no es un numero"))
+                    }),
+                _ => Err(Errors::InvalidCommand(format!(
+                    "se espera [{CANCEL_INPUT},reserva]"
+                ))),
+            },
+            "" => Err(Errors::InvalidCommand(
+                "no se escribio ningun comando".to_string(),
+            )),
+            other => Err(Errors::InvalidCommand(format!(
+                "el comando [{other}] no existe"
+            ))),
+        }
+    }
+}
+
+/// Checks that a command without arguments got none.
+fn no_args(command: &str, args: &[&str], parsed: StoreCommand) -> Result<StoreCommand, Errors> {
+    if args.is_empty() {
+        Ok(parsed)
+    } else {
+        Err(Errors::InvalidCommand(format!(
+            "el comando [{command}] no lleva argumentos"
+        )))
+    }
+}
+
+/// Parses the arguments `producto,cantidad`. A quantity of zero is only valid when `zero_allowed`.
+fn product_and_quantity(
+    command: &str,
+    args: &[&str],
+    zero_allowed: bool,
+) -> Result<(String, usize), Errors> {
+    let (product, quantity) = match args {
+        [product, quantity] if !product.is_empty() => (product, quantity),
+        _ => {
+            return Err(Errors::InvalidCommand(format!(
+                "se espera [{command},producto,cantidad]"
+            )))
+        }
+    };
+    let quantity = <usize as FromStr>::from_str(quantity)
+        .map_err(|_| Errors::InvalidCommand(format!("la cantidad [{quantity}] no es un numero")))?;
+    if quantity == 0 && !zero_allowed {
+        return Err(Errors::InvalidCommand(
+            "la cantidad tiene que ser mayor a cero".to_string(),
+        ));
+    }
+    Ok((product.to_string(), quantity))
+}
@@ -0,0 +1,60 @@
+use crate::errors::Errors;
+use rand::Rng;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Marks the field of a protocol message that carries the trace of the order.
+const TRACE_PREFIX: &str = "trace=";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Identifies an order along its whole path, from the ecommerce that takes it to the store that dispatches it.
+/// It is minted once with each order and travels in every message about it, so the logs of every process
+/// can be put together into the timeline of the order.
+pub struct TraceId(u64);
+
+impl TraceId {
+    /// A trace drawn from the generator of the node, so it is the same on every run with the same seed.
+    pub fn from_rng(rng: &mut impl Rng) -> Self {
+        TraceId(rng.gen())
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for TraceId {
+    type Err = Errors;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(text, 16)
+            .map(TraceId)
+            .map_err(|_| Errors::CouldNotParse)
+    }
+}
+
+impl Serialize for TraceId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Adds the trace to the end of a protocol message, as its last field.
+pub fn with_trace(line: &str, trace_id: TraceId) -> String {
+    format!("{line},{TRACE_PREFIX}{trace_id}")
+}
+
+/// Takes the trace out of a protocol message, leaving the fields that come before it.
+/// Messages from nodes that do not send traces are returned as they are.
+pub fn split_trace(line: &str) -> (&str, Option<TraceId>) {
+    match line.rsplit_once(',') {
+        Some((rest, last)) => match last.strip_prefix(TRACE_PREFIX).map(TraceId::from_str) {
+            Some(Ok(trace_id)) => (rest, Some(trace_id)),
+            _ => (line, None),
+        },
+        None => (line, None),
+    }
+}
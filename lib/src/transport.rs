@@ -0,0 +1,101 @@
+use crate::errors::Errors;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Addresses that start with this prefix are Unix domain sockets, the rest are `ip:port` TCP addresses.
+pub const UNIX_PREFIX: &str = "unix:";
+/// Bytes an in-memory connection holds before a write waits for the other side to read.
+const DUPLEX_BUFFER: usize = 64 * 1024;
+
+/// A byte stream the nodes talk over: a TCP connection, a Unix domain socket or, in tests and simulations,
+/// an in-memory pipe. The actors only read lines from it and write lines to it.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static> Transport for T {}
+
+/// A connection with another node, over any transport.
+pub type Connection = Box<dyn Transport>;
+
+/// Two connected ends of an in-memory connection: what is written to one is read from the other.
+pub fn duplex() -> (Connection, Connection) {
+    let (a, b) = tokio::io::duplex(DUPLEX_BUFFER);
+    (Box::new(a), Box::new(b))
+}
+
+/// Connects to `address`, over a Unix domain socket if it starts with `unix:` and over TCP otherwise.
+pub async fn connect(address: &str) -> Result<Connection, Errors> {
+    match address.strip_prefix(UNIX_PREFIX) {
+        Some(path) => connect_unix(path).await,
+        None => TcpStream::connect(address)
+            .await
+            .map(|stream| Box::new(stream) as Connection)
+            .map_err(|_| Errors::ConnectionError),
+    }
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: &str) -> Result<Connection, Errors> {
+    tokio::net::UnixStream::connect(path)
+        .await
+        .map(|stream| Box::new(stream) as Connection)
+        .map_err(|_| Errors::ConnectionError)
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_: &str) -> Result<Connection, Errors> {
+    Err(Errors::ConnectionError)
+}
+
+/// Accepts connections from other nodes, on a TCP address or on a Unix domain socket.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+impl Listener {
+    /// Listens on `address`, written as for `connect()`. The socket file left by a previous run is replaced.
+    pub async fn bind(address: &str) -> Result<Listener, Errors> {
+        match address.strip_prefix(UNIX_PREFIX) {
+            Some(path) => Listener::bind_unix(path),
+            None => TcpListener::bind(address)
+                .await
+                .map(Listener::Tcp)
+                .map_err(|_| Errors::ConnectionError),
+        }
+    }
+
+    #[cfg(unix)]
+    fn bind_unix(path: &str) -> Result<Listener, Errors> {
+        use std::os::unix::fs::FileTypeExt;
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if metadata.file_type().is_socket() {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+        tokio::net::UnixListener::bind(path)
+            .map(Listener::Unix)
+            .map_err(|_| Errors::ConnectionError)
+    }
+
+    #[cfg(not(unix))]
+    fn bind_unix(_: &str) -> Result<Listener, Errors> {
+        Err(Errors::ConnectionError)
+    }
+
+    pub async fn accept(&self) -> Result<Connection, Errors> {
+        match self {
+            Listener::Tcp(listener) => listener
+                .accept()
+                .await
+                .map(|(stream, _)| Box::new(stream) as Connection)
+                .map_err(|_| Errors::ConnectionError),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener
+                .accept()
+                .await
+                .map(|(stream, _)| Box::new(stream) as Connection)
+                .map_err(|_| Errors::ConnectionError),
+        }
+    }
+}
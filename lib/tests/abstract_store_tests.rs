@@ -3,29 +3,59 @@
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::time::Duration;
 
     use actix::prelude::*;
+    use lib::catalog::{Catalog, CatalogEntry};
+    use lib::clock::real_clock;
+    use lib::metrics::Metrics;
+    use lib::outbox::sequenced;
+    use lib::random::stream_rng;
+    use lib::reconnect::ReconnectPolicy;
+    use lib::sales_report::{Channel, SalesReport};
+    use lib::transport::duplex;
     use lib::{
-        abstract_store::{AbstractStore, AddStock, UpdateStock},
-        coordinator::Coordinator,
+        abstract_store::{_GetStock, AbstractStore, AddStock, UpdateStock},
+        coordinator::{
+            _GetActiveStores, _GetBackorders, Coordinator, GetSalesReport, NewOrder, NewStore,
+        },
     };
+    use tokio::io::AsyncWriteExt;
 
     #[actix_rt::test]
     async fn test_abstract_store_add_stock() {
         let store_id = "1".to_string();
         let coord = Coordinator {
             id: 0,
-            curr_leader: Some(1 as usize),
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
             active_stores: HashMap::new(),
             active_ecoms: HashMap::new(),
             online_orders: vec![],
-            rng: rand::thread_rng(),
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
         };
         let abs_store = AbstractStore {
             write: None,
             store_id: store_id.clone(),
             stock: HashMap::new(),
             orders_buffer: vec![],
+            resumed: false,
+            last_seq: 0,
+            session: 0,
+            metrics: Metrics::new(),
+            left: false,
+            draining: false,
             coordinator: coord.start(),
         };
         let addr = abs_store.start();
@@ -38,7 +68,7 @@ mod tests {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        assert_eq!(stock.contains_key(&"Camisa".to_string()), true);
+        assert!(stock.contains_key("Camisa"));
     }
 
     #[actix_rt::test]
@@ -46,17 +76,35 @@ mod tests {
         let store_id = "1".to_string();
         let coord = Coordinator {
             id: 0,
-            curr_leader: Some(1 as usize),
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
             active_stores: HashMap::new(),
             active_ecoms: HashMap::new(),
             online_orders: vec![],
-            rng: rand::thread_rng(),
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
         };
         let abs_store = AbstractStore {
             write: None,
             store_id: store_id.clone(),
             stock: HashMap::new(),
             orders_buffer: vec![],
+            resumed: false,
+            last_seq: 0,
+            session: 0,
+            metrics: Metrics::new(),
+            left: false,
+            draining: false,
             coordinator: coord.start(),
         };
         let addr = abs_store.start();
@@ -69,7 +117,7 @@ mod tests {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        assert_eq!(stock.contains_key(&"2".to_string()), false);
+        assert!(!stock.contains_key("2"));
     }
 
     #[actix_rt::test]
@@ -77,17 +125,35 @@ mod tests {
         let store_id = "1".to_string();
         let coord = Coordinator {
             id: 0,
-            curr_leader: Some(1 as usize),
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
             active_stores: HashMap::new(),
             active_ecoms: HashMap::new(),
             online_orders: vec![],
-            rng: rand::thread_rng(),
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
         };
         let abs_store = AbstractStore {
             write: None,
             store_id: store_id.clone(),
             stock: HashMap::new(),
             orders_buffer: vec![],
+            resumed: false,
+            last_seq: 0,
+            session: 0,
+            metrics: Metrics::new(),
+            left: false,
+            draining: false,
             coordinator: coord.start(),
         };
         let addr = abs_store.start();
@@ -105,10 +171,7 @@ mod tests {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        let valor = match stock.get(&"Campera".to_string()) {
-            Some(valor) => valor,
-            None => &0,
-        };
+        let valor = stock.get("Campera").unwrap_or(&0);
         assert_eq!(valor, &4);
     }
 
@@ -117,17 +180,35 @@ mod tests {
         let store_id = "1".to_string();
         let coord = Coordinator {
             id: 0,
-            curr_leader: Some(1 as usize),
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
             active_stores: HashMap::new(),
             active_ecoms: HashMap::new(),
             online_orders: vec![],
-            rng: rand::thread_rng(),
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
         };
         let abs_store = AbstractStore {
             write: None,
             store_id: store_id.clone(),
             stock: HashMap::new(),
             orders_buffer: vec![],
+            resumed: false,
+            last_seq: 0,
+            session: 0,
+            metrics: Metrics::new(),
+            left: false,
+            draining: false,
             coordinator: coord.start(),
         };
         let addr = abs_store.start();
@@ -145,15 +226,176 @@ mod tests {
             Ok(Err(_)) => Err(()),
             Err(_) => Err(()),
         };
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
         let stock = match addr.send(lib::abstract_store::_GetStock).await {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        let valor = match stock.get(&"Campera".to_string()) {
-            Some(valor) => valor,
-            None => &0,
-        };
+        let valor = stock.get("Campera").unwrap_or(&0);
         assert_eq!(valor, &2);
     }
+
+    #[actix_rt::test]
+    async fn test_abstract_store_resent_notifications_before_stock() {
+        let coord = Coordinator {
+            id: 1,
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
+            active_stores: HashMap::new(),
+            active_ecoms: HashMap::new(),
+            online_orders: vec![],
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
+        };
+        let addr = coord.start();
+        let (stream, mut store) = duplex();
+        let _ = addr
+            .send(NewStore {
+                store_id: "1".to_string(),
+                stream,
+            })
+            .await;
+
+        // The new leader gets the sale the previous leader did not acknowledge, then the stock that already counts it
+        let lines = format!(
+            "{}\nSTOCK,Campera,8\n{}\n",
+            sequenced(5, 1, "APPROVED,Campera,2,online"),
+            sequenced(5, 2, "APPROVED,Campera,1,online")
+        );
+        assert!(store.write_all(lines.as_bytes()).await.is_ok());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let abs_store = match addr.send(_GetActiveStores).await {
+            Ok(Ok(stores)) => stores["1"].clone(),
+            _ => panic!("La store no se conecto"),
+        };
+        let stock = match abs_store.send(_GetStock).await {
+            Ok(Ok(stock)) => stock,
+            _ => HashMap::new(),
+        };
+        assert_eq!(stock.get("Campera"), Some(&7));
+    }
+
+    #[actix_rt::test]
+    async fn test_abstract_store_counts_orders_of_replayed_offline_log() {
+        let mut catalog = Catalog::new();
+        let _ = catalog.add(CatalogEntry {
+            sku: "Campera".to_string(),
+            name: "campera".to_string(),
+            price_cents: 100,
+            category: "Ropa".to_string(),
+        });
+        let coord = Coordinator {
+            id: 1,
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
+            active_stores: HashMap::new(),
+            active_ecoms: HashMap::new(),
+            online_orders: vec![],
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog,
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
+        };
+        let addr = coord.start();
+        let (stream, mut store) = duplex();
+        let _ = addr
+            .send(NewStore {
+                store_id: "1".to_string(),
+                stream,
+            })
+            .await;
+
+        // Three sales and two cancellations made while the store was disconnected, compacted in its offline log
+        let lines = "STOCK,Campera,10\nAPPROVED,Campera,5,physical,orders=3\nCANCELLED,Campera,4,orders=2\n";
+        assert!(store.write_all(lines.as_bytes()).await.is_ok());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let report = match addr.send(GetSalesReport).await {
+            Ok(Ok(report)) => report,
+            _ => panic!("No se pudo obtener el reporte de ventas"),
+        };
+        let by_channel = report.totals_by_channel();
+        let physical = by_channel.get(&Channel::Physical);
+        assert_eq!(physical.map(|t| t.orders_approved), Some(3));
+        assert_eq!(physical.map(|t| t.units_sold), Some(5));
+        let online = by_channel.get(&Channel::Online);
+        assert_eq!(online.map(|t| t.orders_cancelled), Some(2));
+        assert_eq!(online.map(|t| t.units_cancelled), Some(4));
+    }
+
+    #[actix_rt::test]
+    async fn test_abstract_store_sends_no_orders_to_draining_store() {
+        let mut catalog = Catalog::new();
+        let _ = catalog.add(CatalogEntry {
+            sku: "Campera".to_string(),
+            name: "campera".to_string(),
+            price_cents: 100,
+            category: "Ropa".to_string(),
+        });
+        let coord = Coordinator {
+            id: 1,
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
+            active_stores: HashMap::new(),
+            active_ecoms: HashMap::new(),
+            online_orders: vec![],
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog,
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
+        };
+        let addr = coord.start();
+        let (stream, mut store) = duplex();
+        let _ = addr
+            .send(NewStore {
+                store_id: "1".to_string(),
+                stream,
+            })
+            .await;
+        assert!(store.write_all(b"STOCK,Campera,10\nDRAIN\n").await.is_ok());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // The only store drains, so the order waits as a backorder instead of reaching it
+        let _ = addr
+            .send(NewOrder {
+                order: "Campera,2".to_string(),
+                visited_stores: vec![],
+                trace_id: None,
+                origin: None,
+            })
+            .await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let backorders = match addr.send(_GetBackorders).await {
+            Ok(Ok(backorders)) => backorders,
+            _ => HashMap::new(),
+        };
+        assert_eq!(backorders.get("Campera").map(|queue| queue.len()), Some(1));
+    }
 }
@@ -0,0 +1,153 @@
+// Module dedicated to testing the routes and the HTTP plumbing of the admin API
+
+#[cfg(test)]
+mod tests {
+    use lib::admin_api::{
+        read_request, serve_http, EcomRoute, HttpRequest, HttpResponse, StoreRoute,
+    };
+    use lib::errors::Errors;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpStream;
+
+    fn request(method: &str, path: &str, body: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_store_routes() {
+        assert_eq!(
+            StoreRoute::parse(&request("GET", "/stock", "")),
+            Ok(StoreRoute::Stock)
+        );
+        assert_eq!(
+            StoreRoute::parse(&request(
+                "POST",
+                "/restock",
+                "{\"product\": \"ZAP-001\", \"quantity\": 10}"
+            )),
+            Ok(StoreRoute::Restock {
+                product: "ZAP-001".to_string(),
+                quantity: 10
+            })
+        );
+        assert_eq!(
+            StoreRoute::parse(&request("DELETE", "/reservations/3", "")),
+            Ok(StoreRoute::CancelReservation { reservation_id: 3 })
+        );
+        assert_eq!(
+            StoreRoute::parse(&request("DELETE", "/reservations/tres", "")),
+            Err(Errors::InvalidCommand(
+                "la reserva [tres] no es un numero".to_string()
+            ))
+        );
+        assert_eq!(
+            StoreRoute::parse(&request(
+                "POST",
+                "/sales",
+                "{\"product\": \"ZAP-001\", \"quantity\": 0}"
+            )),
+            Err(Errors::InvalidCommand(
+                "la cantidad tiene que ser mayor a cero".to_string()
+            ))
+        );
+        assert!(matches!(
+            StoreRoute::parse(&request("POST", "/sales", "ZAP-001,2")),
+            Err(Errors::InvalidCommand(_))
+        ));
+        assert_eq!(
+            StoreRoute::parse(&request("POST", "/stock", "")),
+            Err(Errors::UnknownRouteError)
+        );
+    }
+
+    #[test]
+    fn test_ecom_routes() {
+        assert_eq!(
+            EcomRoute::parse(&request("GET", "/leader/", "")),
+            Ok(EcomRoute::Leader)
+        );
+        assert_eq!(
+            EcomRoute::parse(&request(
+                "POST",
+                "/orders",
+                "{\"product\": \"ZAP-001\", \"quantity\": 2}"
+            )),
+            Ok(EcomRoute::Order {
+                product: "ZAP-001".to_string(),
+                quantity: 2
+            })
+        );
+        assert_eq!(
+            EcomRoute::parse(&request("POST", "/stores/2/disconnect", "")),
+            Ok(EcomRoute::DisconnectStore {
+                store_id: "2".to_string()
+            })
+        );
+        assert_eq!(
+            EcomRoute::parse(&request("POST", "/intake/pause", "")),
+            Ok(EcomRoute::Pause)
+        );
+        assert_eq!(
+            EcomRoute::parse(&request("GET", "/reservations", "")),
+            Err(Errors::UnknownRouteError)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_read_request_with_body() {
+        let raw = "POST /orders?debug=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 13\r\n\r\n{\"quantity\":2}";
+        let mut reader = BufReader::new(raw.as_bytes());
+        let parsed = read_request(&mut reader).await;
+        assert_eq!(parsed, Ok(request("POST", "/orders", "{\"quantity\":2")));
+
+        let mut reader = BufReader::new("\r\n".as_bytes());
+        assert!(matches!(
+            read_request(&mut reader).await,
+            Err(Errors::InvalidCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_error_status() {
+        assert_eq!(
+            HttpResponse::from_error(Errors::ReservationNotFoundError).status,
+            404
+        );
+        assert_eq!(HttpResponse::from_error(Errors::NoStockError).status, 409);
+        let response = HttpResponse::from_error(Errors::InvalidCommand("mal".to_string()));
+        assert_eq!(response.status, 400);
+        assert_eq!(response.body, "{\"error\":\"mal\"}");
+    }
+
+    #[actix_rt::test]
+    async fn test_serve_http() {
+        let address = "127.0.0.8:7400";
+        actix::spawn(serve_http(
+            address.to_string(),
+            |request: HttpRequest| async move {
+                match StoreRoute::parse(&request) {
+                    Ok(_) => HttpResponse::accepted(),
+                    Err(e) => HttpResponse::from_error(e),
+                }
+            },
+        ));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut stream = match TcpStream::connect(address).await {
+            Ok(stream) => stream,
+            Err(e) => panic!("No se pudo conectar a la API: {:?}", e),
+        };
+        let _ = stream
+            .write_all(b"POST /connect HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await;
+        let mut answer = String::new();
+        let _ = stream.read_to_string(&mut answer).await;
+        assert!(answer.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(answer.ends_with("\r\n\r\n{\"ok\":true}"));
+    }
+}
@@ -0,0 +1,46 @@
+// Module dedicated to testing the product catalog
+
+#[cfg(test)]
+mod tests {
+    use lib::catalog::{parse_price, Catalog, CatalogEntry};
+    use lib::errors::Errors;
+
+    #[test]
+    fn test_catalog_load_file() {
+        let catalog = match Catalog::load("../txt_files/catalog.txt") {
+            Ok(catalog) => catalog,
+            Err(e) => panic!("No se pudo cargar el catalogo: {:?}", e),
+        };
+        let entry = catalog.get("ZAP-001");
+        assert_eq!(entry.map(|e| e.name.as_str()), Some("zapatillas"));
+        assert_eq!(entry.map(|e| e.price_cents), Some(4500000));
+        assert!(!catalog.contains("sandia"));
+    }
+
+    #[test]
+    fn test_catalog_rejects_duplicate_sku() {
+        let entry = CatalogEntry {
+            sku: "REM-001".to_string(),
+            name: "remera".to_string(),
+            price_cents: 1200000,
+            category: "Indumentaria".to_string(),
+        };
+        let mut catalog = Catalog::new();
+        assert!(catalog.add(entry.clone()).is_ok());
+        assert_eq!(catalog.add(entry), Err(Errors::DuplicateProductError));
+        assert_eq!(
+            catalog.check("sandia").map(|e| e.sku.clone()),
+            Err(Errors::UnknownProductError)
+        );
+    }
+
+    #[test]
+    fn test_catalog_parse_price() {
+        assert_eq!(parse_price("1500"), Ok(150000));
+        assert_eq!(parse_price("1500.5"), Ok(150050));
+        assert_eq!(parse_price("1500.05"), Ok(150005));
+        assert!(parse_price("15.005").is_err());
+        assert!(parse_price("quince").is_err());
+        assert!(parse_price("-3").is_err());
+    }
+}
@@ -0,0 +1,60 @@
+// Module dedicated to testing the virtual clock used by tests and simulations
+
+#[cfg(test)]
+mod tests {
+    use lib::clock::{Clock, VirtualClock};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[actix_rt::test]
+    async fn test_virtual_sleep_ends_when_advanced() {
+        let clock = VirtualClock::new();
+        let start = clock.now();
+        let sleep = clock.sleep(Duration::from_secs(30));
+        let waiting = actix::spawn(sleep);
+
+        clock.advance(Duration::from_secs(29)).await;
+        assert!(!waiting.is_finished());
+        assert_eq!(clock.sleepers(), 1);
+
+        clock.advance(Duration::from_secs(1)).await;
+        assert!(waiting.is_finished());
+        assert_eq!(clock.sleepers(), 0);
+        assert_eq!(clock.now() - start, Duration::from_secs(30));
+        assert_eq!(clock.elapsed(), Duration::from_secs(30));
+    }
+
+    #[actix_rt::test]
+    async fn test_advance_wakes_tasks_that_sleep_again() {
+        let clock = VirtualClock::new();
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let task_clock = clock.clone();
+        let task_ticks = ticks.clone();
+        actix::spawn(async move {
+            loop {
+                task_clock.sleep(Duration::from_secs(2)).await;
+                task_ticks.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        tokio::task::yield_now().await;
+
+        clock.advance(Duration::from_secs(9)).await;
+        assert_eq!(ticks.load(Ordering::SeqCst), 4);
+        clock.advance(Duration::from_secs(1)).await;
+        assert_eq!(ticks.load(Ordering::SeqCst), 5);
+    }
+
+    #[actix_rt::test]
+    async fn test_dropped_sleep_is_not_waiting() {
+        let clock = VirtualClock::new();
+        let sleep = clock.sleep(Duration::from_secs(5));
+        assert_eq!(clock.sleepers(), 1);
+        drop(sleep);
+        assert_eq!(clock.sleepers(), 0);
+
+        // A wait of zero ends without advancing the clock
+        clock.sleep(Duration::ZERO).await;
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+    }
+}
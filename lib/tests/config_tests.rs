@@ -0,0 +1,268 @@
+// Module dedicated to testing the configuration of the binaries
+
+#[cfg(test)]
+mod tests {
+    use lib::config::{EcomConfig, StoreConfig};
+    use lib::errors::Errors;
+    use lib::logging::LogFormat;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        std::iter::once("bin")
+            .chain(args.iter().copied())
+            .map(|arg| arg.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_ecom_config_parses_toml() {
+        let text = r#"
+            id = 1
+            ecoms_address = "127.0.0.1:6000"
+            stores_address = "127.0.0.1:6001"
+            orders_file = "../txt_files/online_orders1.txt"
+            catalog_file = "../txt_files/catalog.txt"
+
+            [[peers]]
+            id = 2
+            address = "127.0.0.2:6000"
+
+            [tuning]
+            backorder_max_wait_secs = 5
+        "#;
+        let config = match EcomConfig::from_toml(text) {
+            Ok(config) => config,
+            Err(e) => panic!("No se pudo leer la configuracion: {:?}", e),
+        };
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(config.peers.len(), 1);
+        assert_eq!(config.peers[0].address, "127.0.0.2:6000");
+        assert_eq!(config.tuning.backorder_max_wait_secs, 5);
+        // Settings that are not in the file keep their default
+        assert_eq!(config.tuning.max_order_delay_secs, 6);
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_field() {
+        let result = StoreConfig::from_toml("id = \"1\"\nstok_file = \"stock.txt\"\n");
+        match result {
+            Err(Errors::InvalidConfig(msg)) => assert!(msg.contains("stok_file")),
+            other => panic!("Se esperaba un error de configuracion: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_config_error_names_bad_peer_address() {
+        let result = EcomConfig::from_args(&args(&[
+            "--id",
+            "1",
+            "--ecoms-address",
+            "127.0.0.1:6000",
+            "--stores-address",
+            "127.0.0.1:6001",
+            "--orders-file",
+            "../txt_files/online_orders1.txt",
+            "--catalog-file",
+            "../txt_files/catalog.txt",
+            "--peers",
+            "2@127.0.0.2",
+        ]));
+        match result {
+            Err(Errors::InvalidConfig(msg)) => assert!(msg.starts_with("peers[0].address")),
+            other => panic!("Se esperaba un error de configuracion: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unix_socket_addresses() {
+        let base = [
+            "--id",
+            "1",
+            "--ecoms-address",
+            "unix:ecom1.sock",
+            "--stores-address",
+            "unix:ecom1_stores.sock",
+            "--orders-file",
+            "../txt_files/online_orders1.txt",
+            "--catalog-file",
+            "../txt_files/catalog.txt",
+        ];
+        let with_peer: Vec<&str> = base
+            .iter()
+            .copied()
+            .chain(["--peers", "2@unix:ecom2.sock@127.0.0.1:6001"])
+            .collect();
+        let config = EcomConfig::from_args(&args(&with_peer));
+        assert_eq!(
+            config.map(|config| config.peers[0].address.clone()),
+            Ok("unix:ecom2.sock".to_string())
+        );
+
+        let with_path: Vec<&str> = base
+            .iter()
+            .copied()
+            .chain(["--seeds", "unix:/tmp/ecom2.sock"])
+            .collect();
+        match EcomConfig::from_args(&args(&with_path)) {
+            Err(Errors::InvalidConfig(msg)) => assert!(msg.starts_with("seeds[0]")),
+            other => panic!("Se esperaba un error de configuracion: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_command_line_overrides_config_file() {
+        let config = StoreConfig::from_args(&args(&[
+            "--config",
+            "../config/store1.toml",
+            "--stock-file",
+            "../txt_files/stock2.txt",
+            "--orders-file",
+            "../txt_files/client_orders2.txt",
+            "--catalog-file",
+            "../txt_files/catalog.txt",
+            "--tuning.max-dispatch-secs",
+            "3",
+        ]));
+        let config = match config {
+            Ok(config) => config,
+            Err(e) => panic!("No se pudo leer la configuracion: {:?}", e),
+        };
+        assert_eq!(config.id, "1");
+        assert_eq!(config.stock_file, "../txt_files/stock2.txt");
+        assert_eq!(config.tuning.max_dispatch_secs, 3);
+        assert_eq!(config.ecommerces.len(), 3);
+    }
+
+    #[test]
+    fn test_admin_api_is_optional_and_local_by_default() {
+        let base = [
+            "--config",
+            "../config/ecom1.toml",
+            "--orders-file",
+            "../txt_files/online_orders1.txt",
+            "--catalog-file",
+            "../txt_files/catalog.txt",
+        ];
+        let config = EcomConfig::from_args(&args(&base));
+        assert_eq!(config.map(|config| config.admin_address()), Ok(None));
+
+        let with_port: Vec<&str> = base
+            .iter()
+            .copied()
+            .chain(["--admin-port", "8080"])
+            .collect();
+        let config = EcomConfig::from_args(&args(&with_port));
+        assert_eq!(
+            config.map(|config| config.admin_address()),
+            Ok(Some("127.0.0.1:8080".to_string()))
+        );
+
+        let bad_host: Vec<&str> = with_port
+            .iter()
+            .copied()
+            .chain(["--admin-host", "localhost"])
+            .collect();
+        assert!(matches!(
+            EcomConfig::from_args(&args(&bad_host)),
+            Err(Errors::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_log_level_and_format() {
+        let base = [
+            "--config",
+            "../config/store1.toml",
+            "--stock-file",
+            "../txt_files/stock.txt",
+            "--orders-file",
+            "../txt_files/client_orders.txt",
+            "--catalog-file",
+            "../txt_files/catalog.txt",
+        ];
+        let config = StoreConfig::from_args(&args(&base));
+        assert_eq!(
+            config.map(|config| (config.log_level, config.log_format)),
+            Ok((String::new(), LogFormat::Text))
+        );
+
+        let with_filters: Vec<&str> = base
+            .iter()
+            .copied()
+            .chain([
+                "--log-level",
+                "warn,lib::store=debug",
+                "--log-format",
+                "json",
+            ])
+            .collect();
+        let config = StoreConfig::from_args(&args(&with_filters));
+        assert_eq!(
+            config.map(|config| (config.log_level, config.log_format)),
+            Ok(("warn,lib::store=debug".to_string(), LogFormat::Json))
+        );
+
+        for bad in [
+            ["--log-level", "lib::store=ruidoso"],
+            ["--log-format", "xml"],
+        ] {
+            let bad_args: Vec<&str> = base.iter().copied().chain(bad).collect();
+            assert!(matches!(
+                StoreConfig::from_args(&args(&bad_args)),
+                Err(Errors::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_seed() {
+        let base = [
+            "--config",
+            "../config/store1.toml",
+            "--stock-file",
+            "../txt_files/stock.txt",
+            "--orders-file",
+            "../txt_files/client_orders.txt",
+            "--catalog-file",
+            "../txt_files/catalog.txt",
+        ];
+        let config = StoreConfig::from_args(&args(&base));
+        assert_eq!(config.map(|config| config.seed), Ok(None));
+
+        let seeded: Vec<&str> = base.iter().copied().chain(["--seed", "42"]).collect();
+        let config = StoreConfig::from_args(&args(&seeded));
+        assert_eq!(config.map(|config| config.seed), Ok(Some(42)));
+
+        let bad_args: Vec<&str> = base.iter().copied().chain(["--seed", "-1"]).collect();
+        assert!(matches!(
+            StoreConfig::from_args(&args(&bad_args)),
+            Err(Errors::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_command_line_rejects_misplaced_tuning_settings() {
+        let mut config = EcomConfig::default();
+        assert_eq!(
+            config.apply_override("tuning.reconnect_attempts", "3"),
+            Ok(())
+        );
+        assert_eq!(config.tuning.reconnect_attempts, 3);
+
+        for setting in ["reconnect_attempts", "tuning.id", "tuning.tuning.seed"] {
+            match config.apply_override(setting, "3") {
+                Err(Errors::InvalidConfig(msg)) => assert!(msg.starts_with(setting)),
+                other => panic!("Se esperaba un error de configuracion: {:?}", other),
+            }
+        }
+
+        let mut config = StoreConfig::default();
+        assert!(matches!(
+            config.apply_override("max_dispatch_secs", "3"),
+            Err(Errors::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            config.apply_override("tuning.stock_file", "stock.txt"),
+            Err(Errors::InvalidConfig(_))
+        ));
+    }
+}
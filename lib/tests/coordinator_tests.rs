@@ -3,42 +3,54 @@
 #[cfg(test)]
 mod tests {
     use actix::prelude::*;
-    use lib::coordinator::{Coordinator, NewStore};
+    use lib::abstract_store::AbstractStore;
+    use lib::catalog::{Catalog, CatalogEntry};
+    use lib::clock::{real_clock, VirtualClock};
+    use lib::coordinator::{
+        _GetActiveStores, _GetBackorders, _GetLeader, ring_successor, CoordElection, Coordinator,
+        DisconnectStore, GetOrderStatus, IntakeOrder, Member, NewEcom, NewOrder, NewStore,
+        SetIntakePaused, ShutDown, StockReplenished, StoreDisconnected,
+    };
+    use lib::errors::Errors;
+    use lib::metrics::{Counter, Histogram, Metrics};
+    use lib::random::stream_rng;
+    use lib::reconnect::ReconnectPolicy;
+    use lib::sales_report::SalesReport;
+    use lib::trace::TraceId;
+    use lib::transport::duplex;
     use std::collections::HashMap;
-    use tokio::net::{TcpListener, TcpStream};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::time::timeout;
     #[actix_rt::test]
     async fn test_coordinator_new_store() {
         let coordinator = Coordinator {
-            id: 0 as usize,
-            curr_leader: Some(1 as usize),
+            id: 0,
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
             active_stores: HashMap::new(),
             active_ecoms: HashMap::new(),
             online_orders: vec![],
-            rng: rand::thread_rng(),
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
         };
 
         let store_id = "1".to_string();
-        let address = "127.0.0.1:7232".to_string();
-        let listener = TcpListener::bind(address.clone()).await;
-        match listener {
-            Ok(_) => {}
-            Err(e) => {
-                println!("Error: {}", e);
-                assert_eq!(true, false);
-            }
-        }
-        let stream_tcp = match TcpStream::connect(address.clone()).await {
-            Ok(stream) => stream,
-            Err(e) => {
-                println!("Error: {}", e);
-                assert_eq!(true, false);
-                return;
-            }
-        };
+        let (stream, _store) = duplex();
 
         let new_store = NewStore {
             store_id: store_id.clone(),
-            stream: stream_tcp,
+            stream,
         };
         let addr = coordinator.start();
         let _ = addr.send(new_store).await;
@@ -46,7 +58,7 @@ mod tests {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        assert_eq!(active_stores.contains_key(&store_id), true);
+        assert!(active_stores.contains_key(&store_id));
     }
 
     #[actix_rt::test]
@@ -54,26 +66,30 @@ mod tests {
     async fn test_coordinator_new_store_fail() {
         let coordinator = Coordinator {
             id: 0,
-            curr_leader: Some(1 as usize),
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
             active_stores: HashMap::new(),
             active_ecoms: HashMap::new(),
             online_orders: vec![],
-            rng: rand::thread_rng(),
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
         };
 
         let store_id = "1".to_string();
-        let address = "127.0.0.2:7232".to_string();
-        let stream_tcp = match TcpStream::connect(address.clone()).await {
-            Ok(stream) => stream,
-            Err(e) => {
-                println!("Error: {}", e);
-                assert_eq!(true, false);
-                return;
-            }
-        };
+        let (stream, _store) = duplex();
         let new_store = NewStore {
             store_id: store_id.clone(),
-            stream: stream_tcp,
+            stream,
         };
         let addr = coordinator.start();
         let _ = addr.send(new_store).await;
@@ -81,41 +97,37 @@ mod tests {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        assert_eq!(active_stores.contains_key(&store_id), false);
+        assert!(!active_stores.contains_key(&store_id));
     }
 
     #[actix_rt::test]
     async fn test_coordinator_store_disconnected() {
         let coordinator = Coordinator {
             id: 0,
-            curr_leader: Some(1 as usize),
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
             active_stores: HashMap::new(),
             active_ecoms: HashMap::new(),
             online_orders: vec![],
-            rng: rand::thread_rng(),
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
         };
 
         let store_id = "1".to_string();
-        let address = "127.0.0.3:7232".to_string();
-        let listener = TcpListener::bind(address.clone()).await;
-        match listener {
-            Ok(_) => {}
-            Err(e) => {
-                println!("Error: {}", e);
-                assert_eq!(true, false);
-            }
-        }
-        let stream_tcp = match TcpStream::connect(address.clone()).await {
-            Ok(stream) => stream,
-            Err(e) => {
-                println!("Error: {}", e);
-                assert_eq!(true, false);
-                return;
-            }
-        };
+        let (stream, _store) = duplex();
         let new_store = NewStore {
             store_id: store_id.clone(),
-            stream: stream_tcp,
+            stream,
         };
         let addr = coordinator.start();
         let _ = addr.send(new_store).await;
@@ -123,17 +135,21 @@ mod tests {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        assert_eq!(active_stores.contains_key(&store_id), true);
+        assert!(active_stores.contains_key(&store_id));
         let _ = addr
             .send(lib::coordinator::StoreDisconnected {
                 store_id: store_id.clone(),
+                stock: HashMap::new(),
+                last_seq: 0,
+                session: 0,
+                addr: active_stores[&store_id].clone(),
             })
             .await;
         let active_stores = match addr.send(lib::coordinator::_GetActiveStores).await {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        assert_eq!(active_stores.contains_key(&store_id), false);
+        assert!(!active_stores.contains_key(&store_id));
     }
 
     #[actix_rt::test]
@@ -141,34 +157,30 @@ mod tests {
     async fn test_coordinator_store_disconnected_fail() {
         let coordinator = Coordinator {
             id: 0,
-            curr_leader: Some(1 as usize),
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
             active_stores: HashMap::new(),
             active_ecoms: HashMap::new(),
             online_orders: vec![],
-            rng: rand::thread_rng(),
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
         };
 
         let store_id = "1".to_string();
-        let address = "127.0.0.4:7232".to_string();
-        let listener = TcpListener::bind(address.clone()).await;
-        match listener {
-            Ok(_) => {}
-            Err(e) => {
-                println!("Error: {}", e);
-                assert_eq!(true, false);
-            }
-        }
-        let stream_tcp = match TcpStream::connect(address.clone()).await {
-            Ok(stream) => stream,
-            Err(e) => {
-                println!("Error: {}", e);
-                assert_eq!(true, false);
-                return;
-            }
-        };
+        let (stream, _store) = duplex();
         let new_store = NewStore {
             store_id: store_id.clone(),
-            stream: stream_tcp,
+            stream,
         };
         let addr = coordinator.start();
         let _ = addr.send(new_store).await;
@@ -176,17 +188,586 @@ mod tests {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        assert_eq!(active_stores.contains_key(&store_id), true);
+        assert!(active_stores.contains_key(&store_id));
         let resultado = addr
             .send(lib::coordinator::StoreDisconnected {
                 store_id: "2".to_string(),
+                stock: HashMap::new(),
+                last_seq: 0,
+                session: 0,
+                addr: active_stores[&store_id].clone(),
             })
             .await;
-        assert_eq!(resultado.is_err(), true);
+        assert!(resultado.is_err());
         let active_stores = match addr.send(lib::coordinator::_GetActiveStores).await {
             Ok(Ok(stock)) => stock,
             _ => HashMap::new(),
         };
-        assert_eq!(active_stores.contains_key(&store_id), true);
+        assert!(active_stores.contains_key(&store_id));
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_store_that_left_is_reported_once() {
+        let coordinator = Coordinator {
+            id: 0,
+            curr_leader: Some(0),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
+            active_stores: HashMap::new(),
+            active_ecoms: HashMap::new(),
+            online_orders: vec![],
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
+        };
+        let metrics = coordinator.metrics.clone();
+        let addr = coordinator.start();
+
+        let store_id = "1".to_string();
+        let (stream, mut store) = duplex();
+        let _ = addr
+            .send(NewStore {
+                store_id: store_id.clone(),
+                stream,
+            })
+            .await;
+        assert!(store.write_all(b"BYE\n").await.is_ok());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // The store comes back before its old connection closes
+        let (stream, _store) = duplex();
+        let _ = addr
+            .send(NewStore {
+                store_id: store_id.clone(),
+                stream,
+            })
+            .await;
+        drop(store);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let active_stores = match addr.send(_GetActiveStores).await {
+            Ok(Ok(stores)) => stores,
+            _ => HashMap::new(),
+        };
+        assert!(active_stores.contains_key(&store_id));
+        assert_eq!(
+            metrics.counter(Counter::ConnectionsDown, &[("peer", "store")]),
+            1
+        );
+    }
+
+    /// Creates a coordinator that is its own leader and has a single store connected, "1",
+    /// whose AbstractStore has no stock at all. Its catalog only knows the SKU "ZAP-001".
+    fn coordinator_with_empty_store(backorder_max_wait: Duration) -> Coordinator {
+        let other_coord = Coordinator {
+            id: 1,
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
+            active_stores: HashMap::new(),
+            active_ecoms: HashMap::new(),
+            online_orders: vec![],
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
+        };
+        let abs_store = AbstractStore {
+            write: None,
+            store_id: "1".to_string(),
+            stock: HashMap::new(),
+            orders_buffer: vec![],
+            resumed: false,
+            last_seq: 0,
+            session: 0,
+            metrics: Metrics::new(),
+            left: false,
+            draining: false,
+            coordinator: other_coord.start(),
+        };
+        let mut active_stores = HashMap::new();
+        active_stores.insert("1".to_string(), abs_store.start());
+
+        let mut catalog = Catalog::new();
+        let _ = catalog.add(CatalogEntry {
+            sku: "ZAP-001".to_string(),
+            name: "zapatillas".to_string(),
+            price_cents: 4500000,
+            category: "Calzado".to_string(),
+        });
+
+        Coordinator {
+            id: 0,
+            curr_leader: Some(0),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
+            active_stores,
+            active_ecoms: HashMap::new(),
+            online_orders: vec![],
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait,
+            catalog,
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_backorder_when_no_store_has_stock() {
+        let addr = coordinator_with_empty_store(Duration::from_secs(30)).start();
+
+        let result = addr
+            .send(NewOrder {
+                order: "ZAP-001,5".to_string(),
+                visited_stores: vec!["1".to_string()],
+                trace_id: None,
+                origin: None,
+            })
+            .await;
+        assert!(matches!(result, Ok(Ok(()))));
+
+        let backorders = match addr.send(_GetBackorders).await {
+            Ok(Ok(backorders)) => backorders,
+            _ => HashMap::new(),
+        };
+        let queue = backorders.get("ZAP-001");
+        assert!(queue.is_some());
+        assert_eq!(queue.map(|q| q.len()), Some(1));
+        assert_eq!(queue.and_then(|q| q.front()).map(|b| b.quantity), Some(5));
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_order_status() {
+        let addr = coordinator_with_empty_store(Duration::from_secs(30)).start();
+
+        let _ = addr
+            .send(NewOrder {
+                order: "ZAP-001,5".to_string(),
+                visited_stores: vec!["1".to_string()],
+                trace_id: None,
+                origin: None,
+            })
+            .await;
+        let _ = addr
+            .send(NewOrder {
+                order: "XXX-999,1".to_string(),
+                visited_stores: vec![],
+                trace_id: None,
+                origin: None,
+            })
+            .await;
+
+        let status = match addr.send(GetOrderStatus).await {
+            Ok(Ok(status)) => status,
+            _ => panic!("No se pudo obtener el estado de los pedidos"),
+        };
+        assert_eq!(status.waiting, vec!["ZAP-001,5".to_string()]);
+        assert_eq!(status.approved, 0);
+        assert_eq!(status.lost, 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_shut_down_reports_backorders() {
+        let addr = coordinator_with_empty_store(Duration::from_secs(30)).start();
+
+        let _ = addr
+            .send(NewOrder {
+                order: "ZAP-001,5".to_string(),
+                visited_stores: vec!["1".to_string()],
+                trace_id: None,
+                origin: None,
+            })
+            .await;
+
+        let pending = match addr.send(ShutDown).await {
+            Ok(Ok(pending)) => pending,
+            _ => panic!("No se pudo cerrar el coordinador"),
+        };
+        assert_eq!(pending.backorders.len(), 1);
+        assert_eq!(pending.detached_stores, 0);
+
+        // Without other ecommerces there is nobody to hand the leadership over to
+        let leader = match addr.send(_GetLeader).await {
+            Ok(Ok(leader)) => leader,
+            _ => None,
+        };
+        assert_eq!(leader, Some(0));
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_pauses_order_intake() {
+        let addr = coordinator_with_empty_store(Duration::from_secs(30)).start();
+
+        let _ = addr.send(SetIntakePaused { paused: true }).await;
+        let result = addr
+            .send(IntakeOrder {
+                order: "ZAP-001,5".to_string(),
+                trace_id: TraceId::from_rng(&mut stream_rng(0, "trace")),
+            })
+            .await;
+        assert!(matches!(result, Ok(Err(Errors::IntakePausedError))));
+
+        let _ = addr.send(SetIntakePaused { paused: false }).await;
+        let result = addr
+            .send(IntakeOrder {
+                order: "ZAP-001,5".to_string(),
+                trace_id: TraceId::from_rng(&mut stream_rng(0, "trace")),
+            })
+            .await;
+        assert!(matches!(result, Ok(Ok(()))));
+
+        let result = addr
+            .send(DisconnectStore {
+                store_id: "2".to_string(),
+            })
+            .await;
+        assert!(matches!(result, Ok(Err(Errors::StoreNotConnectedError))));
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_backorder_waits_for_enough_stock() {
+        let addr = coordinator_with_empty_store(Duration::from_secs(30)).start();
+
+        let _ = addr
+            .send(NewOrder {
+                order: "ZAP-001,5".to_string(),
+                visited_stores: vec!["1".to_string()],
+                trace_id: None,
+                origin: None,
+            })
+            .await;
+        let _ = addr
+            .send(StockReplenished {
+                store_id: "1".to_string(),
+                product: "ZAP-001".to_string(),
+                quantity: 3,
+            })
+            .await;
+
+        let backorders = match addr.send(_GetBackorders).await {
+            Ok(Ok(backorders)) => backorders,
+            _ => HashMap::new(),
+        };
+        assert_eq!(backorders.get("ZAP-001").map(|q| q.len()), Some(1));
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_backorder_expires() {
+        let clock = VirtualClock::new();
+        let mut coordinator = coordinator_with_empty_store(Duration::from_secs(30));
+        coordinator.clock = clock.shared();
+        let addr = coordinator.start();
+
+        let _ = addr
+            .send(NewOrder {
+                order: "ZAP-001,5".to_string(),
+                visited_stores: vec!["1".to_string()],
+                trace_id: None,
+                origin: None,
+            })
+            .await;
+        // The backorders are checked on the clock of the coordinator, without any other message
+        clock.advance(Duration::from_secs(29)).await;
+        let backorders = match addr.send(_GetBackorders).await {
+            Ok(Ok(backorders)) => backorders,
+            _ => HashMap::new(),
+        };
+        assert_eq!(backorders.len(), 1);
+
+        clock.advance(Duration::from_secs(2)).await;
+        let backorders = match addr.send(_GetBackorders).await {
+            Ok(Ok(backorders)) => backorders,
+            _ => HashMap::new(),
+        };
+        assert!(backorders.is_empty());
+
+        let status = match addr.send(GetOrderStatus).await {
+            Ok(Ok(status)) => status,
+            _ => panic!("No se pudo obtener el estado de los pedidos"),
+        };
+        assert_eq!(status.lost, 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_expired_backorder_goes_back_to_its_origin() {
+        let clock = VirtualClock::new();
+        let mut coordinator = coordinator_with_empty_store(Duration::from_secs(30));
+        coordinator.id = 3;
+        coordinator.curr_leader = Some(3);
+        coordinator.clock = clock.shared();
+        let addr = coordinator.start();
+
+        let (stream, peer) = duplex();
+        let _ = addr
+            .send(NewEcom {
+                id: "2".to_string(),
+                stream,
+                dialed: false,
+                closed: None,
+            })
+            .await;
+        let trace_id = TraceId::from_rng(&mut stream_rng(0, "test"));
+        let _ = addr
+            .send(NewOrder {
+                order: "ZAP-001,5".to_string(),
+                visited_stores: vec!["1".to_string()],
+                trace_id: Some(trace_id),
+                origin: Some(2),
+            })
+            .await;
+        clock.advance(Duration::from_secs(31)).await;
+
+        let mut lines = BufReader::new(peer).lines();
+        let line = timeout(Duration::from_secs(1), lines.next_line()).await;
+        assert!(
+            matches!(line, Ok(Ok(Some(line))) if line == format!("EXPIRED,ZAP-001,5,trace={trace_id}"))
+        );
+
+        // The ecommerce that took the order is the one that counts it as lost
+        let status = match addr.send(GetOrderStatus).await {
+            Ok(Ok(status)) => status,
+            _ => panic!("No se pudo obtener el estado de los pedidos"),
+        };
+        assert_eq!(status.lost, 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_hands_backorders_over_to_new_leader() {
+        let addr = coordinator_with_empty_store(Duration::from_secs(30)).start();
+
+        let trace_id = TraceId::from_rng(&mut stream_rng(0, "test"));
+        let _ = addr
+            .send(NewOrder {
+                order: "ZAP-001,5".to_string(),
+                visited_stores: vec!["1".to_string()],
+                trace_id: Some(trace_id),
+                origin: None,
+            })
+            .await;
+
+        // An ecommerce with a bigger id takes the leadership, along with the backorders
+        let (stream, peer) = duplex();
+        let _ = addr
+            .send(NewEcom {
+                id: "2".to_string(),
+                stream,
+                dialed: false,
+                closed: None,
+            })
+            .await;
+
+        let mut lines = BufReader::new(peer).lines();
+        let line = timeout(Duration::from_secs(1), lines.next_line()).await;
+        assert!(
+            matches!(line, Ok(Ok(Some(line))) if line == format!("ORDER,ZAP-001,5,0,trace={trace_id}"))
+        );
+
+        let backorders = match addr.send(_GetBackorders).await {
+            Ok(Ok(backorders)) => backorders,
+            _ => HashMap::new(),
+        };
+        assert!(backorders.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_rejects_unknown_product() {
+        let addr = coordinator_with_empty_store(Duration::from_secs(30)).start();
+
+        let result = addr
+            .send(NewOrder {
+                order: "sandia,90".to_string(),
+                visited_stores: vec![],
+                trace_id: None,
+                origin: None,
+            })
+            .await;
+        assert!(matches!(result, Ok(Err(Errors::UnknownProductError))));
+
+        let backorders = match addr.send(_GetBackorders).await {
+            Ok(Ok(backorders)) => backorders,
+            _ => HashMap::new(),
+        };
+        assert!(backorders.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_counts_forwarded_orders_once() {
+        let coordinator = coordinator_with_empty_store(Duration::from_secs(30));
+        let metrics = coordinator.metrics.clone();
+        let addr = coordinator.start();
+
+        // Forwarded by the ecommerce that took it, which already counted it
+        let _ = addr
+            .send(NewOrder {
+                order: "sandia,90".to_string(),
+                visited_stores: vec![],
+                trace_id: None,
+                origin: Some(2),
+            })
+            .await;
+        assert_eq!(metrics.counter(Counter::OrdersReceived, &[]), 0);
+
+        let _ = addr
+            .send(NewOrder {
+                order: "sandia,90".to_string(),
+                visited_stores: vec![],
+                trace_id: None,
+                origin: None,
+            })
+            .await;
+        assert_eq!(metrics.counter(Counter::OrdersReceived, &[]), 1);
+    }
+
+    #[test]
+    fn test_ring_successor() {
+        let members = vec![1, 2, 4, 7, 9];
+        assert_eq!(ring_successor(2, &members), Some(4));
+        assert_eq!(ring_successor(9, &members), Some(1));
+        // An id that is not in the list still finds its place in the ring
+        assert_eq!(ring_successor(5, &members), Some(7));
+        assert_eq!(ring_successor(3, &[3]), None);
+        assert_eq!(ring_successor(3, &[]), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_election_without_other_ecoms() {
+        let metrics = Metrics::new();
+        let coordinator = Coordinator {
+            id: 2,
+            curr_leader: None,
+            members: HashMap::from([
+                (1, Member::default()),
+                (2, Member::default()),
+                (3, Member::default()),
+            ]),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
+            active_stores: HashMap::new(),
+            active_ecoms: HashMap::new(),
+            online_orders: vec![],
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: metrics.clone(),
+            election_started: None,
+            clock: real_clock(),
+        };
+        let addr = coordinator.start();
+
+        // No configured member is connected, so the ecom elects itself
+        let _ = addr.send(CoordElection { visited: vec![] }).await;
+        let leader = addr.send(_GetLeader).await;
+        assert!(matches!(leader, Ok(Ok(Some(2)))));
+        assert_eq!(metrics.counter(Counter::ElectionsStarted, &[]), 1);
+        assert_eq!(metrics.counter(Counter::ElectionsCompleted, &[]), 1);
+        assert_eq!(metrics.observations(Histogram::ElectionSeconds, &[]), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_keeps_stock_of_disconnected_store() {
+        let coordinator = Coordinator {
+            id: 1,
+            curr_leader: Some(1),
+            members: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
+            active_stores: HashMap::new(),
+            active_ecoms: HashMap::new(),
+            online_orders: vec![],
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
+        };
+        let addr = coordinator.start();
+
+        let (first, _first_store) = duplex();
+        let _ = addr
+            .send(NewStore {
+                store_id: "1".to_string(),
+                stream: first,
+            })
+            .await;
+        let first_addr = match addr.send(_GetActiveStores).await {
+            Ok(Ok(stores)) => stores["1"].clone(),
+            _ => panic!("La store no se conecto"),
+        };
+        let stock = HashMap::from([("ZAP-001".to_string(), 7)]);
+        let result = addr
+            .send(StoreDisconnected {
+                store_id: "1".to_string(),
+                stock: stock.clone(),
+                last_seq: 0,
+                session: 0,
+                addr: first_addr.clone(),
+            })
+            .await;
+        assert!(matches!(result, Ok(Ok(()))));
+
+        // When the store comes back, its AbstractStore starts from the stock it had
+        let (second, _second_store) = duplex();
+        let _ = addr
+            .send(NewStore {
+                store_id: "1".to_string(),
+                stream: second,
+            })
+            .await;
+        let abs_store = match addr.send(_GetActiveStores).await {
+            Ok(Ok(stores)) => stores.get("1").cloned(),
+            _ => None,
+        };
+        let resumed_stock = match abs_store {
+            Some(abs_store) => abs_store.send(lib::abstract_store::_GetStock).await,
+            None => panic!("La store no se volvio a conectar"),
+        };
+        assert!(matches!(resumed_stock, Ok(Ok(s)) if s == stock));
+
+        // The first connection closing late neither removes the store nor replaces the stock kept of it
+        let result = addr
+            .send(StoreDisconnected {
+                store_id: "1".to_string(),
+                stock: HashMap::new(),
+                last_seq: 0,
+                session: 0,
+                addr: first_addr,
+            })
+            .await;
+        assert!(matches!(result, Ok(Err(Errors::StoreNotConnectedError))));
+        let active_stores = match addr.send(_GetActiveStores).await {
+            Ok(Ok(stores)) => stores,
+            _ => HashMap::new(),
+        };
+        assert!(active_stores.contains_key("1"));
     }
 }
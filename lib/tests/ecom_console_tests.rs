@@ -0,0 +1,49 @@
+// Module dedicated to testing the commands of the admin console of the ecommerce
+
+#[cfg(test)]
+mod tests {
+    use lib::ecom_console::EcomCommand;
+    use lib::errors::Errors;
+
+    #[test]
+    fn test_ecom_console_parses_commands() {
+        assert_eq!(EcomCommand::parse("L"), Ok(EcomCommand::ShowState));
+        assert_eq!(EcomCommand::parse("S"), Ok(EcomCommand::ShowStock));
+        assert_eq!(EcomCommand::parse("E"), Ok(EcomCommand::Election));
+        assert_eq!(EcomCommand::parse("P"), Ok(EcomCommand::Pause));
+        assert_eq!(EcomCommand::parse(" R "), Ok(EcomCommand::Resume));
+        assert_eq!(
+            EcomCommand::parse("O,ZAP-001,2"),
+            Ok(EcomCommand::Order {
+                product: "ZAP-001".to_string(),
+                quantity: 2
+            })
+        );
+        assert_eq!(
+            EcomCommand::parse("D, 1"),
+            Ok(EcomCommand::DisconnectStore {
+                store_id: "1".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_ecom_console_rejects_invalid_commands() {
+        for input in [
+            "",
+            "X",
+            "L,1",
+            "O,ZAP-001",
+            "O,ZAP-001,dos",
+            "O,ZAP-001,0",
+            "O,,2",
+            "D",
+            "D,",
+        ] {
+            assert!(
+                matches!(EcomCommand::parse(input), Err(Errors::CouldNotParse)),
+                "[{input}]"
+            );
+        }
+    }
+}
@@ -0,0 +1,325 @@
+// Module dedicated to testing the connections between ecommerces, and between ecommerces and stores
+
+#[cfg(test)]
+mod tests {
+    use actix::prelude::*;
+    use lib::catalog::Catalog;
+    use lib::clock::real_clock;
+    use lib::coordinator::{_GetLeader, _GetMembers, Coordinator, Member, Members};
+    use lib::ecom::{
+        connect_to_ecom, discover_members, discover_stores, ecom_connection_listener, join_cluster,
+        members_from_msg, members_msg, read_line,
+    };
+    use lib::metrics::Metrics;
+    use lib::random::stream_rng;
+    use lib::reconnect::ReconnectPolicy;
+    use lib::sales_report::SalesReport;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    fn member(ecoms_address: &str, stores_address: &str) -> Member {
+        Member {
+            ecoms_address: ecoms_address.to_string(),
+            stores_address: stores_address.to_string(),
+        }
+    }
+
+    fn coordinator(id: usize, members: HashMap<usize, Member>) -> Coordinator {
+        Coordinator {
+            id,
+            curr_leader: Some(id),
+            members,
+            reconnect: ReconnectPolicy::default(),
+            detached_stores: HashMap::new(),
+            active_stores: HashMap::new(),
+            active_ecoms: HashMap::new(),
+            online_orders: vec![],
+            rng: stream_rng(0, "coordinator"),
+            traces: stream_rng(0, "trace"),
+            backorders: HashMap::new(),
+            backorder_max_wait: Duration::from_secs(30),
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            intake_paused: false,
+            metrics: Metrics::new(),
+            election_started: None,
+            clock: real_clock(),
+        }
+    }
+
+    #[test]
+    fn test_members_msg_round_trip() {
+        let members = Members {
+            leader: 3,
+            members: vec![
+                (1, member("127.0.0.1:6000", "127.0.0.1:6001")),
+                (3, member("127.0.0.3:6000", "")),
+            ],
+        };
+        let msg = members_msg(1, &members);
+        assert_eq!(
+            msg,
+            "MEMBERS,1,3,1@127.0.0.1:6000@127.0.0.1:6001/3@127.0.0.3:6000@\n"
+        );
+
+        let (sender_id, parsed) = match members_from_msg(&msg) {
+            Ok(parsed) => parsed,
+            Err(e) => panic!("No se pudo parsear el mensaje: {:?}", e),
+        };
+        assert_eq!(sender_id, 1);
+        assert_eq!(parsed, members);
+        assert!(members_from_msg("MEMBERS,1").is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_ecom_joins_running_cluster() {
+        let seed_address = "127.0.0.20:7400";
+        let joiner_address = "127.0.0.21:7400";
+        let seed = coordinator(1, HashMap::from([(1, member(seed_address, ""))])).start();
+        let joiner = coordinator(2, HashMap::from([(2, member(joiner_address, ""))])).start();
+
+        actix::spawn(ecom_connection_listener(
+            seed_address.to_string(),
+            seed.clone(),
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let result = join_cluster(
+            vec![seed_address.to_string()],
+            joiner.clone(),
+            2,
+            joiner_address.to_string(),
+            "127.0.0.21:7401".to_string(),
+            ReconnectPolicy::default(),
+        )
+        .await;
+        assert!(result.is_ok());
+
+        let seed_members = match seed.send(_GetMembers).await {
+            Ok(Ok(members)) => members,
+            _ => HashMap::new(),
+        };
+        assert_eq!(
+            seed_members.get(&2),
+            Some(&member(joiner_address, "127.0.0.21:7401"))
+        );
+
+        let joiner_members = match joiner.send(_GetMembers).await {
+            Ok(Ok(members)) => members,
+            _ => HashMap::new(),
+        };
+        assert_eq!(
+            joiner_members.get(&1).map(|m| m.ecoms_address.as_str()),
+            Some(seed_address)
+        );
+
+        // The joiner has the biggest id, so both agree that it is the leader
+        assert!(matches!(seed.send(_GetLeader).await, Ok(Ok(Some(2)))));
+        assert!(matches!(joiner.send(_GetLeader).await, Ok(Ok(Some(2)))));
+    }
+
+    #[actix_rt::test]
+    async fn test_joiner_redials_seed_after_losing_it() {
+        let seed_address = "127.0.0.28:7400";
+        let listener = match TcpListener::bind(seed_address).await {
+            Ok(listener) => listener,
+            Err(e) => panic!("No se pudo abrir el listener: {:?}", e),
+        };
+        let joiner = coordinator(2, HashMap::from([(2, member("127.0.0.29:7400", ""))])).start();
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            max_attempts: Some(5),
+            seed: 0,
+        };
+        let join = join_cluster(
+            vec![seed_address.to_string()],
+            joiner,
+            2,
+            "127.0.0.29:7400".to_string(),
+            "127.0.0.29:7401".to_string(),
+            policy,
+        );
+        let seed = async {
+            let (mut stream, _) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => panic!("No se acepto la conexion: {:?}", e),
+            };
+            assert!(read_line(&mut stream).await.is_ok());
+            let members = Members {
+                leader: 1,
+                members: vec![(1, member(seed_address, ""))],
+            };
+            assert!(stream
+                .write_all(members_msg(1, &members).as_bytes())
+                .await
+                .is_ok());
+            stream
+        };
+        let (result, joined) = tokio::join!(join, seed);
+        assert!(result.is_ok());
+
+        // The seed drops the connection it was joined through, and the joiner dials it again
+        drop(joined);
+        let (mut stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => panic!("No se acepto la conexion: {:?}", e),
+        };
+        assert_eq!(read_line(&mut stream).await.ok(), Some("2".to_string()));
+    }
+
+    #[actix_rt::test]
+    async fn test_store_discovers_cluster_through_seed() {
+        let stores_address = "127.0.0.22:7401";
+        let members = HashMap::from([
+            (1, member("127.0.0.22:7400", stores_address)),
+            (2, member("127.0.0.23:7400", "127.0.0.23:7401")),
+        ]);
+        let coord = coordinator(1, members).start();
+
+        actix::spawn(discover_stores(stores_address.to_string(), coord, 1));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let discovered = match discover_members(stores_address).await {
+            Ok(members) => members,
+            Err(e) => panic!("No se pudo descubrir el cluster: {:?}", e),
+        };
+        let stores_addresses: Vec<&str> = discovered
+            .members
+            .iter()
+            .map(|(_, m)| m.stores_address.as_str())
+            .collect();
+        assert_eq!(stores_addresses, vec![stores_address, "127.0.0.23:7401"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_ecom_reconnects_after_peer_drops_connection() {
+        let peer_address = "127.0.0.24:7400";
+        let listener = match TcpListener::bind(peer_address).await {
+            Ok(listener) => listener,
+            Err(e) => panic!("No se pudo abrir el listener: {:?}", e),
+        };
+        let coord = coordinator(1, HashMap::from([(1, member("127.0.0.25:7400", ""))])).start();
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            max_attempts: Some(5),
+            seed: 0,
+        };
+        actix::spawn(connect_to_ecom(
+            peer_address.to_string(),
+            "2".to_string(),
+            coord,
+            "1".to_string(),
+            policy,
+        ));
+
+        // The peer drops the first connection, and the ecommerce introduces itself again
+        for _ in 0..2 {
+            let (mut stream, _) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => panic!("No se acepto la conexion: {:?}", e),
+            };
+            assert_eq!(read_line(&mut stream).await.ok(), Some("1".to_string()));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_ecom_redials_peer_that_shut_down() {
+        let peer_address = "127.0.0.30:7400";
+        let listener = match TcpListener::bind(peer_address).await {
+            Ok(listener) => listener,
+            Err(e) => panic!("No se pudo abrir el listener: {:?}", e),
+        };
+        let coord = coordinator(1, HashMap::from([(1, member("127.0.0.31:7400", ""))])).start();
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            max_attempts: Some(5),
+            seed: 0,
+        };
+        actix::spawn(connect_to_ecom(
+            peer_address.to_string(),
+            "2".to_string(),
+            coord,
+            "1".to_string(),
+            policy,
+        ));
+
+        // The peer shuts down saying BYE, and once it is back the ecommerce introduces itself again
+        let (mut stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => panic!("No se acepto la conexion: {:?}", e),
+        };
+        assert_eq!(read_line(&mut stream).await.ok(), Some("1".to_string()));
+        assert!(stream.write_all(b"BYE\n").await.is_ok());
+        drop(stream);
+        let (mut stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => panic!("No se acepto la conexion: {:?}", e),
+        };
+        assert_eq!(read_line(&mut stream).await.ok(), Some("1".to_string()));
+    }
+
+    #[actix_rt::test]
+    async fn test_ecom_keeps_one_connection_per_peer() {
+        let my_address = "127.0.0.26:7400";
+        let peer_address = "127.0.0.27:7400";
+        let coord = coordinator(1, HashMap::from([(1, member(my_address, ""))])).start();
+        actix::spawn(ecom_connection_listener(
+            my_address.to_string(),
+            coord.clone(),
+        ));
+        let peer = match TcpListener::bind(peer_address).await {
+            Ok(listener) => listener,
+            Err(e) => panic!("No se pudo abrir el listener: {:?}", e),
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The peer dials first, and that connection is kept while it is the only one
+        let mut dialed_by_peer = match TcpStream::connect(my_address).await {
+            Ok(stream) => stream,
+            Err(e) => panic!("No se pudo conectar: {:?}", e),
+        };
+        assert!(dialed_by_peer.write_all(b"2\n").await.is_ok());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The connection dialed by the smallest id replaces it
+        actix::spawn(connect_to_ecom(
+            peer_address.to_string(),
+            "2".to_string(),
+            coord.clone(),
+            "1".to_string(),
+            ReconnectPolicy::default(),
+        ));
+        let (mut dialed_by_me, _) = match peer.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => panic!("No se acepto la conexion: {:?}", e),
+        };
+        assert_eq!(
+            read_line(&mut dialed_by_me).await.ok(),
+            Some("1".to_string())
+        );
+        assert_eq!(
+            read_line(&mut dialed_by_peer).await.ok(),
+            Some("CLOSE".to_string())
+        );
+
+        // Another connection dialed by the peer is closed right away
+        let mut repeated = match TcpStream::connect(my_address).await {
+            Ok(stream) => stream,
+            Err(e) => panic!("No se pudo conectar: {:?}", e),
+        };
+        assert!(repeated.write_all(b"2\n").await.is_ok());
+        assert_eq!(
+            read_line(&mut repeated).await.ok(),
+            Some("CLOSE".to_string())
+        );
+
+        // None of the closed connections was taken as a disconnection of the leader
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(matches!(coord.send(_GetLeader).await, Ok(Ok(Some(2)))));
+    }
+}
@@ -0,0 +1,100 @@
+// Module dedicated to testing the counters, the histograms and the text rendered for Prometheus
+
+#[cfg(test)]
+mod tests {
+    use lib::admin_api::HttpRequest;
+    use lib::metrics::{metrics_response, Counter, Histogram, Metrics};
+
+    fn request(method: &str, path: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_counters_by_label() {
+        let metrics = Metrics::new();
+        metrics.inc(Counter::OrdersApproved, &[("channel", "online")]);
+        metrics.inc(Counter::OrdersApproved, &[("channel", "online")]);
+        metrics.inc(Counter::OrdersApproved, &[("channel", "local")]);
+
+        assert_eq!(
+            metrics.counter(Counter::OrdersApproved, &[("channel", "online")]),
+            2
+        );
+        assert_eq!(
+            metrics.counter(Counter::OrdersApproved, &[("channel", "local")]),
+            1
+        );
+        assert_eq!(metrics.counter(Counter::OrdersCancelled, &[]), 0);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE orders_approved_total counter\n"));
+        assert!(text.contains("orders_approved_total{channel=\"online\"} 2\n"));
+        assert!(text.contains("orders_approved_total{channel=\"local\"} 1\n"));
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let metrics = Metrics::new();
+        let labels = [("outcome", "dispatched")];
+        metrics.observe(Histogram::DispatchDeadlineRatio, &labels, 0.2);
+        metrics.observe(Histogram::DispatchDeadlineRatio, &labels, 0.9);
+        metrics.observe(Histogram::DispatchDeadlineRatio, &labels, 10.0);
+
+        assert_eq!(
+            metrics.observations(Histogram::DispatchDeadlineRatio, &labels),
+            3
+        );
+        let text = metrics.render();
+        assert!(text.contains("# TYPE dispatch_deadline_ratio histogram\n"));
+        assert!(
+            text.contains("dispatch_deadline_ratio_bucket{outcome=\"dispatched\",le=\"0.25\"} 1\n")
+        );
+        assert!(
+            text.contains("dispatch_deadline_ratio_bucket{outcome=\"dispatched\",le=\"1\"} 2\n")
+        );
+        assert!(
+            text.contains("dispatch_deadline_ratio_bucket{outcome=\"dispatched\",le=\"4\"} 2\n")
+        );
+        assert!(
+            text.contains("dispatch_deadline_ratio_bucket{outcome=\"dispatched\",le=\"+Inf\"} 3\n")
+        );
+        assert!(text.contains("dispatch_deadline_ratio_count{outcome=\"dispatched\"} 3\n"));
+    }
+
+    #[test]
+    fn test_mailbox_drops() {
+        let metrics = Metrics::new();
+        assert!(metrics.sent::<()>(Ok(()), "store"));
+        assert!(!metrics.sent(Err(()), "store"));
+        assert!(!metrics.sent(Err(()), "coordinator"));
+
+        assert_eq!(
+            metrics.counter(Counter::MailboxDrops, &[("actor", "store")]),
+            1
+        );
+        assert_eq!(
+            metrics.counter(Counter::MailboxDrops, &[("actor", "coordinator")]),
+            1
+        );
+    }
+
+    #[test]
+    fn test_metrics_route() {
+        let metrics = Metrics::new();
+        metrics.inc(Counter::ElectionsStarted, &[]);
+
+        let response = metrics_response(&metrics, &request("GET", "/metrics"));
+        assert_eq!(response.status, 200);
+        assert!(response.content_type.starts_with("text/plain"));
+        assert!(response.body.contains("elections_started_total 1\n"));
+
+        let response = metrics_response(&metrics, &request("GET", "/stock"));
+        assert_eq!(response.status, 404);
+        let response = metrics_response(&metrics, &request("POST", "/metrics"));
+        assert_eq!(response.status, 404);
+    }
+}
@@ -0,0 +1,77 @@
+// Module dedicated to testing the log of stock events kept while a store is disconnected
+
+#[cfg(test)]
+mod tests {
+    use lib::offline_log::{orders_in, OfflineLog};
+
+    #[test]
+    fn test_offline_log_compacts_events() {
+        let mut log = OfflineLog::new();
+        assert!(log.is_empty());
+
+        assert!(log.record("APPROVED,ZAP-001,2,physical"));
+        assert!(log.record("APPROVED,ZAP-001,3,physical"));
+        assert!(log.record("APPROVED,ZAP-001,1,online"));
+        assert!(log.record("CANCELLED,REM-002,4"));
+        assert!(log.record("CANCELLED,REM-002,1"));
+        assert!(!log.is_empty());
+
+        assert_eq!(
+            log.replay(),
+            vec![
+                "APPROVED,ZAP-001,5,physical,orders=2".to_string(),
+                "APPROVED,ZAP-001,1,online,orders=1".to_string(),
+                "CANCELLED,REM-002,5,orders=2".to_string(),
+            ]
+        );
+        assert!(log.is_empty());
+        assert!(log.replay().is_empty());
+    }
+
+    #[test]
+    fn test_offline_log_keeps_orders_of_replayed_lines() {
+        let mut log = OfflineLog::new();
+        // A replayed line that is kept again, because the leader was lost before it got it, still adds up its orders
+        assert!(log.record("APPROVED,ZAP-001,5,physical,orders=2"));
+        assert!(log.record("APPROVED,ZAP-001,1,physical,trace=00000000000000ab"));
+        assert_eq!(
+            log.lines(),
+            vec!["APPROVED,ZAP-001,6,physical,orders=3".to_string()]
+        );
+
+        assert_eq!(orders_in(&["CANCELLED", "REM-002", "5", "orders=2"]), 2);
+        assert_eq!(orders_in(&["CANCELLED", "REM-002", "5"]), 1);
+    }
+
+    #[test]
+    fn test_offline_log_replays_restock_before_sales() {
+        let mut log = OfflineLog::new();
+        // 10 units, 2 sold, restocked to 18, 3 sold: the store ends with 15
+        assert!(log.record("APPROVED,ZAP-001,2,physical"));
+        assert!(log.record("STOCK,ZAP-001,18"));
+        assert!(log.record("APPROVED,ZAP-001,3,online"));
+
+        // The leader takes 20 and then applies the 5 units sold
+        assert_eq!(
+            log.replay(),
+            vec![
+                "STOCK,ZAP-001,20".to_string(),
+                "APPROVED,ZAP-001,2,physical,orders=1".to_string(),
+                "APPROVED,ZAP-001,3,online,orders=1".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_offline_log_ignores_other_answers() {
+        let mut log = OfflineLog::new();
+        assert!(!log.record("LEADER,?,?"));
+        assert!(!log.record("RESYNC"));
+        assert!(!log.record("APPROVED,ZAP-001,muchos,physical"));
+        assert!(log.is_empty());
+
+        // A restock is a stock event too, so the leader learns it on reconnection
+        assert!(log.record("STOCK,ZAP-001,10"));
+        assert_eq!(log.lines(), vec!["STOCK,ZAP-001,10".to_string()]);
+    }
+}
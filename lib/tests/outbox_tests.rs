@@ -0,0 +1,62 @@
+// Module dedicated to testing the outbox of the notifications from the store to the leader
+
+#[cfg(test)]
+mod tests {
+    use lib::outbox::{ack_msg, parse_sequenced, Outbox};
+
+    #[test]
+    fn test_outbox_keeps_notifications_until_acknowledged() {
+        let mut outbox = Outbox::new();
+        let session = outbox.session();
+        assert!(outbox.is_empty());
+
+        assert_eq!(
+            outbox.push("APPROVED,ZAP-001,2,physical".to_string()),
+            format!("SEQ,{session},1,APPROVED,ZAP-001,2,physical")
+        );
+        assert_eq!(
+            outbox.push("CANCELLED,ZAP-001,1".to_string()),
+            format!("SEQ,{session},2,CANCELLED,ZAP-001,1")
+        );
+        assert_eq!(
+            outbox.push("STOCK,ZAP-001,7".to_string()),
+            format!("SEQ,{session},3,STOCK,ZAP-001,7")
+        );
+
+        outbox.ack(2);
+        assert_eq!(
+            outbox.pending(),
+            vec![format!("SEQ,{session},3,STOCK,ZAP-001,7")]
+        );
+
+        // Acknowledging an old number again changes nothing
+        outbox.ack(1);
+        assert_eq!(outbox.pending().len(), 1);
+        outbox.ack(3);
+        assert!(outbox.is_empty());
+
+        // The numbers keep growing after the outbox is emptied
+        assert_eq!(
+            outbox.push("STOCK,ZAP-001,6".to_string()),
+            format!("SEQ,{session},4,STOCK,ZAP-001,6")
+        );
+    }
+
+    #[test]
+    fn test_outbox_wire_format() {
+        assert!(Outbox::is_notification("APPROVED,ZAP-001,2,online"));
+        assert!(Outbox::is_notification("CANCELLED,ZAP-001,2"));
+        assert!(Outbox::is_notification("STOCK,ZAP-001,2"));
+        assert!(!Outbox::is_notification("LEADER,?,?"));
+        assert!(!Outbox::is_notification("RESYNC"));
+
+        assert_eq!(
+            parse_sequenced("SEQ,7,12,APPROVED,ZAP-001,2,online"),
+            Some((7, 12, "APPROVED,ZAP-001,2,online"))
+        );
+        assert_eq!(parse_sequenced("APPROVED,ZAP-001,2,online"), None);
+        assert_eq!(parse_sequenced("SEQ,7,doce,STOCK,ZAP-001,2"), None);
+        assert_eq!(parse_sequenced("SEQ,12,APPROVED,ZAP-001,2,online"), None);
+        assert_eq!(ack_msg(12), "ACK,12");
+    }
+}
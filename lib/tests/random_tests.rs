@@ -0,0 +1,50 @@
+// Module dedicated to testing that the random decisions of a node can be repeated from its seed
+
+#[cfg(test)]
+mod tests {
+    use lib::random::{node_seed, stream_rng};
+    use lib::reconnect::ReconnectPolicy;
+    use lib::trace::TraceId;
+    use rand::Rng;
+    use std::time::Duration;
+
+    fn draws(seed: u64, stream: &str) -> Vec<u64> {
+        let mut rng = stream_rng(seed, stream);
+        (0..10).map(|_| rng.gen_range(0, 1000)).collect()
+    }
+
+    #[test]
+    fn test_same_seed_same_draws() {
+        assert_eq!(draws(7, "coordinator"), draws(7, "coordinator"));
+        assert_ne!(draws(7, "coordinator"), draws(8, "coordinator"));
+        assert_ne!(draws(7, "coordinator"), draws(7, "order_manager"));
+
+        let mut rng = stream_rng(7, "order_manager");
+        let mut again = stream_rng(7, "order_manager");
+        assert_eq!(TraceId::from_rng(&mut rng), TraceId::from_rng(&mut again));
+    }
+
+    #[test]
+    fn test_node_seed() {
+        assert_eq!(node_seed(Some(42)), 42);
+    }
+
+    #[test]
+    fn test_backoff_jitter_follows_the_seed() {
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(10000),
+            max_attempts: Some(8),
+            seed: 0,
+        }
+        .seeded(42);
+        let delays = |peer: &str| {
+            let mut backoff = policy.backoff(peer);
+            (0..8)
+                .filter_map(|_| backoff.next_delay())
+                .collect::<Vec<Duration>>()
+        };
+        assert_eq!(delays("1"), delays("1"));
+        assert_ne!(delays("1"), delays("2"));
+    }
+}
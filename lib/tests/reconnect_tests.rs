@@ -0,0 +1,65 @@
+// Module dedicated to testing the backoff used to reconnect with peers
+
+#[cfg(test)]
+mod tests {
+    use lib::errors::Errors;
+    use lib::reconnect::{connect_with_backoff, ReconnectPolicy};
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    fn policy(initial_ms: u64, max_ms: u64, attempts: u32) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(initial_ms),
+            max_delay: Duration::from_millis(max_ms),
+            max_attempts: Some(attempts),
+            seed: 0,
+        }
+    }
+
+    #[test]
+    fn test_backoff_grows_up_to_max_delay() {
+        let mut backoff = policy(100, 1000, 6).backoff("1");
+        let expected = [100, 200, 400, 800, 1000, 1000];
+        for delay in expected {
+            let next = backoff.next_delay().map(|d| d.as_millis() as u64);
+            assert!(matches!(next, Some(ms) if ms >= delay / 2 && ms <= delay));
+        }
+        assert_eq!(backoff.attempts(), 6);
+        assert_eq!(backoff.next_delay(), None);
+
+        backoff.reset();
+        let next = backoff.next_delay().map(|d| d.as_millis() as u64);
+        assert!(matches!(next, Some(ms) if (50..=100).contains(&ms)));
+    }
+
+    #[test]
+    fn test_forever_backoff_never_runs_out() {
+        let mut backoff = policy(1, 10, 1).forever().backoff("1");
+        for _ in 0..100 {
+            assert!(backoff.next_delay().is_some());
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_connect_with_backoff_waits_for_listener() {
+        let address = "127.0.0.30:7410";
+        let listener = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            let listener = TcpListener::bind(address).await.ok()?;
+            listener.accept().await.ok()
+        });
+
+        let mut backoff = policy(20, 50, 20).backoff("1");
+        let result = connect_with_backoff(address, &mut backoff).await;
+        assert!(result.is_ok());
+        assert_eq!(backoff.attempts(), 0);
+        assert!(matches!(listener.await, Ok(Some(_))));
+    }
+
+    #[actix_rt::test]
+    async fn test_connect_with_backoff_gives_up() {
+        let mut backoff = policy(5, 10, 2).backoff("1");
+        let result = connect_with_backoff("127.0.0.31:7411", &mut backoff).await;
+        assert!(matches!(result, Err(Errors::ConnectionError)));
+    }
+}
@@ -0,0 +1,56 @@
+// Module dedicated to testing the aggregates of the sales report
+
+#[cfg(test)]
+mod tests {
+    use lib::sales_report::{Channel, LossReason, SalesReport};
+
+    fn sample_report() -> SalesReport {
+        let mut report = SalesReport::new();
+        report.record_sale("1", Channel::Physical, "ZAP-001", 2, 1, 4500000);
+        report.record_sale("1", Channel::Online, "ZAP-001", 1, 1, 4500000);
+        report.record_sale("2", Channel::Online, "REM-001", 3, 1, 1200000);
+        report.record_cancellation("2", "REM-001", 5, 1);
+        report.record_lost(LossReason::UnknownProduct);
+        report.record_lost(LossReason::UnknownProduct);
+        report
+    }
+
+    #[test]
+    fn test_sales_report_totals() {
+        let report = sample_report();
+
+        let by_store = report.totals_by_store();
+        assert_eq!(by_store["1"].units_sold, 3);
+        assert_eq!(by_store["1"].revenue_cents, 13500000);
+        assert_eq!(by_store["2"].orders_cancelled, 1);
+        assert_eq!(by_store["2"].units_cancelled, 5);
+
+        let by_channel = report.totals_by_channel();
+        assert_eq!(by_channel[&Channel::Physical].orders_approved, 1);
+        assert_eq!(by_channel[&Channel::Online].orders_approved, 2);
+
+        let by_product = report.totals_by_product();
+        assert_eq!(by_product["REM-001"].revenue_cents, 3600000);
+
+        assert_eq!(report.lost_orders(LossReason::UnknownProduct), 2);
+        assert_eq!(report.lost_orders(LossReason::BackorderExpired), 0);
+    }
+
+    #[test]
+    fn test_sales_report_csv_and_json() {
+        let report = sample_report();
+
+        let csv = report.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines.contains(&"1,physical,ZAP-001,1,2,9000000,0,0"));
+        assert!(lines.contains(&"2,online,REM-001,1,3,3600000,1,5"));
+
+        let json = match report.to_json() {
+            Ok(json) => json,
+            Err(e) => panic!("No se pudo generar el JSON: {:?}", e),
+        };
+        assert!(json.contains("\"unknown_product\": 2"));
+        assert!(json.contains("\"channel\": \"physical\""));
+    }
+}
@@ -0,0 +1,47 @@
+// Module dedicated to testing the summary of the work left pending when a process shuts down
+
+#[cfg(test)]
+mod tests {
+    use lib::shutdown::{write_pending_lines, ShutdownSummary, PENDING_EXIT_CODE};
+
+    #[test]
+    fn test_shutdown_summary_exit_code() {
+        let mut summary = ShutdownSummary::new();
+        assert!(summary.is_clean());
+        assert_eq!(summary.exit_code(), 0);
+
+        summary.add("reservas abiertas", 0);
+        summary.add("notificaciones sin confirmar", 0);
+        assert_eq!(summary.exit_code(), 0);
+
+        summary.add("eventos sin enviar al lider", 2);
+        assert!(!summary.is_clean());
+        assert_eq!(summary.exit_code(), PENDING_EXIT_CODE);
+        assert_eq!(
+            summary.lines(),
+            vec![
+                "reservas abiertas: 0".to_string(),
+                "notificaciones sin confirmar: 0".to_string(),
+                "eventos sin enviar al lider: 2".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_pending_lines() {
+        let dir = std::env::temp_dir().join("shutdown_tests");
+        let dir = dir.to_string_lossy().to_string();
+        let lines = vec![
+            "SEQ,3,CANCELLED,ZAP-001,1".to_string(),
+            "APPROVED,ZAP-001,2,physical".to_string(),
+        ];
+        assert!(write_pending_lines(&dir, "store_1_pending", &lines).is_ok());
+
+        let path = std::path::Path::new(&dir).join("store_1_pending.txt");
+        let content = std::fs::read_to_string(path).unwrap_or_default();
+        assert_eq!(
+            content,
+            "SEQ,3,CANCELLED,ZAP-001,1\nAPPROVED,ZAP-001,2,physical\n"
+        );
+    }
+}
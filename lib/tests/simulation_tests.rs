@@ -0,0 +1,212 @@
+// Module dedicated to testing whole clusters run in a single process with the simulation harness
+
+#[cfg(test)]
+mod tests {
+    use lib::catalog::{Catalog, CatalogEntry};
+    use lib::config::StoreTuning;
+    use lib::errors::Errors;
+    use lib::simulation::{Cluster, ClusterConfig, Node, Step};
+    use lib::store::ProductStock;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    const PRODUCT: &str = "ZAP-001";
+
+    /// Three ecommerces and two stores with 10 units each. Every reservation is dispatched after one second,
+    /// before its time limit, so no order is cancelled.
+    fn config() -> ClusterConfig {
+        let mut catalog = Catalog::new();
+        let _ = catalog.add(CatalogEntry {
+            sku: PRODUCT.to_string(),
+            name: "Zapatillas".to_string(),
+            price_cents: 1000,
+            category: "Calzado".to_string(),
+        });
+        let stock = BTreeMap::from([(
+            PRODUCT.to_string(),
+            ProductStock {
+                available_quantity: 10,
+                reserved_quantity: 0,
+                safety_stock: 0,
+            },
+        )]);
+        ClusterConfig {
+            ecoms: vec![1, 2, 3],
+            stores: BTreeMap::from([("A".to_string(), stock.clone()), ("B".to_string(), stock)]),
+            catalog,
+            seed: 7,
+            store_tuning: StoreTuning {
+                min_dispatch_secs: 1,
+                max_dispatch_secs: 2,
+                ..StoreTuning::default()
+            },
+            ..ClusterConfig::default()
+        }
+    }
+
+    async fn available(cluster: &Cluster, store: &str) -> usize {
+        cluster.stock(store).await.unwrap()[PRODUCT].available_quantity
+    }
+
+    #[actix_rt::test]
+    async fn test_election_after_killing_the_leader() {
+        let mut cluster = Cluster::start(config()).await.unwrap();
+        for ecom in [1, 2, 3] {
+            assert_eq!(cluster.leader(ecom).await, Ok(Some(3)));
+        }
+        assert_eq!(cluster.connections("A").await.unwrap().leader, "3");
+
+        cluster.kill(&Node::Ecom(3)).await.unwrap();
+        assert_eq!(cluster.leader(3).await, Err(Errors::NodeDownError));
+        assert_eq!(cluster.leader(1).await, Ok(Some(2)));
+        assert_eq!(cluster.leader(2).await, Ok(Some(2)));
+        assert_eq!(cluster.connections("A").await.unwrap().leader, "2");
+        assert_eq!(cluster.connections("B").await.unwrap().leader, "2");
+
+        cluster.restart(&Node::Ecom(3)).await.unwrap();
+        for ecom in [1, 2, 3] {
+            assert_eq!(cluster.leader(ecom).await, Ok(Some(3)));
+        }
+        let connections = cluster.connections("B").await.unwrap();
+        assert_eq!(connections.leader, "3");
+        assert_eq!(connections.ecoms, vec!["1", "2", "3"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_stock_after_partition_and_heal() {
+        let mut cluster = Cluster::start(config()).await.unwrap();
+        let b_side = vec![Node::Ecom(3), Node::Store("B".to_string())];
+        let results = cluster
+            .run(vec![
+                Step::Order {
+                    ecom: 1,
+                    product: PRODUCT.to_string(),
+                    quantity: 3,
+                },
+                Step::Advance(Duration::from_secs(2)),
+                Step::Partition(b_side),
+                Step::Sale {
+                    store: "B".to_string(),
+                    product: PRODUCT.to_string(),
+                    quantity: 2,
+                },
+                Step::Order {
+                    ecom: 1,
+                    product: PRODUCT.to_string(),
+                    quantity: 4,
+                },
+                Step::Advance(Duration::from_secs(2)),
+            ])
+            .await;
+        assert!(results.iter().all(Result::is_ok));
+
+        // Each side has its own leader, and the orders of ecommerce 1 only reach store A
+        assert_eq!(cluster.leader(1).await, Ok(Some(2)));
+        assert_eq!(cluster.leader(3).await, Ok(Some(3)));
+        assert_eq!(
+            cluster.stores_stock(3).await.unwrap()["B"][PRODUCT],
+            available(&cluster, "B").await
+        );
+        assert!(available(&cluster, "A").await <= 10 - 4);
+
+        cluster.heal().await;
+        assert_eq!(cluster.leader(1).await, Ok(Some(3)));
+        assert_eq!(cluster.leader(2).await, Ok(Some(3)));
+        assert_eq!(
+            available(&cluster, "A").await + available(&cluster, "B").await,
+            20 - 3 - 2 - 4
+        );
+
+        // The leader knows the stock every store has after the partition
+        let known = cluster.stores_stock(3).await.unwrap();
+        assert_eq!(known["A"][PRODUCT], available(&cluster, "A").await);
+        assert_eq!(known["B"][PRODUCT], available(&cluster, "B").await);
+    }
+
+    #[actix_rt::test]
+    async fn test_offline_sales_replayed_to_a_new_leader() {
+        let mut cluster = Cluster::start(config()).await.unwrap();
+        let store_a = Node::Store("A".to_string());
+        let results = cluster
+            .run(vec![
+                Step::Partition(vec![store_a.clone()]),
+                Step::Sale {
+                    store: "A".to_string(),
+                    product: PRODUCT.to_string(),
+                    quantity: 2,
+                },
+                Step::Kill(Node::Ecom(3)),
+                Step::Heal,
+                Step::Sale {
+                    store: "A".to_string(),
+                    product: PRODUCT.to_string(),
+                    quantity: 1,
+                },
+            ])
+            .await;
+        assert!(results.iter().all(Result::is_ok));
+
+        // The new leader never had the stock of A, so it takes the one A shares instead of the sales A replayed
+        assert_eq!(cluster.leader(1).await, Ok(Some(2)));
+        assert_eq!(available(&cluster, "A").await, 10 - 2 - 1);
+        assert_eq!(
+            cluster.stores_stock(2).await.unwrap()["A"][PRODUCT],
+            available(&cluster, "A").await
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_leader_follows_store_that_restarted() {
+        let mut cluster = Cluster::start(config()).await.unwrap();
+        let store_a = Node::Store("A".to_string());
+        let results = cluster
+            .run(vec![
+                Step::Sale {
+                    store: "A".to_string(),
+                    product: PRODUCT.to_string(),
+                    quantity: 3,
+                },
+                Step::Kill(store_a.clone()),
+                Step::Restart(store_a),
+                Step::Sale {
+                    store: "A".to_string(),
+                    product: PRODUCT.to_string(),
+                    quantity: 1,
+                },
+            ])
+            .await;
+        assert!(results.iter().all(Result::is_ok));
+
+        // The restarted store numbers its notifications from 1 again, and the leader still applies them
+        assert_eq!(available(&cluster, "A").await, 10 - 1);
+        assert_eq!(
+            cluster.stores_stock(3).await.unwrap()["A"][PRODUCT],
+            available(&cluster, "A").await
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_unknown_nodes() {
+        let mut cluster = Cluster::start(config()).await.unwrap();
+        assert_eq!(
+            cluster.kill(&Node::Store("Z".to_string())).await,
+            Err(Errors::UnknownNodeError)
+        );
+        assert_eq!(
+            cluster.order(9, PRODUCT, 1).await,
+            Err(Errors::UnknownNodeError)
+        );
+
+        cluster.kill(&Node::Store("A".to_string())).await.unwrap();
+        assert_eq!(
+            cluster.sale("A", PRODUCT, 1).await,
+            Err(Errors::NodeDownError)
+        );
+        cluster
+            .restart(&Node::Store("A".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(cluster.sale("A", PRODUCT, 1).await, Ok(()));
+        assert_eq!(available(&cluster, "A").await, 9);
+    }
+}
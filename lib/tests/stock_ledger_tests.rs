@@ -0,0 +1,185 @@
+// Module dedicated to testing the invariants of the stock ledger
+
+#[cfg(test)]
+mod tests {
+    use lib::errors::Errors;
+    use lib::stock_ledger::{MovementReason, StockLedger};
+    use lib::store::ProductStock;
+    use proptest::prelude::*;
+
+    const PRODUCTS: [&str; 3] = ["product0", "product1", "product2"];
+
+    #[derive(Debug, Clone)]
+    enum Operation {
+        Sell(usize, usize),
+        Reserve(usize, usize),
+        CancelReservation(usize, usize),
+        Dispatch(usize, usize),
+        SetSafetyStock(usize, usize),
+        Restock(usize, usize),
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        let product = 0..PRODUCTS.len() + 1;
+        prop_oneof![
+            (product.clone(), 0..40usize).prop_map(|(p, q)| Operation::Sell(p, q)),
+            (product.clone(), 0..40usize).prop_map(|(p, q)| Operation::Reserve(p, q)),
+            (product.clone(), 0..40usize).prop_map(|(p, q)| Operation::CancelReservation(p, q)),
+            (product.clone(), 0..40usize).prop_map(|(p, q)| Operation::Dispatch(p, q)),
+            (product.clone(), 0..20usize).prop_map(|(p, q)| Operation::SetSafetyStock(p, q)),
+            (product, 0..40usize).prop_map(|(p, q)| Operation::Restock(p, q)),
+        ]
+    }
+
+    /// Index `PRODUCTS.len()` stands for a product that is not in the ledger.
+    fn product_name(index: usize) -> &'static str {
+        PRODUCTS.get(index).copied().unwrap_or("unknown")
+    }
+
+    fn ledger_with(initial: &[usize]) -> StockLedger {
+        let mut ledger = StockLedger::new();
+        for (product, quantity) in PRODUCTS.iter().zip(initial) {
+            let stock = ProductStock {
+                available_quantity: *quantity,
+                reserved_quantity: 0,
+                safety_stock: 0,
+            };
+            assert!(ledger.add_product(product.to_string(), stock).is_ok());
+        }
+        ledger
+    }
+
+    fn apply(ledger: &mut StockLedger, op: &Operation) -> Result<(), Errors> {
+        match op {
+            Operation::Sell(p, q) => ledger.sell(product_name(*p), *q),
+            Operation::Reserve(p, q) => ledger.reserve(product_name(*p), *q),
+            Operation::CancelReservation(p, q) => ledger.cancel_reservation(product_name(*p), *q),
+            Operation::Dispatch(p, q) => ledger.dispatch(product_name(*p), *q),
+            Operation::SetSafetyStock(p, q) => ledger.set_safety_stock(product_name(*p), *q),
+            Operation::Restock(p, q) => ledger.restock(product_name(*p), *q),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_ledger_keeps_reserved_below_available(
+            initial in proptest::collection::vec(0..60usize, PRODUCTS.len()),
+            ops in proptest::collection::vec(operation(), 0..200),
+        ) {
+            let mut ledger = ledger_with(&initial);
+            for op in &ops {
+                let _ = apply(&mut ledger, op);
+                for stock in ledger.products().values() {
+                    prop_assert!(stock.reserved_quantity <= stock.available_quantity);
+                }
+            }
+        }
+
+        #[test]
+        fn test_ledger_failed_operations_change_nothing(
+            initial in proptest::collection::vec(0..60usize, PRODUCTS.len()),
+            ops in proptest::collection::vec(operation(), 0..200),
+        ) {
+            let mut ledger = ledger_with(&initial);
+            for op in &ops {
+                let before = ledger.products().clone();
+                let movements_before = ledger.movements().len();
+                match apply(&mut ledger, op) {
+                    Ok(_) => prop_assert_eq!(ledger.movements().len(), movements_before + 1),
+                    Err(_) => {
+                        prop_assert_eq!(ledger.movements().len(), movements_before);
+                        for (product, stock) in ledger.products() {
+                            let old = &before[product];
+                            prop_assert_eq!(stock.available_quantity, old.available_quantity);
+                            prop_assert_eq!(stock.reserved_quantity, old.reserved_quantity);
+                            prop_assert_eq!(stock.safety_stock, old.safety_stock);
+                        }
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn test_ledger_movements_add_up_to_the_stock(
+            initial in proptest::collection::vec(0..60usize, PRODUCTS.len()),
+            ops in proptest::collection::vec(operation(), 0..200),
+        ) {
+            let mut ledger = ledger_with(&initial);
+            for op in &ops {
+                let _ = apply(&mut ledger, op);
+            }
+
+            for (product, stock) in ledger.products() {
+                let mut available: i64 = 0;
+                let mut reserved: i64 = 0;
+                for movement in ledger.movements().iter().filter(|m| &m.product == product) {
+                    let quantity = movement.quantity as i64;
+                    match movement.reason {
+                        MovementReason::InitialStock | MovementReason::Restock => {
+                            available += quantity
+                        }
+                        MovementReason::LocalSale => available -= quantity,
+                        MovementReason::Reservation => reserved += quantity,
+                        MovementReason::ReservationCancelled => reserved -= quantity,
+                        MovementReason::Dispatch => {
+                            available -= quantity;
+                            reserved -= quantity;
+                        }
+                        MovementReason::SafetyStockChange => {}
+                    }
+                }
+                prop_assert_eq!(available, stock.available_quantity as i64);
+                prop_assert_eq!(reserved, stock.reserved_quantity as i64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ledger_rejects_invalid_initial_stock() {
+        let mut ledger = StockLedger::new();
+        let stock = ProductStock {
+            available_quantity: 2,
+            reserved_quantity: 3,
+            safety_stock: 0,
+        };
+        assert_eq!(
+            ledger.add_product("product0".to_string(), stock),
+            Err(Errors::StockInvariantError)
+        );
+    }
+
+    #[test]
+    fn test_ledger_dispatch_without_reservation_fails() {
+        let mut ledger = ledger_with(&[10]);
+        assert_eq!(
+            ledger.dispatch("product0", 1),
+            Err(Errors::NotEnoughReservedError)
+        );
+        assert_eq!(
+            ledger.cancel_reservation("product0", 1),
+            Err(Errors::NotEnoughReservedError)
+        );
+    }
+
+    #[test]
+    fn test_ledger_keeps_only_the_latest_movements() {
+        let mut ledger = StockLedger::with_max_movements(3);
+        let stock = ProductStock {
+            available_quantity: 10,
+            reserved_quantity: 0,
+            safety_stock: 0,
+        };
+        assert!(ledger.add_product("product0".to_string(), stock).is_ok());
+        for quantity in 1..=5 {
+            assert_eq!(ledger.restock("product0", quantity), Ok(()));
+        }
+
+        assert_eq!(ledger.movements().len(), 3);
+        let quantities: Vec<usize> = ledger.movements().iter().map(|m| m.quantity).collect();
+        assert_eq!(quantities, vec![3, 4, 5]);
+        assert_eq!(
+            ledger.get("product0").map(|s| s.available_quantity),
+            Some(25)
+        );
+    }
+}
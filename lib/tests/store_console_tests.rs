@@ -0,0 +1,65 @@
+// Module dedicated to testing the commands of the terminal of the store
+
+#[cfg(test)]
+mod tests {
+    use lib::errors::Errors;
+    use lib::store_console::StoreCommand;
+
+    #[test]
+    fn test_store_console_parses_commands() {
+        assert_eq!(StoreCommand::parse("C"), Ok(StoreCommand::Connect));
+        assert_eq!(StoreCommand::parse("K"), Ok(StoreCommand::Disconnect));
+        assert_eq!(StoreCommand::parse("S"), Ok(StoreCommand::ShowStock));
+        assert_eq!(StoreCommand::parse("R"), Ok(StoreCommand::ShowReservations));
+        assert_eq!(StoreCommand::parse("L"), Ok(StoreCommand::ShowConnections));
+        assert_eq!(StoreCommand::parse("help"), Ok(StoreCommand::Help));
+        assert_eq!(StoreCommand::parse("H"), Ok(StoreCommand::Help));
+        assert_eq!(
+            StoreCommand::parse("F,ZAP-001,0"),
+            Ok(StoreCommand::SetSafetyStock {
+                product: "ZAP-001".to_string(),
+                quantity: 0
+            })
+        );
+        assert_eq!(
+            StoreCommand::parse("A, ZAP-001, 10"),
+            Ok(StoreCommand::Restock {
+                product: "ZAP-001".to_string(),
+                quantity: 10
+            })
+        );
+        assert_eq!(
+            StoreCommand::parse("V,ZAP-001,2"),
+            Ok(StoreCommand::Sale {
+                product: "ZAP-001".to_string(),
+                quantity: 2
+            })
+        );
+        assert_eq!(
+            StoreCommand::parse("X,7"),
+            Ok(StoreCommand::CancelReservation { reservation_id: 7 })
+        );
+    }
+
+    #[test]
+    fn test_store_console_explains_errors() {
+        let cases = [
+            ("", "no se escribio ningun comando"),
+            ("Z", "el comando [Z] no existe"),
+            ("S,ZAP-001", "el comando [S] no lleva argumentos"),
+            ("A,ZAP-001", "se espera [A,producto,cantidad]"),
+            ("V,,2", "se espera [V,producto,cantidad]"),
+            ("A,ZAP-001,diez", "la cantidad [diez] no es un numero"),
+            ("V,ZAP-001,0", "la cantidad tiene que ser mayor a cero"),
+            ("X", "se espera [X,reserva]"),
+            ("X,siete", "la reserva [siete] no es un numero"),
+        ];
+        for (input, reason) in cases {
+            assert_eq!(
+                StoreCommand::parse(input),
+                Err(Errors::InvalidCommand(reason.to_string())),
+                "[{input}]"
+            );
+        }
+    }
+}
@@ -3,27 +3,59 @@
 #[cfg(test)]
 mod tests {
     use actix::prelude::*;
+    use lib::catalog::{Catalog, CatalogEntry};
+    use lib::clock::{real_clock, SharedClock, VirtualClock};
     use lib::errors::Errors;
-    use lib::store::{LocalProductOrder, ProductStock, ReserveProduct, Store, _GetStock};
+    use lib::metrics::Metrics;
+    use lib::offline_log::OfflineLog;
+    use lib::outbox::{parse_sequenced, Outbox};
+    use lib::random::stream_rng;
+    use lib::sales_report::{Channel, SalesReport};
+    use lib::stock_ledger::StockLedger;
+    use lib::store::{
+        _GetOfflineLog, _GetOutbox, _GetStock, CancelReservation, DispatchProduct, Drain,
+        EcomConnectionClosed, GetConnections, GetPendingWork, GetReservations, GetSalesReport,
+        KillConnection, LocalProductOrder, MemberEvent, NewEcomHandler, NewMember, ProductStock,
+        ReserveProduct, Restock, SetSafetyStock, Store,
+    };
+    use lib::trace::{with_trace, TraceId};
+    use lib::transport::duplex;
     use std::collections::HashMap;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
     const VOLUME_SIZE: usize = 10000;
 
     #[actix_rt::test]
     async fn test_store_actor() {
         let mut store = Store {
-            stock: HashMap::new(),
+            id: "1".to_string(),
+            stock: StockLedger::new(),
             reserve_sender: tokio::sync::mpsc::channel(1).0,
             active_ecoms: HashMap::new(),
             connection: false,
             leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
         };
 
         let product_stock = ProductStock {
             available_quantity: 10,
             reserved_quantity: 0,
+            safety_stock: 0,
         };
 
-        store.stock.insert("product1".to_string(), product_stock);
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
 
         let order = LocalProductOrder {
             product: "product1".to_string(),
@@ -31,14 +63,7 @@ mod tests {
         };
 
         let addr = store.start();
-        let res = match addr.send(order).await {
-            Ok(Ok(res)) => res,
-            _ => {
-                assert_eq!(true, false);
-                return;
-            }
-        };
-        assert_eq!(res, ());
+        assert!(matches!(addr.send(order).await, Ok(Ok(()))));
 
         let product_stock = match addr.send(_GetStock {}).await {
             Ok(Ok(stock)) => stock,
@@ -62,19 +87,35 @@ mod tests {
     #[actix_rt::test]
     async fn test_store_actor_not_enough_stock() {
         let mut store = Store {
-            stock: HashMap::new(),
+            id: "1".to_string(),
+            stock: StockLedger::new(),
             reserve_sender: tokio::sync::mpsc::channel(1).0,
             active_ecoms: HashMap::new(),
             connection: false,
             leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
         };
 
         let product_stock = ProductStock {
             available_quantity: 10,
             reserved_quantity: 0,
+            safety_stock: 0,
         };
 
-        store.stock.insert("product1".to_string(), product_stock);
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
 
         let order = LocalProductOrder {
             product: "product1".to_string(),
@@ -114,19 +155,35 @@ mod tests {
     #[actix_rt::test]
     async fn test_store_actor_product_not_found() {
         let mut store = Store {
-            stock: HashMap::new(),
+            id: "1".to_string(),
+            stock: StockLedger::new(),
             reserve_sender: tokio::sync::mpsc::channel(1).0,
             active_ecoms: HashMap::new(),
             connection: false,
             leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
         };
 
         let product_stock = ProductStock {
             available_quantity: 10,
             reserved_quantity: 0,
+            safety_stock: 0,
         };
 
-        store.stock.insert("product1".to_string(), product_stock);
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
 
         let order = LocalProductOrder {
             product: "product2".to_string(),
@@ -166,19 +223,35 @@ mod tests {
     #[actix_rt::test]
     async fn test_store_actor_reserve() {
         let mut store = Store {
-            stock: HashMap::new(),
+            id: "1".to_string(),
+            stock: StockLedger::new(),
             reserve_sender: tokio::sync::mpsc::channel(1).0,
             active_ecoms: HashMap::new(),
             connection: false,
             leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
         };
 
         let product_stock = ProductStock {
             available_quantity: 10,
             reserved_quantity: 0,
+            safety_stock: 0,
         };
 
-        store.stock.insert("product1".to_string(), product_stock);
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
 
         let order = LocalProductOrder {
             product: "product1".to_string(),
@@ -213,6 +286,7 @@ mod tests {
                 product: "product1".to_string(),
                 quantity: 5,
                 time_limit: 1,
+                trace_id: None,
             })
             .await;
 
@@ -240,19 +314,35 @@ mod tests {
     #[actix_rt::test]
     async fn test_store_actor_reserve_not_enough_stock() {
         let mut store = Store {
-            stock: HashMap::new(),
+            id: "1".to_string(),
+            stock: StockLedger::new(),
             reserve_sender: tokio::sync::mpsc::channel(1).0,
             active_ecoms: HashMap::new(),
             connection: false,
             leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
         };
 
         let product_stock = ProductStock {
             available_quantity: 10,
             reserved_quantity: 0,
+            safety_stock: 0,
         };
 
-        store.stock.insert("product1".to_string(), product_stock);
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
 
         let order = LocalProductOrder {
             product: "product1".to_string(),
@@ -287,6 +377,7 @@ mod tests {
                 product: "product1".to_string(),
                 quantity: 10,
                 time_limit: 1,
+                trace_id: None,
             })
             .await
         {
@@ -321,20 +412,36 @@ mod tests {
     #[actix_rt::test]
     async fn test_store_actor_order_volume_random() {
         let mut store = Store {
-            stock: HashMap::new(),
+            id: "1".to_string(),
+            stock: StockLedger::new(),
             reserve_sender: tokio::sync::mpsc::channel(1).0,
             active_ecoms: HashMap::new(),
             connection: false,
             leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
         };
 
         for i in 0..VOLUME_SIZE {
             let product_stock = ProductStock {
                 available_quantity: 25,
                 reserved_quantity: 0,
+                safety_stock: 0,
             };
 
-            store.stock.insert(format!("product{}", i), product_stock);
+            assert!(store
+                .stock
+                .add_product(format!("product{}", i), product_stock)
+                .is_ok());
         }
 
         let mut orders = Vec::new();
@@ -358,7 +465,7 @@ mod tests {
                 return;
             }
         };
-        for i in 0..VOLUME_SIZE {
+        for (i, order) in orders.iter().enumerate() {
             let product_stock = match product_stock.get(&format!("product{}", i)) {
                 Some(stock) => stock,
                 None => {
@@ -366,7 +473,7 @@ mod tests {
                     return;
                 }
             };
-            assert_eq!(product_stock.available_quantity, 25 - orders[i].quantity);
+            assert_eq!(product_stock.available_quantity, 25 - order.quantity);
             assert_eq!(product_stock.reserved_quantity, 0);
         }
     }
@@ -374,20 +481,36 @@ mod tests {
     #[actix_rt::test]
     async fn test_store_actor_order_volume_random_not_enough_stock() {
         let mut store = Store {
-            stock: HashMap::new(),
+            id: "1".to_string(),
+            stock: StockLedger::new(),
             reserve_sender: tokio::sync::mpsc::channel(1).0,
             active_ecoms: HashMap::new(),
             connection: false,
             leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
         };
 
         for i in 0..VOLUME_SIZE {
             let product_stock = ProductStock {
                 available_quantity: 25,
                 reserved_quantity: 0,
+                safety_stock: 0,
             };
 
-            store.stock.insert(format!("product{}", i), product_stock);
+            assert!(store
+                .stock
+                .add_product(format!("product{}", i), product_stock)
+                .is_ok());
         }
 
         let mut orders = Vec::new();
@@ -430,4 +553,804 @@ mod tests {
             assert_eq!(product_stock.reserved_quantity, 0);
         }
     }
+
+    #[actix_rt::test]
+    async fn test_store_actor_reserve_respects_safety_stock() {
+        let mut store = Store {
+            id: "1".to_string(),
+            stock: StockLedger::new(),
+            reserve_sender: tokio::sync::mpsc::channel(1).0,
+            active_ecoms: HashMap::new(),
+            connection: false,
+            leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
+        };
+
+        let product_stock = ProductStock {
+            available_quantity: 10,
+            reserved_quantity: 0,
+            safety_stock: 4,
+        };
+
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
+
+        let addr = store.start();
+        let res = match addr
+            .send(ReserveProduct {
+                product: "product1".to_string(),
+                quantity: 7,
+                time_limit: 1,
+                trace_id: None,
+            })
+            .await
+        {
+            Ok(Err(res)) => res,
+            _ => {
+                assert_eq!(true, false);
+                return;
+            }
+        };
+        assert_eq!(res, Errors::NotEnoughStockError);
+
+        let res = addr
+            .send(ReserveProduct {
+                product: "product1".to_string(),
+                quantity: 6,
+                time_limit: 1,
+                trace_id: None,
+            })
+            .await;
+        assert!(matches!(res, Ok(Ok(()))));
+
+        // In-person customers can still buy the units kept as safety stock
+        let res = addr
+            .send(LocalProductOrder {
+                product: "product1".to_string(),
+                quantity: 4,
+            })
+            .await;
+        assert!(matches!(res, Ok(Ok(()))));
+
+        let product_stock = match addr.send(_GetStock {}).await {
+            Ok(Ok(stock)) => stock,
+            _ => {
+                assert_eq!(true, false);
+                return;
+            }
+        };
+        let product_stock = match product_stock.get("product1") {
+            Some(stock) => stock,
+            None => {
+                assert_eq!(true, false);
+                return;
+            }
+        };
+
+        assert_eq!(product_stock.available_quantity, 6);
+        assert_eq!(product_stock.reserved_quantity, 6);
+    }
+
+    #[actix_rt::test]
+    async fn test_store_actor_set_safety_stock() {
+        let mut store = Store {
+            id: "1".to_string(),
+            stock: StockLedger::new(),
+            reserve_sender: tokio::sync::mpsc::channel(1).0,
+            active_ecoms: HashMap::new(),
+            connection: false,
+            leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
+        };
+
+        let product_stock = ProductStock {
+            available_quantity: 10,
+            reserved_quantity: 0,
+            safety_stock: 0,
+        };
+
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
+
+        let addr = store.start();
+        let res = addr
+            .send(SetSafetyStock {
+                product: "product1".to_string(),
+                quantity: 8,
+            })
+            .await;
+        assert!(matches!(res, Ok(Ok(()))));
+
+        let res = match addr
+            .send(ReserveProduct {
+                product: "product1".to_string(),
+                quantity: 3,
+                time_limit: 1,
+                trace_id: None,
+            })
+            .await
+        {
+            Ok(Err(res)) => res,
+            _ => {
+                assert_eq!(true, false);
+                return;
+            }
+        };
+        assert_eq!(res, Errors::NotEnoughStockError);
+
+        let res = match addr
+            .send(SetSafetyStock {
+                product: "product2".to_string(),
+                quantity: 1,
+            })
+            .await
+        {
+            Ok(Err(res)) => res,
+            _ => {
+                assert_eq!(true, false);
+                return;
+            }
+        };
+        assert_eq!(res, Errors::ProductNotFoundError);
+    }
+
+    #[actix_rt::test]
+    async fn test_store_actor_sales_report() {
+        let mut catalog = Catalog::new();
+        let _ = catalog.add(CatalogEntry {
+            sku: "product1".to_string(),
+            name: "producto".to_string(),
+            price_cents: 250,
+            category: "varios".to_string(),
+        });
+        let mut store = Store {
+            id: "1".to_string(),
+            stock: StockLedger::new(),
+            reserve_sender: tokio::sync::mpsc::channel(1).0,
+            active_ecoms: HashMap::new(),
+            connection: false,
+            leader: 0,
+            catalog,
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
+        };
+
+        let product_stock = ProductStock {
+            available_quantity: 10,
+            reserved_quantity: 0,
+            safety_stock: 0,
+        };
+
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
+
+        let addr = store.start();
+        for _ in 0..2 {
+            let res = addr
+                .send(LocalProductOrder {
+                    product: "product1".to_string(),
+                    quantity: 3,
+                })
+                .await;
+            assert!(matches!(res, Ok(Ok(()))));
+        }
+
+        let report = match addr.send(GetSalesReport).await {
+            Ok(Ok(report)) => report,
+            _ => {
+                assert_eq!(true, false);
+                return;
+            }
+        };
+        let by_channel = report.totals_by_channel();
+        let physical = by_channel.get(&Channel::Physical);
+        assert_eq!(physical.map(|t| t.orders_approved), Some(2));
+        assert_eq!(physical.map(|t| t.units_sold), Some(6));
+        assert_eq!(physical.map(|t| t.revenue_cents), Some(1500));
+        assert!(!by_channel.contains_key(&Channel::Online));
+    }
+
+    #[actix_rt::test]
+    async fn test_store_forwards_member_events() {
+        let (members_sender, mut members_receiver) = tokio::sync::mpsc::channel(1);
+        let store = Store {
+            id: "1".to_string(),
+            stock: StockLedger::new(),
+            reserve_sender: tokio::sync::mpsc::channel(1).0,
+            active_ecoms: HashMap::new(),
+            connection: false,
+            leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: Some(members_sender),
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
+        };
+        let addr = store.start();
+
+        let result = addr
+            .send(NewMember {
+                ecom_id: "4".to_string(),
+                address: "127.0.0.4:6001".to_string(),
+            })
+            .await;
+        assert!(matches!(result, Ok(Ok(()))));
+        assert_eq!(
+            members_receiver.recv().await,
+            Some(MemberEvent::Joined {
+                ecom_id: "4".to_string(),
+                address: "127.0.0.4:6001".to_string(),
+            })
+        );
+
+        // A closed connection is retried, so the ecommerce is still a member of the cluster
+        let result = addr
+            .send(EcomConnectionClosed {
+                ecom_id: "4".to_string(),
+            })
+            .await;
+        assert!(matches!(result, Ok(Ok(()))));
+        assert!(members_receiver.try_recv().is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_store_keeps_sales_while_disconnected() {
+        let mut store = Store {
+            id: "1".to_string(),
+            stock: StockLedger::new(),
+            reserve_sender: tokio::sync::mpsc::channel(1).0,
+            active_ecoms: HashMap::new(),
+            connection: false,
+            leader: 2,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
+        };
+        let product_stock = ProductStock {
+            available_quantity: 10,
+            reserved_quantity: 0,
+            safety_stock: 0,
+        };
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
+        let addr = store.start();
+
+        // No ecommerce is connected, so the sales wait in the offline log
+        for quantity in [2, 3] {
+            let order = LocalProductOrder {
+                product: "product1".to_string(),
+                quantity,
+            };
+            assert!(matches!(addr.send(order).await, Ok(Ok(()))));
+        }
+
+        let mut log = match addr.send(_GetOfflineLog).await {
+            Ok(Ok(log)) => log,
+            _ => OfflineLog::new(),
+        };
+        assert_eq!(
+            log.replay(),
+            vec!["APPROVED,product1,5,physical,orders=2".to_string()]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_store_resends_until_leader_acknowledges() {
+        let outbox = Outbox::new();
+        let session = outbox.session();
+        let mut store = Store {
+            id: "1".to_string(),
+            stock: StockLedger::new(),
+            reserve_sender: tokio::sync::mpsc::channel(1).0,
+            active_ecoms: HashMap::new(),
+            connection: false,
+            leader: 2,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox,
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
+        };
+        let product_stock = ProductStock {
+            available_quantity: 10,
+            reserved_quantity: 0,
+            safety_stock: 0,
+        };
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
+        let addr = store.start();
+
+        // The leader is the other end of an in-memory connection
+        let (stream, leader) = duplex();
+        let _ = addr
+            .send(NewEcomHandler {
+                stream,
+                ecom_id: "2".to_string(),
+                closed: None,
+            })
+            .await;
+
+        let order = LocalProductOrder {
+            product: "product1".to_string(),
+            quantity: 2,
+        };
+        assert!(matches!(addr.send(order).await, Ok(Ok(()))));
+
+        let (read, mut write) = tokio::io::split(leader);
+        let mut lines = BufReader::new(read).lines();
+        assert_eq!(
+            lines.next_line().await.ok().flatten(),
+            Some(format!("SEQ,{session},1,APPROVED,product1,2,physical"))
+        );
+        let pending = match addr.send(_GetOutbox).await {
+            Ok(Ok(outbox)) => outbox.pending(),
+            _ => vec![],
+        };
+        assert_eq!(pending.len(), 1);
+
+        assert!(write.write_all(b"ACK,1\n").await.is_ok());
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let acknowledged = match addr.send(_GetOutbox).await {
+            Ok(Ok(outbox)) => outbox.is_empty(),
+            _ => false,
+        };
+        assert!(acknowledged);
+    }
+
+    /// A store with 10 units of "product1" and a reservation of 4 of them, whose id is 0.
+    async fn store_with_open_reservation() -> Addr<Store> {
+        store_with_open_reservation_on(real_clock()).await
+    }
+
+    async fn store_with_open_reservation_on(clock: SharedClock) -> Addr<Store> {
+        let mut store = Store {
+            id: "1".to_string(),
+            stock: StockLedger::new(),
+            reserve_sender: tokio::sync::mpsc::channel(10).0,
+            active_ecoms: HashMap::new(),
+            connection: false,
+            leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock,
+            traces: stream_rng(0, "trace"),
+        };
+        let product_stock = ProductStock {
+            available_quantity: 10,
+            reserved_quantity: 0,
+            safety_stock: 0,
+        };
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
+        let addr = store.start();
+        let reserve = ReserveProduct {
+            product: "product1".to_string(),
+            quantity: 4,
+            time_limit: 10,
+            trace_id: None,
+        };
+        assert!(matches!(addr.send(reserve).await, Ok(Ok(()))));
+        addr
+    }
+
+    #[actix_rt::test]
+    async fn test_store_reservation_keeps_trace() {
+        let (reserve_sender, mut reserve_receiver) = tokio::sync::mpsc::channel(10);
+        let mut store = Store {
+            id: "1".to_string(),
+            stock: StockLedger::new(),
+            reserve_sender,
+            active_ecoms: HashMap::new(),
+            connection: false,
+            leader: 0,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox: Outbox::new(),
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
+        };
+        let product_stock = ProductStock {
+            available_quantity: 10,
+            reserved_quantity: 0,
+            safety_stock: 0,
+        };
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
+        let addr = store.start();
+        let trace_id = TraceId::from_rng(&mut stream_rng(0, "order"));
+        let reserve = ReserveProduct {
+            product: "product1".to_string(),
+            quantity: 4,
+            time_limit: 10,
+            trace_id: Some(trace_id),
+        };
+        assert!(matches!(addr.send(reserve).await, Ok(Ok(()))));
+
+        let reservations = match addr.send(GetReservations).await {
+            Ok(Ok(reservations)) => reservations,
+            _ => panic!("No se pudieron obtener las reservas"),
+        };
+        assert_eq!(reservations.get(&0).map(|r| r.trace_id), Some(trace_id));
+        assert_eq!(
+            reserve_receiver.recv().await,
+            Some(format!("product1,4,10,0,trace={trace_id}"))
+        );
+    }
+
+    async fn reserved_quantity(addr: &Addr<Store>) -> Option<usize> {
+        match addr.send(_GetStock {}).await {
+            Ok(Ok(stock)) => stock.get("product1").map(|s| s.reserved_quantity),
+            _ => None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_store_answers_reservations_and_connections() {
+        let addr = store_with_open_reservation().await;
+
+        let reservations = match addr.send(GetReservations).await {
+            Ok(Ok(reservations)) => reservations,
+            _ => panic!("No se pudieron obtener las reservas"),
+        };
+        assert_eq!(reservations.len(), 1);
+        let reservation = reservations.get(&0);
+        assert_eq!(reservation.map(|r| r.product.as_str()), Some("product1"));
+        assert_eq!(reservation.map(|r| r.quantity), Some(4));
+        assert_eq!(reservation.map(|r| r.time_limit), Some(10));
+        // The order came without a trace, so the store started one from its seed
+        assert_eq!(
+            reservation.map(|r| r.trace_id),
+            Some(TraceId::from_rng(&mut stream_rng(0, "trace")))
+        );
+
+        let connections = match addr.send(GetConnections).await {
+            Ok(Ok(connections)) => connections,
+            _ => panic!("No se pudieron obtener las conexiones"),
+        };
+        assert_eq!(connections.leader, "0");
+        assert!(!connections.leader_connected);
+        assert!(connections.ecoms.is_empty());
+        assert!(!connections.draining);
+    }
+
+    #[actix_rt::test]
+    async fn test_store_restock_and_cancel_reservation() {
+        let addr = store_with_open_reservation().await;
+
+        let restock = Restock {
+            product: "product1".to_string(),
+            quantity: 5,
+        };
+        assert!(matches!(addr.send(restock).await, Ok(Ok(()))));
+        let restock = Restock {
+            product: "product2".to_string(),
+            quantity: 5,
+        };
+        assert!(matches!(
+            addr.send(restock).await,
+            Ok(Err(Errors::ProductNotFoundError))
+        ));
+
+        let cancel = CancelReservation { reservation_id: 0 };
+        assert!(matches!(addr.send(cancel).await, Ok(Ok(()))));
+        assert_eq!(reserved_quantity(&addr).await, Some(0));
+        let cancel = CancelReservation { reservation_id: 0 };
+        assert!(matches!(
+            addr.send(cancel).await,
+            Ok(Err(Errors::ReservationNotFoundError))
+        ));
+
+        // Without a leader both changes wait in the offline log
+        let mut log = match addr.send(_GetOfflineLog).await {
+            Ok(Ok(log)) => log,
+            _ => OfflineLog::new(),
+        };
+        assert_eq!(
+            log.replay(),
+            vec![
+                "STOCK,product1,15".to_string(),
+                "CANCELLED,product1,4,orders=1".to_string()
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_store_drain_cancels_open_reservations() {
+        let clock = VirtualClock::new();
+        let addr = store_with_open_reservation_on(clock.shared()).await;
+        let drain = Drain {
+            max_wait: std::time::Duration::from_secs(60),
+        };
+        assert!(matches!(addr.send(drain).await, Ok(Ok(()))));
+
+        // While draining, no new reservations are taken, and the order is told cancelled
+        let reserve = ReserveProduct {
+            product: "product1".to_string(),
+            quantity: 1,
+            time_limit: 10,
+            trace_id: None,
+        };
+        assert!(matches!(
+            addr.send(reserve).await,
+            Ok(Err(Errors::StoreDrainingError))
+        ));
+        assert_eq!(reserved_quantity(&addr).await, Some(4));
+
+        // The reservation still open when the wait is over is cancelled
+        clock.advance(std::time::Duration::from_secs(59)).await;
+        assert_eq!(reserved_quantity(&addr).await, Some(4));
+        clock.advance(std::time::Duration::from_secs(1)).await;
+        assert_eq!(reserved_quantity(&addr).await, Some(0));
+        let mut log = match addr.send(_GetOfflineLog).await {
+            Ok(Ok(log)) => log,
+            _ => OfflineLog::new(),
+        };
+        assert_eq!(
+            log.replay(),
+            vec!["CANCELLED,product1,5,orders=2".to_string()]
+        );
+
+        // The dispatch of the cancelled reservation arrives later and changes nothing
+        let dispatch = DispatchProduct {
+            product: "product1".to_string(),
+            quantity: 4,
+            cancel_order: false,
+            reservation_id: 0,
+            trace_id: TraceId::from_rng(&mut stream_rng(0, "order")),
+        };
+        assert!(matches!(addr.send(dispatch).await, Ok(Ok(()))));
+        let available = match addr.send(_GetStock {}).await {
+            Ok(Ok(stock)) => stock.get("product1").map(|s| s.available_quantity),
+            _ => None,
+        };
+        assert_eq!(available, Some(10));
+    }
+
+    #[actix_rt::test]
+    async fn test_store_cancels_orders_sent_during_drain() {
+        let addr = store_with_open_reservation_on(real_clock()).await;
+        let (stream, leader) = duplex();
+        let _ = addr
+            .send(NewEcomHandler {
+                stream,
+                ecom_id: "0".to_string(),
+                closed: None,
+            })
+            .await;
+        let drain = Drain {
+            max_wait: std::time::Duration::from_secs(60),
+        };
+        assert!(matches!(addr.send(drain).await, Ok(Ok(()))));
+
+        // The ecommerces are told right away, so they stop routing orders to the store
+        let mut lines = BufReader::new(leader).lines();
+        assert_eq!(
+            lines.next_line().await.ok().flatten(),
+            Some("DRAIN".to_string())
+        );
+
+        // An order sent before the ecommerce knew is not lost, the leader is told it was cancelled
+        let trace_id = TraceId::from_rng(&mut stream_rng(0, "order"));
+        let reserve = ReserveProduct {
+            product: "product1".to_string(),
+            quantity: 1,
+            time_limit: 10,
+            trace_id: Some(trace_id),
+        };
+        assert!(matches!(
+            addr.send(reserve).await,
+            Ok(Err(Errors::StoreDrainingError))
+        ));
+        let line = lines.next_line().await.ok().flatten().unwrap_or_default();
+        let notification = parse_sequenced(&line).map(|(_, _, notification)| notification);
+        assert_eq!(
+            notification,
+            Some(with_trace("CANCELLED,product1,1", trace_id).as_str())
+        );
+
+        // Killing the connection ends the drain, so the store takes reservations again
+        assert!(matches!(addr.send(KillConnection {}).await, Ok(Ok(()))));
+        let pending = match addr.send(GetPendingWork).await {
+            Ok(Ok(pending)) => pending,
+            _ => panic!("No se pudo obtener el trabajo pendiente"),
+        };
+        assert!(!pending.draining);
+        let reserve = ReserveProduct {
+            product: "product1".to_string(),
+            quantity: 1,
+            time_limit: 10,
+            trace_id: None,
+        };
+        assert!(matches!(addr.send(reserve).await, Ok(Ok(()))));
+    }
+
+    #[actix_rt::test]
+    async fn test_store_drain_ends_with_last_dispatch() {
+        let addr = store_with_open_reservation().await;
+        let drain = Drain {
+            max_wait: std::time::Duration::from_secs(60),
+        };
+        assert!(matches!(addr.send(drain).await, Ok(Ok(()))));
+
+        let dispatch = DispatchProduct {
+            product: "product1".to_string(),
+            quantity: 4,
+            cancel_order: false,
+            reservation_id: 0,
+            trace_id: TraceId::from_rng(&mut stream_rng(0, "order")),
+        };
+        assert!(matches!(addr.send(dispatch).await, Ok(Ok(()))));
+        assert_eq!(reserved_quantity(&addr).await, Some(0));
+
+        // The drain is over, so the store takes reservations again once it is connected
+        let reserve = ReserveProduct {
+            product: "product1".to_string(),
+            quantity: 1,
+            time_limit: 10,
+            trace_id: None,
+        };
+        assert!(matches!(addr.send(reserve).await, Ok(Ok(()))));
+    }
+
+    #[actix_rt::test]
+    async fn test_store_drain_waits_for_leader_acknowledgement() {
+        let outbox = Outbox::new();
+        let session = outbox.session();
+        let mut store = Store {
+            id: "1".to_string(),
+            stock: StockLedger::new(),
+            reserve_sender: tokio::sync::mpsc::channel(1).0,
+            active_ecoms: HashMap::new(),
+            connection: false,
+            leader: 2,
+            catalog: Catalog::new(),
+            sales: SalesReport::new(),
+            members_sender: None,
+            offline_log: OfflineLog::new(),
+            outbox,
+            reservations: HashMap::new(),
+            next_reservation_id: 0,
+            draining: None,
+            metrics: Metrics::new(),
+            clock: real_clock(),
+            traces: stream_rng(0, "trace"),
+        };
+        let product_stock = ProductStock {
+            available_quantity: 10,
+            reserved_quantity: 0,
+            safety_stock: 0,
+        };
+        assert!(store
+            .stock
+            .add_product("product1".to_string(), product_stock)
+            .is_ok());
+        let addr = store.start();
+
+        // The leader is the other end of an in-memory connection
+        let (stream, leader) = duplex();
+        let _ = addr
+            .send(NewEcomHandler {
+                stream,
+                ecom_id: "2".to_string(),
+                closed: None,
+            })
+            .await;
+
+        let order = LocalProductOrder {
+            product: "product1".to_string(),
+            quantity: 2,
+        };
+        assert!(matches!(addr.send(order).await, Ok(Ok(()))));
+        let drain = Drain {
+            max_wait: std::time::Duration::from_secs(60),
+        };
+        assert!(matches!(addr.send(drain).await, Ok(Ok(()))));
+
+        // There are no reservations, but the store waits for the leader to acknowledge its sale
+        let pending = match addr.send(GetPendingWork).await {
+            Ok(Ok(pending)) => pending,
+            _ => panic!("No se pudo obtener el trabajo pendiente"),
+        };
+        assert!(pending.draining);
+        assert_eq!(pending.unacknowledged.len(), 1);
+
+        let (read, mut write) = tokio::io::split(leader);
+        let mut lines = BufReader::new(read).lines();
+        assert_eq!(
+            lines.next_line().await.ok().flatten(),
+            Some(format!("SEQ,{session},1,APPROVED,product1,2,physical"))
+        );
+        assert_eq!(
+            lines.next_line().await.ok().flatten(),
+            Some("DRAIN".to_string())
+        );
+        assert!(write.write_all(b"ACK,1\n").await.is_ok());
+        assert_eq!(
+            lines.next_line().await.ok().flatten(),
+            Some("BYE".to_string())
+        );
+
+        let pending = match addr.send(GetPendingWork).await {
+            Ok(Ok(pending)) => pending,
+            _ => panic!("No se pudo obtener el trabajo pendiente"),
+        };
+        assert!(!pending.draining);
+        assert!(pending.unacknowledged.is_empty());
+        assert!(pending.offline.is_empty());
+    }
 }
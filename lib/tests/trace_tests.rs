@@ -0,0 +1,46 @@
+// Module dedicated to testing how the trace of an order travels in the protocol messages
+
+#[cfg(test)]
+mod tests {
+    use lib::offline_log::OfflineLog;
+    use lib::random::stream_rng;
+    use lib::trace::{split_trace, with_trace, TraceId};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_trace_round_trip() {
+        let trace_id = TraceId::from_rng(&mut stream_rng(0, "trace"));
+        let text = trace_id.to_string();
+        assert_eq!(text.len(), 16);
+        assert_eq!(TraceId::from_str(&text), Ok(trace_id));
+
+        let line = with_trace("ORDER,ZAP-001,2", trace_id);
+        assert_eq!(line, format!("ORDER,ZAP-001,2,trace={text}"));
+        assert_eq!(split_trace(&line), ("ORDER,ZAP-001,2", Some(trace_id)));
+    }
+
+    #[test]
+    fn test_lines_without_trace() {
+        assert_eq!(split_trace("ZAP-001,2,5"), ("ZAP-001,2,5", None));
+        assert_eq!(split_trace("BYE"), ("BYE", None));
+        assert_eq!(
+            split_trace("ZAP-001,2,trace=zz"),
+            ("ZAP-001,2,trace=zz", None)
+        );
+    }
+
+    #[test]
+    fn test_offline_log_ignores_the_trace() {
+        let mut log = OfflineLog::new();
+        let trace_id = TraceId::from_rng(&mut stream_rng(0, "trace"));
+        assert!(log.record(&with_trace("APPROVED,ZAP-001,2,online", trace_id)));
+        assert!(log.record(&with_trace("CANCELLED,ZAP-001,1", trace_id)));
+        assert_eq!(
+            log.lines(),
+            vec![
+                "APPROVED,ZAP-001,2,online,orders=1".to_string(),
+                "CANCELLED,ZAP-001,1,orders=1".to_string()
+            ]
+        );
+    }
+}
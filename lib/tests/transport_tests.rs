@@ -0,0 +1,67 @@
+// Module dedicated to testing the transports the nodes talk over
+
+#[cfg(test)]
+mod tests {
+    use lib::ecom::read_line;
+    use lib::transport::{connect, duplex, Listener};
+    use tokio::io::AsyncWriteExt;
+
+    #[actix_rt::test]
+    async fn test_duplex_connection() {
+        let (mut store, mut ecom) = duplex();
+        assert!(store.write_all(b"1\nSTOCK,ZAP-001,5\n").await.is_ok());
+        assert_eq!(read_line(&mut ecom).await, Ok("1".to_string()));
+        assert_eq!(
+            read_line(&mut ecom).await,
+            Ok("STOCK,ZAP-001,5".to_string())
+        );
+
+        drop(store);
+        assert!(read_line(&mut ecom).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_tcp_listener() {
+        let address = "127.0.0.40:7420";
+        let listener = match Listener::bind(address).await {
+            Ok(listener) => listener,
+            Err(e) => panic!("No se pudo abrir el listener: {:?}", e),
+        };
+        let mut dialed = match connect(address).await {
+            Ok(stream) => stream,
+            Err(e) => panic!("No se pudo conectar: {:?}", e),
+        };
+        let mut accepted = match listener.accept().await {
+            Ok(stream) => stream,
+            Err(e) => panic!("No se acepto la conexion: {:?}", e),
+        };
+        assert!(dialed.write_all(b"DISCOVER\n").await.is_ok());
+        assert_eq!(read_line(&mut accepted).await, Ok("DISCOVER".to_string()));
+    }
+
+    #[actix_rt::test]
+    async fn test_unix_socket_listener() {
+        let path = std::env::temp_dir().join(format!("ecom_{}.sock", std::process::id()));
+        let address = format!("unix:{}", path.display());
+        // A socket left by a previous run is replaced
+        for _ in 0..2 {
+            let listener = match Listener::bind(&address).await {
+                Ok(listener) => listener,
+                Err(e) => panic!("No se pudo abrir el socket: {:?}", e),
+            };
+            let mut dialed = match connect(&address).await {
+                Ok(stream) => stream,
+                Err(e) => panic!("No se pudo conectar: {:?}", e),
+            };
+            let mut accepted = match listener.accept().await {
+                Ok(stream) => stream,
+                Err(e) => panic!("No se acepto la conexion: {:?}", e),
+            };
+            assert!(accepted.write_all(b"LEADER,2,2\n").await.is_ok());
+            assert_eq!(read_line(&mut dialed).await, Ok("LEADER,2,2".to_string()));
+        }
+        let _ = std::fs::remove_file(path);
+
+        assert!(connect("unix:no_existe.sock").await.is_err());
+    }
+}
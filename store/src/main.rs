@@ -1,276 +1,641 @@
 use actix::prelude::*;
 use actix::Actor;
-use lib::store::NewEcomHandler;
+use lib::admin_api::{serve_http, HttpRequest, HttpResponse, StoreRoute};
+use lib::clock::{real_clock, SharedClock};
+use lib::ecom::discover_members;
+use lib::logging::{connection_span, init_logging};
+use lib::metrics::{serve_metrics, Metrics};
+use lib::offline_log::OfflineLog;
+use lib::outbox::Outbox;
+use lib::random::{node_seed, stream_rng};
+use lib::reconnect::{connect_with_backoff, ReconnectPolicy};
+use lib::sales_report::SalesReport;
+use lib::shutdown::{shutdown_signal, write_pending_lines, ShutdownSummary};
+use lib::stock_ledger::StockLedger;
+use lib::store::reserves_manager;
+use lib::store::{
+    GetConnections, GetPendingWork, GetReservations, GetSalesReport, GetStock, MemberEvent,
+    NewEcomHandler, PendingWork,
+};
 use lib::{
+    catalog::Catalog,
+    config::StoreConfig,
     errors::Errors,
     store::{
-        Connect, DispatchProduct, KillConnection, LocalProductOrder, ProductStock, ShowState, Store,
+        CancelReservation, Connect, Drain, LocalProductOrder, ProductStock, Restock,
+        SetSafetyStock, ShowConnections, ShowReservations, ShowState, Store,
     },
+    store_console::{StoreCommand, USAGE},
 };
-use rand::{thread_rng, Rng};
 use std::collections::HashMap;
 use std::env::args;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::str::FromStr;
-use tokio::net::TcpStream;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio::{fs::File as TFile, io::AsyncWriteExt, task};
+use tokio::sync::oneshot;
+use tokio::{fs::File as TFile, io::AsyncWriteExt, task, task::JoinHandle};
 use tokio::{
     io::{AsyncBufReadExt, BufReader as TBufReader},
     join,
-    time::Duration,
+    time::{sleep, Duration},
 };
 use tokio_stream::{wrappers::LinesStream, StreamExt};
+use tracing::{error, info, warn, Instrument};
 
-const STOCK_FILE_INDEX: usize = 1;
-const ORDERS_FILE_INDEX: usize = 2;
-const ID_INDEX: usize = 3;
-const ECOM_AMOUNT_INDEX: usize = 4;
-const PORT_INDEX: usize = 5;
-
-const MIN_SECS_DISPATCH: u64 = 1;
-const MAX_SECS_DISPATCH: u64 = 10;
-const PHYSICAL_CLIENTS_DELAY: u64 = 2;
-const DISCONNECTION_CHANNEL_SIZE: usize = 5;
+const CONNECTION_CHANNEL_SIZE: usize = 5;
 const RESERVE_CHANNEL_SIZE: usize = 10;
+const MEMBERS_CHANNEL_SIZE: usize = 10;
 
+/// Sent through the connection channel when the store is told to connect again.
 const CONNECT_INPUT: &str = "C";
-const KILL_INPUT: &str = "K";
-const STOCK_INPUT: &str = "S";
 
-const IPS_START: usize = 6;
-const IPS_END_INDEX: usize = IPS_START + 6;
+const REPORTS_DIR: &str = "reports";
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 /// This main initializes the Store actor and to run every async function that make possible for the store
-/// side to run concurrently
+/// side to run concurrently. The configuration is given as a TOML file with `--config` and can be
+/// overridden setting by setting with `--<setting> <value>`.
+/// On SIGINT or SIGTERM the store shuts down in order, and the exit status tells whether it left work pending.
 fn main() -> Result<(), Errors> {
-    let args: Vec<String> = args().collect(); // Args order: stock_file orders_file ecommerce_addr id
+    let args: Vec<String> = args().collect();
+    let config = StoreConfig::from_args(&args)?;
+    init_logging(&config.log_level, config.log_format)?;
+    let seed = node_seed(config.seed);
+    info!(seed, "Semilla de la store");
 
     let (reserve_sender, mut reserve_receiver): (Sender<String>, Receiver<String>) =
         mpsc::channel(RESERVE_CHANNEL_SIZE);
+    let (members_sender, members_receiver): (Sender<MemberEvent>, Receiver<MemberEvent>) =
+        mpsc::channel(MEMBERS_CHANNEL_SIZE);
 
-    let store = initialize_store(args[STOCK_FILE_INDEX].clone(), reserve_sender)?;
+    let catalog = Catalog::load(&config.catalog_file)?;
+    let metrics = Metrics::new();
+    let clock = real_clock();
+    let store = Store {
+        id: config.id.clone(),
+        stock: load_stock(
+            config.stock_file.clone(),
+            &catalog,
+            config.tuning.max_stock_movements,
+        )?,
+        reserve_sender,
+        active_ecoms: HashMap::new(),
+        connection: false,
+        leader: 0,
+        catalog: catalog.clone(),
+        sales: SalesReport::new(),
+        members_sender: Some(members_sender.clone()),
+        offline_log: OfflineLog::new(),
+        outbox: Outbox::new(),
+        reservations: HashMap::new(),
+        next_reservation_id: 0,
+        draining: None,
+        metrics: metrics.clone(),
+        clock: clock.clone(),
+        traces: stream_rng(seed, "trace"),
+    };
 
-    let ecom_amount = <usize as FromStr>::from_str(&args[ECOM_AMOUNT_INDEX])
-        .map_err(|_| Errors::CouldNotParse)?;
-    let (senders_vect, receivers_vect) =
-        create_channels(ecom_amount).map_err(|_| Errors::ChannelError)?;
-    let ips_ecoms = args_vec(&args);
+    // Every connection with an ecommerce listens to this channel to know when the store reconnects
+    let (connection_sender, _) = broadcast::channel(CONNECTION_CHANNEL_SIZE);
+    let ips_ecoms: Vec<(String, String)> = config
+        .ecommerces
+        .iter()
+        .map(|ecom| (ecom.address.clone(), ecom.id.to_string()))
+        .collect();
 
     let system = System::new();
-    system.block_on(async {
+    let exit_code = system.block_on(async {
         let store_addr = store.start();
 
-        let physical_sales_fut =
-            physical_sales(args[ORDERS_FILE_INDEX].clone(), store_addr.clone());
-        let ecom_connection_fut = ecom_connection(
-            ips_ecoms,
-            args[ID_INDEX].clone(),
+        let physical_sales_fut = physical_sales(
+            config.orders_file.clone(),
+            &catalog,
+            config.tuning.physical_clients_delay_secs,
             store_addr.clone(),
-            receivers_vect,
+            clock.clone(),
         );
-        let reserves_manager_fut = reserves_manager(store_addr.clone(), &mut reserve_receiver);
-        let user_input_fut = user_input(store_addr.clone(), senders_vect);
-
-        let _ = join!(
-            physical_sales_fut,
-            reserves_manager_fut,
-            user_input_fut,
-            ecom_connection_fut
+        let reconnect = config.tuning.reconnect_policy().seeded(seed);
+        let links = OnlineSalesLinks {
+            my_id: config.id.clone(),
+            store: store_addr.clone(),
+            connection_sender: connection_sender.clone(),
+            members_sender: members_sender.clone(),
+        };
+        let ecom_connection_fut = ecom_connection(ips_ecoms, links, members_receiver, reconnect);
+        let discover_ecoms_fut =
+            discover_ecoms(config.seeds.clone(), members_sender, reconnect.forever());
+        let reserves_manager_fut = reserves_manager(
+            store_addr.clone(),
+            &mut reserve_receiver,
+            &config.tuning,
+            stream_rng(seed, "reserves_manager"),
+            clock.clone(),
+        );
+        let drain_max_wait = Duration::from_secs(config.tuning.drain_max_wait_secs);
+        // The channel stays open after the terminal is closed, so the connections keep being handled
+        let user_input_fut = user_input(
+            store_addr.clone(),
+            connection_sender.clone(),
+            drain_max_wait,
         );
+        let admin_api_fut = admin_api(
+            config.admin_address(),
+            store_addr.clone(),
+            connection_sender.clone(),
+            drain_max_wait,
+        );
+        let metrics_fut = serve_metrics(config.metrics_address(), metrics);
+
+        let tasks_fut = async {
+            join!(
+                physical_sales_fut,
+                reserves_manager_fut,
+                user_input_fut,
+                ecom_connection_fut,
+                discover_ecoms_fut,
+                admin_api_fut,
+                metrics_fut
+            )
+        };
+
+        tokio::select! {
+            _ = tasks_fut => {}
+            signal = shutdown_signal() => {
+                info!(signal, "Cerrando la store");
+            }
+        }
+        let summary = shut_down(&store_addr, &config.id, drain_max_wait).await;
+        dump_sales_report(&store_addr, &config.id).await;
+        summary.exit_code()
     });
 
-    Ok(())
+    std::process::exit(exit_code)
 }
 
-/// Creates a vec with tuples in the form of (ip, id), where each tuple corresponds to a different ecom in the network
-fn args_vec(args: &[String]) -> Vec<(String, String)> {
-    let mut ecoms: Vec<(String, String)> = vec![];
-    for i in (IPS_START..IPS_END_INDEX).step_by(2) {
-        let ip = format!("{}:{}", args[i].clone(), args[PORT_INDEX].clone());
-        let id = args[i + 1].clone();
-        ecoms.push((ip, id));
+/// Shuts the store down in order. Intake already stopped, so the store is drained: it waits for its open
+/// reservations and for the leader to acknowledge its notifications, and then says _BYE_ to the ecommerces.
+/// The notifications that could not be delivered are written to the reports directory.
+async fn shut_down(store: &Addr<Store>, id: &str, drain_max_wait: Duration) -> ShutdownSummary {
+    let _ = store
+        .send(Drain {
+            max_wait: drain_max_wait,
+        })
+        .await;
+    let pending = loop {
+        match store.send(GetPendingWork).await {
+            Ok(Ok(pending)) if !pending.draining => break pending,
+            Ok(Ok(_)) => sleep(SHUTDOWN_POLL_INTERVAL).await,
+            _ => {
+                error!("No se pudo obtener el trabajo pendiente de la store");
+                break PendingWork::default();
+            }
+        }
+    };
+
+    let lines: Vec<String> = pending
+        .unacknowledged
+        .iter()
+        .chain(pending.offline.iter())
+        .cloned()
+        .collect();
+    let name = format!("store_{id}_pending");
+    if !lines.is_empty() {
+        match write_pending_lines(REPORTS_DIR, &name, &lines) {
+            Ok(_) => info!(
+                path = %format!("{REPORTS_DIR}/{name}"),
+                "Notificaciones pendientes guardadas"
+            ),
+            Err(e) => error!(error = ?e, "No se pudieron guardar las notificaciones pendientes"),
+        }
+    }
+
+    let mut summary = ShutdownSummary::new();
+    summary.add("reservas abiertas", pending.open_reservations);
+    summary.add("notificaciones sin confirmar", pending.unacknowledged.len());
+    summary.add("eventos sin enviar al lider", pending.offline.len());
+    for line in summary.lines() {
+        warn!("Pendiente al cerrar: {line}");
     }
-    ecoms
+    summary
 }
 
-type StringChannels = (Vec<Sender<String>>, Vec<Receiver<String>>);
-/// Creates a vec with a channel for each known ecom in the network.
-fn create_channels(amount: usize) -> Result<StringChannels, Errors> {
-    let mut senders: Vec<Sender<String>> = vec![];
-    let mut receivers: Vec<Receiver<String>> = vec![];
-    for _ in 0..amount {
-        let (sender, receiver): (Sender<String>, Receiver<String>) =
-            mpsc::channel(DISCONNECTION_CHANNEL_SIZE);
-        senders.push(sender);
-        receivers.push(receiver);
+/// Asks the Store actor for its sales report and writes it as CSV and JSON in the reports directory.
+async fn dump_sales_report(store: &Addr<Store>, id: &str) {
+    let name = format!("store_{id}_sales");
+    match store.send(GetSalesReport).await {
+        Ok(Ok(report)) => match report.write_files(REPORTS_DIR, &name) {
+            Ok(_) => info!(
+                path = %format!("{REPORTS_DIR}/{name}"),
+                "Reporte de ventas guardado"
+            ),
+            Err(e) => error!(error = ?e, "No se pudo guardar el reporte de ventas"),
+        },
+        _ => error!("No se pudo obtener el reporte de ventas"),
     }
-    Ok((senders, receivers))
 }
 
-/// This async function listens to the user input from the terminal and handles the different supportede messages.
-async fn user_input(store: Addr<Store>, senders_vect: Vec<Sender<String>>) -> Result<(), Errors> {
+/// This async function listens to the user input from the terminal and handles the different supported commands.
+/// Disconnecting drains the store first, waiting up to `drain_max_wait` for its reservations.
+async fn user_input(
+    store: Addr<Store>,
+    connection_sender: broadcast::Sender<String>,
+    drain_max_wait: Duration,
+) -> Result<(), Errors> {
     let mut input = tokio::io::BufReader::new(tokio::io::stdin()).lines();
-    while let Ok(msg) = input.next_line().await {
-        if let Some(user_msg) = msg {
-            match user_msg.as_str() {
-                KILL_INPUT => {
-                    let _ = store.send(KillConnection {}).await;
-                }
-                CONNECT_INPUT => {
-                    let _ = store.send(Connect {}).await;
-                    for sender in &senders_vect {
-                        let _ = sender.send(user_msg.clone()).await;
-                    }
-                }
-                STOCK_INPUT => {
-                    let _ = store.send(ShowState {}).await;
+    while let Ok(Some(line)) = input.next_line().await {
+        let command = match StoreCommand::parse(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                let reason = match e {
+                    Errors::InvalidCommand(reason) => reason,
+                    other => format!("{:?}", other),
+                };
+                warn!(
+                    line,
+                    reason, "Comando invalido, escribir [help] para ver los comandos"
+                );
+                continue;
+            }
+        };
+        let result = match command {
+            StoreCommand::Connect => connect(&store, &connection_sender).await,
+            StoreCommand::Disconnect => {
+                store
+                    .send(Drain {
+                        max_wait: drain_max_wait,
+                    })
+                    .await
+            }
+            StoreCommand::ShowStock => store.send(ShowState {}).await,
+            StoreCommand::SetSafetyStock { product, quantity } => {
+                store.send(SetSafetyStock { product, quantity }).await
+            }
+            StoreCommand::Restock { product, quantity } => {
+                store.send(Restock { product, quantity }).await
+            }
+            StoreCommand::ShowReservations => store.send(ShowReservations).await,
+            StoreCommand::CancelReservation { reservation_id } => {
+                store.send(CancelReservation { reservation_id }).await
+            }
+            StoreCommand::Sale { product, quantity } => {
+                store.send(LocalProductOrder { product, quantity }).await
+            }
+            StoreCommand::ShowConnections => store.send(ShowConnections).await,
+            StoreCommand::Help => {
+                for usage in USAGE {
+                    println!("    {usage}");
                 }
-                _ => eprintln!(
-                    "[USER_INPUT] Se recibio un mensaje por terminal que no se sabe responder"
-                ),
+                Ok(Ok(()))
             }
+        };
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => warn!(line, error = ?e, "No se pudo ejecutar el comando"),
+            Err(_) => error!(line, "No se pudo enviar el comando a la store"),
         }
     }
     Ok(())
 }
 
-/// This async function receives online orders from the Store actor. For each order it will create a task that will sleep
-/// for a random number of seconds, simulating the time that is waited for the reserved product to be dispatched.
-async fn reserves_manager(
+/// Connects the store again and tells every connection with an ecommerce, so they dial it.
+async fn connect(
+    store: &Addr<Store>,
+    connection_sender: &broadcast::Sender<String>,
+) -> Result<Result<(), Errors>, MailboxError> {
+    let result = store.send(Connect {}).await;
+    let _ = connection_sender.send(CONNECT_INPUT.to_string());
+    result
+}
+
+/// Serves the admin API in `address`, if it is set. Each request is answered with the same messages as the terminal.
+async fn admin_api(
+    address: Option<String>,
     store: Addr<Store>,
-    reserve_receiver: &mut Receiver<String>,
+    connection_sender: broadcast::Sender<String>,
+    drain_max_wait: Duration,
 ) -> Result<(), Errors> {
-    let mut rng = thread_rng();
-    let mut task_id = 0;
-
-    while let Some(msg) = reserve_receiver.recv().await {
-        // Seconds that will simulate how long the product is reserved
-        let seconds: u64 = rng.gen_range(MIN_SECS_DISPATCH, MAX_SECS_DISPATCH);
-        let duration_to_dispatch = std::time::Duration::from_secs(seconds);
-
-        // Creation of DispatchProduct message
-        let split: Vec<&str> = msg.split(',').collect();
-        let product = split[0].to_string();
-        let quantity = <usize as FromStr>::from_str(split[1]).map_err(|_| Errors::CouldNotParse)?;
-        let time_limit = <u64 as FromStr>::from_str(split[2]).map_err(|_| Errors::CouldNotParse)?;
-        let cancel_order = time_limit < seconds;
-        let dispatch_msg = DispatchProduct {
-            product,
-            quantity,
-            cancel_order,
-        };
-
-        // Cloned store address to notify when to dispatch the product
-        let cloned_addr = store.clone();
-
-        // Tasks will run concurrently waiting for each product to be ready to be dispatched
-        tokio::spawn(async move {
-            tokio::time::sleep(duration_to_dispatch).await;
-            match cloned_addr.try_send(dispatch_msg) {
-                Ok(_) => println!("[TASK_{task_id}] Se envio correctamente el mensaje DispatchProduct con orden [{msg}]"),
-                Err(_) => println!("[TASK_{task_id}] No se pudo enviar el mensaje DispatchProduct con orden [{msg}]"),
-            };
-        });
-        task_id += 1;
+    let address = match address {
+        Some(address) => address,
+        None => return Ok(()),
+    };
+    let result = serve_http(address, move |request| {
+        answer_api_request(
+            request,
+            store.clone(),
+            connection_sender.clone(),
+            drain_max_wait,
+        )
+    })
+    .await;
+    if let Err(e) = &result {
+        error!(error = ?e, "No se pudo levantar la API de administracion");
     }
-    Ok(())
+    result
+}
+
+async fn answer_api_request(
+    request: HttpRequest,
+    store: Addr<Store>,
+    connection_sender: broadcast::Sender<String>,
+    drain_max_wait: Duration,
+) -> HttpResponse {
+    let route = match StoreRoute::parse(&request) {
+        Ok(route) => route,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+    let result = match route {
+        StoreRoute::Stock => return HttpResponse::from_query(store.send(GetStock).await),
+        StoreRoute::Reservations => {
+            return HttpResponse::from_query(store.send(GetReservations).await)
+        }
+        StoreRoute::Connections => {
+            return HttpResponse::from_query(store.send(GetConnections).await)
+        }
+        StoreRoute::Sale { product, quantity } => {
+            store.send(LocalProductOrder { product, quantity }).await
+        }
+        StoreRoute::Restock { product, quantity } => {
+            store.send(Restock { product, quantity }).await
+        }
+        StoreRoute::CancelReservation { reservation_id } => {
+            store.send(CancelReservation { reservation_id }).await
+        }
+        StoreRoute::Connect => connect(&store, &connection_sender).await,
+        StoreRoute::Disconnect => {
+            store
+                .send(Drain {
+                    max_wait: drain_max_wait,
+                })
+                .await
+        }
+    };
+    HttpResponse::from_command(result)
 }
 
 /// For each ecom in the network, a task that will handle the conection is created.
+/// Then it follows the changes of the cluster: a task is created for each ecommerce that joins,
+/// and the task of an ecommerce that leaves is dropped.
+/// The configured ecommerces are retried forever, the ones that joined later only until the retries of `policy` run out.
 async fn ecom_connection(
     ips_ecoms: Vec<(String, String)>,
-    my_id: String,
-    store: Addr<Store>,
-    mut receivers: Vec<Receiver<String>>,
+    links: OnlineSalesLinks,
+    mut members_receiver: Receiver<MemberEvent>,
+    policy: ReconnectPolicy,
 ) -> Result<(), Errors> {
+    let mut connections: HashMap<String, JoinHandle<()>> = HashMap::new();
     for (ip, id) in ips_ecoms {
-        let my_id_clone = my_id.clone();
-        let store_clone = store.clone();
-        if let Some(mut receiver) = receivers.pop() {
-            task::spawn(async move {
-                let _ = online_sales(ip, my_id_clone, store_clone, &mut receiver, id).await;
-            });
-        } else {
-            eprintln!("[ECOM_CONNECTION] No hay receivers para mandarle online_sales()");
+        let handle = links.spawn_online_sales(ip, id.clone(), policy.forever());
+        connections.insert(id, handle);
+    }
+    while let Some(event) = members_receiver.recv().await {
+        match event {
+            MemberEvent::Joined { ecom_id, address } => {
+                if connections.contains_key(&ecom_id) {
+                    continue;
+                }
+                info!(ecom_id, address, "Se unio un ecommerce");
+                let handle = links.spawn_online_sales(address, ecom_id.clone(), policy);
+                connections.insert(ecom_id, handle);
+            }
+            MemberEvent::Left { ecom_id } => {
+                if let Some(handle) = connections.remove(&ecom_id) {
+                    info!(ecom_id, "Se deja de conectar con el ecommerce");
+                    handle.abort();
+                }
+            }
         }
     }
     Ok(())
 }
 
-/// This async function handles the connection of the store with the ecommerce.
+/// What every connection task needs to hand its ecommerce to the Store actor.
+struct OnlineSalesLinks {
+    my_id: String,
+    store: Addr<Store>,
+    connection_sender: broadcast::Sender<String>,
+    members_sender: Sender<MemberEvent>,
+}
+
+impl OnlineSalesLinks {
+    /// Spawns the task that keeps the connection with an ecommerce. If the ecommerce can not be reached again
+    /// it is reported as gone, so `ecom_connection()` forgets it.
+    fn spawn_online_sales(
+        &self,
+        ip: String,
+        ecom_id: String,
+        policy: ReconnectPolicy,
+    ) -> JoinHandle<()> {
+        let my_id = self.my_id.clone();
+        let store = self.store.clone();
+        let members_sender = self.members_sender.clone();
+        let mut receiver = self.connection_sender.subscribe();
+        let span = connection_span("ecom", &ecom_id);
+        task::spawn(
+            async move {
+                let result =
+                    online_sales(ip, my_id, store, &mut receiver, ecom_id.clone(), policy).await;
+                if result.is_err() {
+                    let _ = members_sender.send(MemberEvent::Left { ecom_id }).await;
+                }
+            }
+            .instrument(span),
+        )
+    }
+}
+
+/// Asks the seeds, one at a time, for the members of the cluster until one of them answers.
+/// If none answers, it tries again following `policy`.
+/// Every member is handed to `ecom_connection()` so the store connects to it.
+async fn discover_ecoms(
+    seeds: Vec<String>,
+    members_sender: Sender<MemberEvent>,
+    policy: ReconnectPolicy,
+) -> Result<(), Errors> {
+    if seeds.is_empty() {
+        return Ok(());
+    }
+    let mut backoff = policy.backoff("seeds");
+    loop {
+        for seed in &seeds {
+            if discover_from_seed(seed, &members_sender).await? {
+                return Ok(());
+            }
+        }
+        match backoff.next_delay() {
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => return Err(Errors::ConnectionError),
+        }
+    }
+}
+
+/// Asks a seed for the members of the cluster. Returns whether the seed answered.
+async fn discover_from_seed(
+    seed: &str,
+    members_sender: &Sender<MemberEvent>,
+) -> Result<bool, Errors> {
+    match discover_members(seed).await {
+        Ok(members) => {
+            for (id, member) in members.members {
+                if member.stores_address.is_empty() {
+                    continue;
+                }
+                let event = MemberEvent::Joined {
+                    ecom_id: id.to_string(),
+                    address: member.stores_address,
+                };
+                members_sender
+                    .send(event)
+                    .await
+                    .map_err(|_| Errors::ChannelError)?;
+            }
+            Ok(true)
+        }
+        Err(_) => {
+            warn!(
+                seed,
+                "No se pudo descubrir el cluster a traves de la semilla"
+            );
+            Ok(false)
+        }
+    }
+}
+
+/// This async function handles the connection of the store with the ecommerce. The ecommerce is dialed with the
+/// backoff of `policy`, and dialed again when it closes the connection. When the store kills the connection
+/// it waits until the store connects again. Returns an error once the ecommerce could not be reached.
 async fn online_sales(
     ip_addr: String,
     id: String,
     store: Addr<Store>,
-    receiver: &mut Receiver<String>,
+    receiver: &mut broadcast::Receiver<String>,
     ecom_id: String,
+    policy: ReconnectPolicy,
 ) -> Result<(), Errors> {
+    let mut backoff = policy.backoff(&ecom_id);
     loop {
-        if let Ok(mut stream) = TcpStream::connect(ip_addr.clone()).await {
-            let _ = stream.write(id.as_bytes()).await;
-            let _ = store.try_send(NewEcomHandler {
-                stream,
-                ecom_id: ecom_id.clone(),
-            });
-
-            if let Some(connection) = receiver.recv().await {
-                if connection == CONNECT_INPUT {
-                    println!("[ONLINE_SALES] Rconectando con ecommerce");
-                } else {
-                    println!("[ONLINE_SALES] Se termina la conexion con el ecommerce");
+        let mut stream = match connect_with_backoff(&ip_addr, &mut backoff).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!(address = %ip_addr, "Se deja de intentar conectar con el ecommerce");
+                return Err(e);
+            }
+        };
+        let _ = stream.write_all(format!("{id}\n").as_bytes()).await;
+        let (closed_sender, closed_receiver) = oneshot::channel();
+        let _ = store.try_send(NewEcomHandler {
+            stream,
+            ecom_id: ecom_id.clone(),
+            closed: Some(closed_sender),
+        });
+
+        tokio::select! {
+            closed = closed_receiver => {
+                if closed.is_ok() {
+                    warn!("Se perdio la conexion con el ecommerce, reconectando");
+                    if let Some(delay) = backoff.next_delay() {
+                        tokio::time::sleep(delay).await;
+                    }
+                    continue;
+                }
+                // The store killed the connection, it is made again when the store reconnects
+                wait_for_connect(receiver).await?;
+                info!("Reconectando con el ecommerce");
+            }
+            connection = receiver.recv() => {
+                match connection {
+                    Ok(connection) if connection == CONNECT_INPUT => {
+                        info!("Reconectando con el ecommerce");
+                    }
+                    Ok(_) => info!("Se termina la conexion con el ecommerce"),
+                    // The store is shutting down: the connection is left to the drain, and it is not made again
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                    Err(_) => error!("No se pudo leer el canal de reconexion con el ecommerce"),
                 }
-            } else {
-                eprintln!("[ONLINE_SALES] Error a la hora de leer por el channel para recuperar la conexion con el ecom.");
             }
         }
     }
 }
 
+/// Waits until the user tells the store to connect again.
+async fn wait_for_connect(receiver: &mut broadcast::Receiver<String>) -> Result<(), Errors> {
+    loop {
+        match receiver.recv().await {
+            Ok(connection) if connection == CONNECT_INPUT => return Ok(()),
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return Err(Errors::ChannelError),
+        }
+    }
+}
+
 /// Responsible of execution the async function thath simulates the arrival of physical clients.
-async fn physical_sales(client_orders: String, store: Addr<Store>) -> Result<(), Errors> {
-    let result = receive_clients(client_orders, &store).await;
+async fn physical_sales(
+    client_orders: String,
+    catalog: &Catalog,
+    delay_secs: u64,
+    store: Addr<Store>,
+    clock: SharedClock,
+) -> Result<(), Errors> {
+    let result = receive_clients(client_orders, catalog, delay_secs, &store, &clock).await;
     match result {
-        Ok(_) => println!("[PHYS_SALES] ¡All orders were processed!"),
-        Err(e) => println!("[PHYS_SALES] ¡Error: {:?}!", e),
+        Ok(_) => info!("Se atendieron todos los clientes del local"),
+        Err(e) => error!(error = ?e, "No se pudieron atender los clientes del local"),
     }
     Ok(())
 }
 
-/// Initializes the Struct Actor, thath will become the Store actor. Reads the stock file
-/// so as to create the Store with its initial stock.
-fn initialize_store(stock_file: String, reserve_sender: Sender<String>) -> Result<Store, Errors> {
+/// Reads the stock file so as to create the initial stock of the Store actor.
+/// Every product in the file must be a SKU of the catalog.
+fn load_stock(
+    stock_file: String,
+    catalog: &Catalog,
+    max_movements: usize,
+) -> Result<StockLedger, Errors> {
     let initial_stock = File::open(stock_file).map_err(|_| Errors::FileDoesNotExist)?;
     let reader = BufReader::new(initial_stock);
-    let mut stock_hash: HashMap<String, ProductStock> = HashMap::new();
+    let mut stock = StockLedger::with_max_movements(max_movements);
 
     for line in reader.lines() {
         let text = line.map_err(|_| Errors::ErrorReadingFile)?;
 
         let item: Vec<&str> = text.split(',').collect();
+        if catalog.check(item[0]).is_err() {
+            error!(
+                sku = item[0],
+                "El SKU del archivo de stock no esta en el catalogo"
+            );
+            return Err(Errors::UnknownProductError);
+        }
 
         let quantity = <usize as FromStr>::from_str(item[1]).map_err(|_| Errors::CouldNotParse)?;
+        // The safety stock column is optional, products without it keep no units for in-person customers
+        let safety_stock = match item.get(2) {
+            Some(safety) => {
+                <usize as FromStr>::from_str(safety).map_err(|_| Errors::CouldNotParse)?
+            }
+            None => 0,
+        };
 
         let product_stock = ProductStock {
             available_quantity: quantity,
             reserved_quantity: 0,
+            safety_stock,
         };
-        stock_hash.insert(item[0].to_string(), product_stock);
+        stock.add_product(item[0].to_string(), product_stock)?;
     }
-    let store = Store {
-        stock: stock_hash,
-        reserve_sender,
-        active_ecoms: HashMap::new(),
-        connection: false,
-        leader: 0,
-    };
-    Ok(store)
+    Ok(stock)
 }
 
 /// This async function simulates the arrival of physical clients. It reads the client_orders file
-/// and sleeps for a random number of seconds.
-async fn receive_clients(clients: String, store: &Addr<Store>) -> Result<(), Errors> {
+/// and sleeps `delay_secs` seconds of `clock` between clients. Orders of products that are not in the catalog are rejected.
+async fn receive_clients(
+    clients: String,
+    catalog: &Catalog,
+    delay_secs: u64,
+    store: &Addr<Store>,
+    clock: &SharedClock,
+) -> Result<(), Errors> {
     let file = TFile::open(clients)
         .await
         .map_err(|_| Errors::FileDoesNotExist)?;
@@ -280,6 +645,10 @@ async fn receive_clients(clients: String, store: &Addr<Store>) -> Result<(), Err
         let text = line.map_err(|_| Errors::ErrorReadingFile)?;
 
         let splitted_order: Vec<&str> = text.split(',').collect();
+        if catalog.check(splitted_order[0]).is_err() {
+            warn!(order = %text, "Pedido del local rechazado, el SKU no esta en el catalogo");
+            continue;
+        }
         let order_quantity =
             <usize as FromStr>::from_str(splitted_order[1]).map_err(|_| Errors::CouldNotParse)?;
 
@@ -288,7 +657,7 @@ async fn receive_clients(clients: String, store: &Addr<Store>) -> Result<(), Err
             product: splitted_order[0].to_owned(),
             quantity: order_quantity,
         };
-        println!("[PHYS_SALES] Order: {} = {}", order.product, order.quantity);
+        info!(order = %text, "Pedido de un cliente del local");
 
         // Sending the order of the client to the store
         let result = store
@@ -296,10 +665,10 @@ async fn receive_clients(clients: String, store: &Addr<Store>) -> Result<(), Err
             .await
             .map_err(|_| Errors::CouldNotReserve)?;
         if result.is_err() {
-            println!("[PHYS_SALES] No hay stock para el pedido [{text}]");
+            info!(order = %text, "No hay stock para el pedido del local");
         }
 
-        tokio::time::sleep(Duration::from_secs(PHYSICAL_CLIENTS_DELAY)).await;
+        clock.sleep(Duration::from_secs(delay_secs)).await;
     }
     Ok(())
 }